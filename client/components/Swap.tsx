@@ -6,7 +6,6 @@ import { IoIosSend } from "react-icons/io";
 import { FaRegPaste } from "react-icons/fa6";
 import { LuScanFace } from "react-icons/lu";
 import { Html5Qrcode } from "html5-qrcode";
-import { StrKey } from "@stellar/stellar-sdk";
 import { Barretenberg } from "@aztec/bb.js";
 import { useWallet } from "../utils/WalletProvider";
 import {
@@ -14,8 +13,13 @@ import {
   buildDepositTx,
   submitSorobanTx,
 } from "../lib/stellar";
-import { generateCommitment, amountToFieldHex } from "../lib/commitment";
-import { generateWithdrawProof, type WithdrawProofInputs } from "../lib/prover";
+import {
+  addressToField,
+  amountToFieldHex,
+  generateCommitment,
+  isValidRecipient,
+} from "../lib/commitment";
+import { generateWithdrawProof, NO_RELAYER_FEE, type WithdrawProofInputs } from "../lib/prover";
 
 const RELAYER_URL =
   process.env.NEXT_PUBLIC_RELAYER_URL || "http://localhost:3001";
@@ -67,19 +71,6 @@ function toHex(buf: Uint8Array): string {
   );
 }
 
-function addressToField(address: string): string {
-  let decoded: Uint8Array;
-  if (StrKey.isValidMed25519PublicKey(address)) {
-    decoded = new Uint8Array(
-      StrKey.decodeMed25519PublicKey(address).slice(0, 32),
-    );
-  } else {
-    decoded = StrKey.decodeEd25519PublicKey(address);
-  }
-  decoded[0] = 0;
-  return toHex(decoded);
-}
-
 // ---------------------------------------------------------------------------
 // Component
 // ---------------------------------------------------------------------------
@@ -226,10 +217,7 @@ export default function Swap() {
       setTxStatus({ stage: "error", message: "Enter a destination address" });
       return;
     }
-    if (
-      !StrKey.isValidEd25519PublicKey(recipientAddr) &&
-      !StrKey.isValidMed25519PublicKey(recipientAddr)
-    ) {
+    if (!isValidRecipient(recipientAddr)) {
       setTxStatus({ stage: "error", message: "Invalid Stellar address" });
       return;
     }
@@ -315,13 +303,14 @@ export default function Swap() {
 
       // ---- 6. Generate ZK proof ----
       setTxStatus({ stage: "proving" });
-      const recipientField = addressToField(recipientAddr);
+      const recipientField = await addressToField(recipientAddr);
 
       const proofInputs: WithdrawProofInputs = {
         root,
         nullifierHash: commitmentData.nullifierHash,
         recipient: recipientField,
         amount: amountField,
+        relayerFee: NO_RELAYER_FEE,
         nullifier: commitmentData.nullifier,
         secret: commitmentData.secret,
         merkleProof,
@@ -4,8 +4,8 @@ import { useState } from "react";
 import { IoIosSend } from "react-icons/io";
 import { FaRegPaste } from "react-icons/fa6";
 import { useWallet } from "../utils/WalletProvider";
-import { generateWithdrawProof, WithdrawProofInputs } from "../lib/prover";
-import { amountToFieldHex } from "../lib/commitment";
+import { generateWithdrawProof, NO_RELAYER_FEE, WithdrawProofInputs } from "../lib/prover";
+import { addressToField, amountToFieldHex, isValidRecipient } from "../lib/commitment";
 import { Barretenberg } from "@aztec/bb.js";
 
 type WithdrawStatus =
@@ -62,34 +62,6 @@ function toHex(buf: Uint8Array): string {
   );
 }
 
-import { StrKey } from "@stellar/stellar-sdk";
-
-// Extract the 32-byte Ed25519 public key from either a G... or M... address
-function decodeEd25519(address: string): Uint8Array {
-  if (StrKey.isValidMed25519PublicKey(address)) {
-    // M... muxed address: payload is ed25519 (32 bytes) + memo id (8 bytes)
-    return new Uint8Array(StrKey.decodeMed25519PublicKey(address).slice(0, 32));
-  }
-  return StrKey.decodeEd25519PublicKey(address);
-}
-
-// Convert a G... or M... Stellar address to a BN254 field element
-async function addressToField(address: string): Promise<string> {
-  const decoded = decodeEd25519(address);
-  // Zero out the first byte to keep the value below the BN254 field modulus (~2^254)
-  decoded[0] = 0;
-  return toHex(decoded);
-}
-
-// Convert M... to G... for Soroban contract calls (Soroban only accepts G... or C...)
-function toBaseAddress(address: string): string {
-  if (StrKey.isValidMed25519PublicKey(address)) {
-    const ed25519 = StrKey.decodeMed25519PublicKey(address).slice(0, 32);
-    return StrKey.encodeEd25519PublicKey(Buffer.from(ed25519));
-  }
-  return address;
-}
-
 export default function Withdraw() {
   const [noteJson, setNoteJson] = useState("");
   const [recipient, setRecipient] = useState("");
@@ -113,6 +85,10 @@ export default function Withdraw() {
       setStatus({ stage: "error", message: "Enter a recipient address" });
       return;
     }
+    if (!isValidRecipient(recipient.trim())) {
+      setStatus({ stage: "error", message: "Invalid Stellar address" });
+      return;
+    }
 
     if (!noteJson.trim()) {
       setStatus({ stage: "error", message: "Enter your withdrawal note" });
@@ -177,6 +153,7 @@ export default function Withdraw() {
         nullifierHash,
         recipient: recipientField,
         amount: amountField,
+        relayerFee: NO_RELAYER_FEE,
         nullifier,
         secret,
         merkleProof,
@@ -1,4 +1,5 @@
 import { Barretenberg, randomBytes } from "@aztec/bb.js";
+import { Address, StrKey, nativeToScVal, xdr } from "@stellar/stellar-sdk";
 
 // ---------------------------------------------------------------------------
 // Types
@@ -55,12 +56,53 @@ export function fieldHexToAmount(fieldHex: string): number {
   return Number(n);
 }
 
+// ---------------------------------------------------------------------------
+// Recipient field for the circuit: sha256 of the address as an
+// ScVal::Address, top byte zeroed to stay below the BN254 modulus. Matches
+// `rotor_note::Recipient::field` in Rust and the relayer. Accounts (G...),
+// contracts (C...) and muxed accounts (M...) each get their own field, and
+// a muxed id is part of it.
+// ---------------------------------------------------------------------------
+export function isValidRecipient(address: string): boolean {
+  return (
+    StrKey.isValidEd25519PublicKey(address) ||
+    StrKey.isValidMed25519PublicKey(address) ||
+    StrKey.isValidContract(address)
+  );
+}
+
+export async function addressToField(address: string): Promise<string> {
+  const xdr = new Address(address).toScVal().toXDR();
+  const field = new Uint8Array(await crypto.subtle.digest("SHA-256", xdr));
+  field[0] = 0;
+  return toHex(field);
+}
+
+// A withdrawal into a contract call binds the call, not an address, as the
+// recipient field: sha256 of ScVal::Vec([target, data]) with the top byte
+// zeroed, matching `rotor_note::call_field`. Spend such a proof with the
+// contract's `withdraw_and_call`, which pays `target` and passes it `data`
+// in its `on_rotor_withdraw` callback.
+export async function callToField(
+  target: string,
+  data: Uint8Array
+): Promise<string> {
+  const call = xdr.ScVal.scvVec([
+    new Address(target).toScVal(),
+    nativeToScVal(data),
+  ]);
+  const field = new Uint8Array(
+    await crypto.subtle.digest("SHA-256", call.toXDR())
+  );
+  field[0] = 0;
+  return toHex(field);
+}
+
 // ---------------------------------------------------------------------------
 // Generate a commitment matching the Noir circuit:
 //
 //   commitment       = Poseidon2::hash([nullifier, secret, amount], 3)  — amount bound
 //   nullifier_hash   = Poseidon2::hash([nullifier], 1)
-//   _binding         = Poseidon2::hash([secret, recipient], 2)
 //
 // The amount is bound in the commitment so the note can only be spent for that amount.
 // ---------------------------------------------------------------------------
@@ -87,29 +129,6 @@ export async function generateCommitment(amountStroops: number): Promise<Commitm
   };
 }
 
-// ---------------------------------------------------------------------------
-// Compute the recipient binding hash (for verification purposes)
-//
-//   binding = Poseidon2::hash([secret, recipient], 2)
-//
-// The recipient uses this during withdrawal to prove they are the
-// intended recipient without revealing the secret publicly.
-// ---------------------------------------------------------------------------
-export async function computeRecipientBinding(
-  secretHex: string,
-  recipientHex: string
-): Promise<string> {
-  const bb = await Barretenberg.new();
-
-  const secretBuf = hexToBytes(secretHex);
-  const recipientBuf = hexToBytes(recipientHex);
-
-  const result = await bb.poseidon2Hash({ inputs: [secretBuf, recipientBuf] });
-  await bb.destroy();
-
-  return toHex(result.hash);
-}
-
 /** Convert a hex string (with or without 0x prefix) to a 32-byte Uint8Array */
 function hexToBytes(hex: string): Uint8Array {
   const clean = hex.startsWith("0x") ? hex.slice(2) : hex;
@@ -82,6 +82,7 @@ async function main() {
 amount = "${amountFieldHex}"
 root = "${root}"
 nullifier_hash = "${toHex(nullifierHash)}"
+relayer_fee = "${"0x" + "0".repeat(64)}"
 recipient = "${recipient}"
 nullifier = "${toHex(nullifier)}"
 secret = "${toHex(secret)}"
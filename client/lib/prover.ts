@@ -15,6 +15,7 @@ export interface WithdrawProofInputs {
   nullifierHash: string;
   recipient: string;
   amount: string;           // Amount as field element (hex), same as in commitment
+  relayerFee: string;       // Most the relayer may take, as field element (hex)
 
   nullifier: string;
   secret: string;
@@ -22,12 +23,27 @@ export interface WithdrawProofInputs {
   isEven: boolean[];
 }
 
-/** Result of proof generation. publicInputs = [root, nullifierHash, recipient, amount]. */
+/**
+ * Result of proof generation. publicInputs = [root, nullifierHash, recipient,
+ * amount, relayerFee], without relayerFee for an older circuit.json.
+ */
 export interface WithdrawProofResult {
   proof: Uint8Array;
   publicInputs: string[];
 }
 
+/** relayerFee for a relayer that charges nothing, like the one in relayer/. */
+export const NO_RELAYER_FEE = "0x" + "0".repeat(64);
+
+/**
+ * Whether circuit.json commits to a relayer fee (the contract's WithdrawV2
+ * layout). An artifact compiled before has 4 public inputs (WithdrawV1): its
+ * proofs take no relayerFee and pay the relayer nothing.
+ */
+export const COMMITS_TO_RELAYER_FEE = circuit.abi.parameters.some(
+  (param) => param.name === "relayer_fee"
+);
+
 // ---------------------------------------------------------------------------
 // Proof generation
 // ---------------------------------------------------------------------------
@@ -56,6 +72,7 @@ export async function generateWithdrawProof(
     nullifier_hash: inputs.nullifierHash,
     recipient: inputs.recipient,
     amount: inputs.amount,
+    ...(COMMITS_TO_RELAYER_FEE ? { relayer_fee: inputs.relayerFee } : {}),
     nullifier: inputs.nullifier,
     secret: inputs.secret,
     merkleProof: inputs.merkleProof,
@@ -135,6 +135,7 @@ async function main() {
     nullifier_hash: toHex(nullifierHash),
     recipient,
     amount: amountFieldHex,
+    relayer_fee: "0x" + "0".repeat(64),
     nullifier: toHex(nullifier),
     secret: toHex(secret),
     merkleProof,
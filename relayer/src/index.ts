@@ -3,6 +3,7 @@ import { serve } from "@hono/node-server";
 import { cors } from "hono/cors";
 import { UltraHonkBackend } from "@aztec/bb.js";
 import * as StellarSdk from "@stellar/stellar-sdk";
+import { createHash } from "node:crypto";
 import circuit from "./circuit.json";
 
 // ---------------------------------------------------------------------------
@@ -17,6 +18,16 @@ const NETWORK_PASSPHRASE =
 const CONTRACT_ID = process.env.CONTRACT_ID || "";
 const RELAYER_SECRET = process.env.RELAYER_SECRET || "";
 
+// Public inputs of the compiled circuit, and so of every proof it verifies:
+// 5 once circuit.json commits to a relayer fee (the contract's WithdrawV2
+// layout), 4 for an artifact compiled before (WithdrawV1), whose proofs pay
+// the relayer nothing.
+const PUBLIC_INPUTS = circuit.abi.parameters.filter(
+  (param) => param.visibility === "public"
+).length;
+// rotor-core's `PublicInputLayout` for those proofs, passed to `withdraw`.
+const WITHDRAW_LAYOUT = PUBLIC_INPUTS === 5 ? 3 /* WithdrawV2 */ : 1 /* WithdrawV1 */;
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
@@ -25,25 +36,22 @@ function hexToBuffer(hex: string): Buffer {
   return Buffer.from(hex.replace(/^0x/, ""), "hex");
 }
 
-function decodeEd25519(address: string): Buffer {
-  if (StellarSdk.StrKey.isValidMed25519PublicKey(address)) {
-    return Buffer.from(StellarSdk.StrKey.decodeMed25519PublicKey(address).slice(0, 32));
-  }
-  return Buffer.from(StellarSdk.StrKey.decodeEd25519PublicKey(address));
+function isValidRecipient(address: string): boolean {
+  return (
+    StellarSdk.StrKey.isValidEd25519PublicKey(address) ||
+    StellarSdk.StrKey.isValidMed25519PublicKey(address) ||
+    StellarSdk.StrKey.isValidContract(address)
+  );
 }
 
+// The proof's recipient field: sha256 of the address as an ScVal::Address,
+// top byte zeroed to stay below the BN254 modulus. Matches
+// `rotor_note::Recipient::field`; muxed accounts keep their id.
 function stellarAddressToField(address: string): string {
-  const raw = decodeEd25519(address);
-  raw[0] = 0;
-  return "0x" + raw.toString("hex");
-}
-
-function toBaseAddress(address: string): string {
-  if (StellarSdk.StrKey.isValidMed25519PublicKey(address)) {
-    const ed25519 = StellarSdk.StrKey.decodeMed25519PublicKey(address).slice(0, 32);
-    return StellarSdk.StrKey.encodeEd25519PublicKey(Buffer.from(ed25519));
-  }
-  return address;
+  const xdr = new StellarSdk.Address(address).toScVal().toXDR();
+  const field = createHash("sha256").update(xdr).digest();
+  field[0] = 0;
+  return "0x" + field.toString("hex");
 }
 
 /** Parse amount (stroops) from proof public input (field element hex) */
@@ -174,8 +182,8 @@ app.post("/verify", async (c) => {
 //
 // Body: {
 //   proof:        number[],     // UltraHonk proof bytes
-//   publicInputs: string[],     // [root, nullifierHash, recipientField, amount] — amount from proof
-//   recipient:    string,        // Stellar G... address
+//   publicInputs: string[],     // [root, nullifierHash, recipientField, amount, relayerFee?] — amount from proof
+//   recipient:    string,        // Stellar G..., M... or C... address
 // }
 // Amount is taken from publicInputs[3] (bound in the commitment), not from the request body.
 // ---------------------------------------------------------------------------
@@ -194,33 +202,24 @@ app.post("/withdraw", async (c) => {
       );
     }
 
-    if (!Array.isArray(publicInputs) || publicInputs.length !== 4) {
+    if (!Array.isArray(publicInputs) || publicInputs.length !== PUBLIC_INPUTS) {
       return c.json(
         {
           success: false,
-          error:
-            "publicInputs must contain exactly 4 elements [root, nullifierHash, recipientField, amount]",
+          error: `publicInputs must contain exactly ${PUBLIC_INPUTS} elements, as the circuit outputs`,
         },
         400
       );
     }
 
-    if (
-      !StellarSdk.StrKey.isValidEd25519PublicKey(recipient) &&
-      !StellarSdk.StrKey.isValidMed25519PublicKey(recipient)
-    ) {
+    if (!isValidRecipient(recipient)) {
       return c.json(
         { success: false, error: "Invalid Stellar recipient address" },
         400
       );
     }
 
-    const [root, nullifierHash, recipientField, amountField] = publicInputs as [
-      string,
-      string,
-      string,
-      string,
-    ];
+    const [, , recipientField, amountField] = publicInputs as string[];
 
     let amountStroops: number;
     try {
@@ -287,18 +286,21 @@ app.post("/withdraw", async (c) => {
 
     // ----- Step 3: Call withdraw() on the Soroban contract -----
     //
-    // The contract validates root + nullifier, then transfers XLM from
-    // the contract's balance to the recipient via the SAC — all atomic.
+    // The contract validates root + nullifier, checks the recipient against
+    // the proof's recipient field, then transfers XLM from the contract's
+    // balance to the recipient via the SAC — all atomic.
 
     console.log("[Relayer] Building Soroban withdraw transaction…");
     console.log(`[Relayer] Amount: ${amountStroops} stroops (${(amountStroops / 10_000_000).toFixed(7)} XLM)`);
     const relayerAccount = await rpc.getAccount(relayerPubkey);
     const contract = new StellarSdk.Contract(CONTRACT_ID);
 
-    const recipientBase = toBaseAddress(recipient);
-
-    // Pass proof_amount as BytesN<32> — the contract derives the i128 amount
-    // from the lower 16 bytes of this BN254 field element.
+    // Pass the public inputs as verified, each a BytesN<32>, in the
+    // contract's WithdrawV2 layout (WithdrawV1 for an older circuit.json) —
+    // the contract derives the i128 amount from the lower 16 bytes of the
+    // amount field, and caps relayer_fee at the proof's relayerFee.
+    // This relayer charges no relayer_fee. The recipient is passed as given,
+    // so an M... address keeps its muxed id on the transfer.
     const sorobanTx = new StellarSdk.TransactionBuilder(relayerAccount, {
       fee: "1000000",
       networkPassphrase: NETWORK_PASSPHRASE,
@@ -306,9 +308,15 @@ app.post("/withdraw", async (c) => {
       .addOperation(
         contract.call(
           "withdraw",
-          StellarSdk.xdr.ScVal.scvBytes(hexToBuffer(nullifierHash)),
-          new StellarSdk.Address(recipientBase).toScVal(),
-          StellarSdk.xdr.ScVal.scvBytes(hexToBuffer(amountField))
+          new StellarSdk.Address(relayerPubkey).toScVal(),
+          StellarSdk.nativeToScVal(WITHDRAW_LAYOUT, { type: "u32" }),
+          StellarSdk.xdr.ScVal.scvVec(
+            (publicInputs as string[]).map((field) =>
+              StellarSdk.xdr.ScVal.scvBytes(hexToBuffer(field))
+            )
+          ),
+          new StellarSdk.Address(recipient).toScVal(),
+          StellarSdk.nativeToScVal(0, { type: "i128" })
         )
       )
       .setTimeout(180)
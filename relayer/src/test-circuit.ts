@@ -0,0 +1,105 @@
+/**
+ * Checks the compiled withdrawal circuit shipped to the relayer and the client
+ * against the circuit source and the contract.
+ *
+ * A circuit.json left behind by a change to circuit/src/main.nr proves and
+ * verifies a different statement than the source, and a public-input count
+ * the contract has no layout for makes every withdrawal fail.
+ *
+ * Usage:  bun run test:circuit
+ * After changing the circuit:  cd circuit && nargo compile, then copy
+ * target/circuit.json to relayer/src/ and client/lib/.
+ */
+
+import { readFileSync } from "node:fs";
+import { join } from "node:path";
+
+const ROOT = join(import.meta.dir, "..", "..");
+
+function read(path: string): string {
+  return readFileSync(join(ROOT, path), "utf8");
+}
+
+interface Artifact {
+  abi: { parameters: { name: string; visibility: string }[] };
+  bytecode: string;
+  file_map: Record<string, { path: string; source: string }>;
+}
+
+let passed = 0;
+let failed = 0;
+
+function check(name: string, ok: boolean, detail?: string) {
+  if (ok) {
+    passed++;
+    console.log(`    PASS: ${name}`);
+  } else {
+    failed++;
+    console.error(`    FAIL: ${name}${detail ? " — " + detail : ""}`);
+  }
+}
+
+/** Public parameters of `fn main`, in order. */
+function sourcePublicInputs(source: string): string[] {
+  const signature = source.slice(source.indexOf("fn main("), source.indexOf(") {"));
+  return [...signature.matchAll(/^\s*(\w+)\s*:\s*pub\b/gm)].map((m) => m[1]);
+}
+
+/** Input counts of the layouts `withdraw` accepts, from rotor-core. */
+function withdrawLayoutCounts(contract: string): Map<string, number> {
+  const counts = new Map<string, number>();
+  for (const [, layout, count] of contract.matchAll(
+    /PublicInputLayout::(Withdraw\w+) => (\d+)/g
+  )) {
+    counts.set(layout, Number(count));
+  }
+  return counts;
+}
+
+const source = read("circuit/src/main.nr");
+const contract = read("stellar/contracts/rotor-core/src/contract.rs");
+const expected = sourcePublicInputs(source);
+const layouts = withdrawLayoutCounts(contract);
+
+console.log("=== Withdrawal circuit artifacts ===\n");
+console.log(`    circuit/src/main.nr public inputs: ${expected.join(", ")}`);
+console.log(
+  `    withdraw layouts: ${[...layouts].map(([l, n]) => `${l} (${n})`).join(", ")}\n`
+);
+
+for (const path of ["relayer/src/circuit.json", "client/lib/circuit.json"]) {
+  const artifact = JSON.parse(read(path)) as Artifact;
+  const publicInputs = artifact.abi.parameters
+    .filter((param) => param.visibility === "public")
+    .map((param) => param.name);
+
+  console.log(`[${path}]`);
+  check(
+    "public-input count matches a withdraw layout",
+    [...layouts.values()].includes(publicInputs.length),
+    `${publicInputs.length} public inputs`
+  );
+  check(
+    "public inputs match circuit/src/main.nr",
+    publicInputs.join() === expected.join(),
+    `artifact has ${publicInputs.join(", ")}; recompile with nargo`
+  );
+  check(
+    "compiled from the current circuit/src/main.nr",
+    Object.values(artifact.file_map).some(
+      (file) => file.path.endsWith("src/main.nr") && file.source === source
+    ),
+    "source differs; recompile with nargo"
+  );
+  console.log();
+}
+
+const relayer = JSON.parse(read("relayer/src/circuit.json")) as Artifact;
+const client = JSON.parse(read("client/lib/circuit.json")) as Artifact;
+check(
+  "relayer and client ship the same circuit",
+  relayer.bytecode === client.bytecode
+);
+
+console.log(`\n=== Results: ${passed} passed, ${failed} failed ===`);
+if (failed > 0) process.exit(1);
@@ -23,6 +23,8 @@ const PROVER_INPUTS = {
   recipient:
     "0x00c98557f5ea6f0bae0ed968dcd88709144938ba4dfaaaf66c63bef82d663ba3",
   amount: "0x0000000000000000000000000000000000000000000000000000000000989680",
+  relayer_fee:
+    "0x00000000000000000000000000000000000000000000000000000000000186a0",
   nullifier:
     "0x00f1ad8bace18966d778cb6f1486fe31ee0a192346e91aa07e44baa94e2c9b0f",
   secret:
@@ -247,7 +249,7 @@ async function main() {
       }),
     });
     const data = await res.json();
-    if (!data.success && data.error?.includes("4 elements"))
+    if (!data.success && data.error?.includes("5 elements"))
       pass("Wrong public inputs length rejected");
     else fail("Wrong public inputs accepted", JSON.stringify(data));
   }
@@ -1,5 +1,5 @@
 #![no_std]
 
-mod merkle_tree;
+pub mod merkle_tree;
 #[cfg(test)]
 mod test;
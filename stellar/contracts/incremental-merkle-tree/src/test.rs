@@ -1,9 +1,84 @@
 #![cfg(test)]
 #![cfg(test)]
 extern crate alloc;
+extern crate std;
 
-use crate::merkle_tree::{IncrementalMerkleTree, IncrementalMerkleTreeClient};
-use soroban_sdk::{testutils::Address as _, Address, BytesN, Env};
+use alloc::vec::Vec;
+
+use crate::merkle_tree::{DataKey, IncrementalMerkleTree, IncrementalMerkleTreeClient};
+use proptest::prelude::*;
+use rotor_admin::{roles::Role, AdminSet};
+use rotor_merkle::testutils::{cost, Cost};
+use soroban_poseidon::poseidon2_hash;
+use soroban_sdk::{
+    crypto::BnScalar,
+    testutils::{Address as _, EnvTestConfig},
+    vec, Address, Bytes, BytesN, Env, U256,
+};
+
+// Fixtures produced by the Noir toolchain. The tree has to reproduce the
+// circuit's public `root` or no proof generated against it can be spent.
+const PROVER_TOML: &str = include_str!("../../../../circuit/Prover.toml");
+const CIRCUIT_JSON: &str = include_str!("../../../../circuit/target/circuit.json");
+
+fn parse_field(env: &Env, s: &str) -> BytesN<32> {
+    let s = s.trim().trim_matches('"').trim_start_matches("0x");
+    assert_eq!(s.len(), 64, "field fixtures are 32-byte hex");
+    let mut arr = [0u8; 32];
+    for (i, byte) in arr.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    BytesN::from_array(env, &arr)
+}
+
+/// Raw right-hand side of `key = value` in Prover.toml.
+fn prover_value(key: &str) -> &'static str {
+    PROVER_TOML
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+        .unwrap_or_else(|| panic!("{key} missing from Prover.toml"))
+}
+
+fn prover_field(env: &Env, key: &str) -> BytesN<32> {
+    parse_field(env, prover_value(key))
+}
+
+fn prover_fields(env: &Env, key: &str) -> Vec<BytesN<32>> {
+    prover_value(key)
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|v| parse_field(env, v))
+        .collect()
+}
+
+/// Noir `Poseidon2::hash(inputs, inputs.len())`.
+fn noir_hash(env: &Env, inputs: &[&BytesN<32>]) -> BytesN<32> {
+    let mut fields = vec![env];
+    for input in inputs {
+        fields.push_back(U256::from_be_bytes(
+            env,
+            &Bytes::from_array(env, &input.to_array()),
+        ));
+    }
+    poseidon2_hash::<4, BnScalar>(env, &fields)
+        .to_be_bytes()
+        .try_into()
+        .unwrap()
+}
+
+fn fixture_commitment(env: &Env) -> BytesN<32> {
+    noir_hash(
+        env,
+        &[
+            &prover_field(env, "nullifier"),
+            &prover_field(env, "secret"),
+            &prover_field(env, "amount"),
+        ],
+    )
+}
 
 #[test]
 fn test_tree_initialization() {
@@ -54,3 +129,368 @@ fn test_tree_insertion() {
     let expected_zero = client.get_zero(&19);
     assert_ne!(root, expected_zero, "Root should change after insertion");
 }
+
+#[test]
+fn test_insert_requires_inserter_role() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+    let approvers = vec![&env, admin.clone()];
+    let leaf = BytesN::from_array(&env, &[1u8; 32]);
+
+    let indexer = Address::generate(&env);
+    assert!(client.try_insert(&indexer, &leaf).is_err());
+
+    // The admin hands inserting over to another key.
+    client.grant_role(&approvers, &Role::Inserter, &indexer);
+    client.revoke_role(&approvers, &Role::Inserter, &admin);
+    assert!(client.has_role(&Role::Inserter, &indexer));
+    assert!(client.try_insert(&admin, &leaf).is_err());
+    assert_eq!(client.insert(&indexer, &leaf), 0);
+
+    // A 2-of-2 admin set governs roles from now on.
+    let next = AdminSet {
+        signers: vec![&env, admin.clone(), Address::generate(&env)],
+        threshold: 2,
+    };
+    client.propose_admin(&approvers, &next);
+    client.accept_admin();
+    assert_eq!(client.get_admin(), next);
+    assert!(client
+        .try_grant_role(&approvers, &Role::Inserter, &admin)
+        .is_err());
+    client.grant_role(&next.signers, &Role::Inserter, &admin);
+    assert_eq!(client.insert(&admin, &leaf), 1);
+}
+
+#[test]
+fn test_zeros_match_hash_pair() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    for level in 0..rotor_merkle::ZERO_LEVELS - 1 {
+        let zero = client.get_zero(&level);
+        assert_eq!(
+            client.hash_pair(&zero, &zero),
+            client.get_zero(&(level + 1)),
+            "zeros({}) must be Poseidon2(zeros({level}), zeros({level}))",
+            level + 1
+        );
+    }
+}
+
+#[test]
+fn test_hash_pair_matches_noir_poseidon2() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    let left = prover_field(&env, "nullifier");
+    let right = prover_field(&env, "secret");
+    assert_eq!(
+        client.hash_pair(&left, &right),
+        noir_hash(&env, &[&left, &right])
+    );
+    assert_ne!(
+        client.hash_pair(&left, &right),
+        client.hash_pair(&right, &left)
+    );
+}
+
+#[test]
+fn test_nullifier_hash_matches_circuit() {
+    let env = Env::default();
+    let nullifier = prover_field(&env, "nullifier");
+
+    assert_eq!(
+        noir_hash(&env, &[&nullifier]),
+        prover_field(&env, "nullifier_hash")
+    );
+}
+
+#[test]
+fn test_merkle_proof_fixture_is_empty_tree() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    // The fixture note is the first leaf, so every sibling is an empty subtree.
+    let proof = prover_fields(&env, "merkleProof");
+    assert_eq!(proof.len(), 20);
+    for (level, sibling) in proof.iter().enumerate() {
+        assert_eq!(*sibling, client.get_zero(&(level as u32)));
+    }
+    assert!(prover_value("is_even")
+        .split(',')
+        .all(|b| b.contains("true")));
+}
+
+#[test]
+fn test_root_matches_circuit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    let index = client.insert(&admin, &fixture_commitment(&env));
+    assert_eq!(index, 0);
+
+    let root = prover_field(&env, "root");
+    assert_eq!(client.get_latest_root(), root);
+    assert!(client.is_known_root(&root));
+
+    // Walk the circuit's own path as well, mirroring merkle_tree.nr.
+    let mut node = fixture_commitment(&env);
+    for sibling in prover_fields(&env, "merkleProof") {
+        node = client.hash_pair(&node, &sibling);
+    }
+    assert_eq!(node, root);
+}
+
+#[test]
+fn test_circuit_public_inputs() {
+    let abi: serde_json::Value = serde_json::from_str(CIRCUIT_JSON).unwrap();
+    let params = abi["abi"]["parameters"].as_array().unwrap();
+
+    let public: Vec<&str> = params
+        .iter()
+        .filter(|p| p["visibility"] == "public")
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(public, ["root", "nullifier_hash", "recipient", "amount"]);
+
+    let proof = params.iter().find(|p| p["name"] == "merkleProof").unwrap();
+    assert_eq!(
+        proof["type"]["length"], 20,
+        "circuit depth must match the tree"
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Differential tests: frontier insert vs a naive full-tree recomputation
+// ---------------------------------------------------------------------------
+
+/// Root of a depth-`depth` tree holding `leaves`, rebuilt level by level with
+/// empty positions filled by `zeros(level)`.
+fn reference_root(
+    client: &IncrementalMerkleTreeClient,
+    depth: u32,
+    leaves: &[BytesN<32>],
+) -> BytesN<32> {
+    let mut layer: Vec<BytesN<32>> = leaves.to_vec();
+    for level in 0..depth {
+        if layer.len() % 2 == 1 {
+            layer.push(client.get_zero(&level));
+        }
+        layer = layer
+            .chunks(2)
+            .map(|pair| client.hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer.pop().unwrap()
+}
+
+/// Random 32-byte values kept below the BN254 modulus.
+fn field_leaves(max: usize) -> impl Strategy<Value = Vec<[u8; 32]>> {
+    proptest::collection::vec(any::<[u8; 32]>(), 1..=max).prop_map(|leaves| {
+        leaves
+            .into_iter()
+            .map(|mut leaf| {
+                leaf[0] &= 0x1f;
+                leaf
+            })
+            .collect()
+    })
+}
+
+/// Property cases run many times; skip writing a ledger snapshot for each one.
+fn prop_env() -> Env {
+    Env::new_with_config(EnvTestConfig {
+        capture_snapshot_at_drop: false,
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn prop_insert_matches_full_tree(depth in 1u32..=4, raw in field_leaves(16)) {
+        let env = prop_env();
+        env.mock_all_auths();
+        let admin = Address::generate(&env);
+        let contract_id = env.register(IncrementalMerkleTree, (&admin, depth));
+        let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+        let mut leaves = Vec::new();
+        for leaf in raw.iter().take(1 << depth) {
+            let leaf = BytesN::from_array(&env, leaf);
+            prop_assert_eq!(client.insert(&admin, &leaf), leaves.len() as u64);
+            leaves.push(leaf);
+            prop_assert_eq!(client.get_latest_root(), reference_root(&client, depth, &leaves));
+        }
+    }
+
+    #[test]
+    fn prop_root_history_window(inserts in 1usize..70) {
+        let env = prop_env();
+        env.mock_all_auths();
+        let admin = Address::generate(&env);
+        let contract_id = env.register(IncrementalMerkleTree, (&admin, 8u32));
+        let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+        let mut roots = alloc::vec![client.get_latest_root()];
+        for i in 0..inserts {
+            client.insert(&admin, &BytesN::from_array(&env, &[i as u8; 32]));
+            roots.push(client.get_latest_root());
+        }
+
+        // Only the 30 most recent roots survive in the ring buffer.
+        let kept = roots.len().saturating_sub(30);
+        for (i, root) in roots.iter().enumerate() {
+            prop_assert_eq!(client.is_known_root(root), i >= kept, "root {} of {}", i, roots.len());
+        }
+    }
+}
+
+#[test]
+fn test_insert_up_to_capacity() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 2u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    let mut leaves = Vec::new();
+    for i in 0..4u8 {
+        let leaf = BytesN::from_array(&env, &[i + 1; 32]);
+        assert_eq!(client.insert(&admin, &leaf), i as u64);
+        leaves.push(leaf);
+    }
+    assert_eq!(
+        client.get_latest_root(),
+        reference_root(&client, 2, &leaves)
+    );
+
+    let full_root = client.get_latest_root();
+    let extra = BytesN::from_array(&env, &[9u8; 32]);
+    assert!(
+        client.try_insert(&admin, &extra).is_err(),
+        "tree of depth 2 holds 4 leaves"
+    );
+    assert_eq!(client.get_next_index(), 4);
+    assert_eq!(client.get_latest_root(), full_root);
+}
+
+/// The last slot of a depth-32 tree: the index no longer fits the old `u32`
+/// arithmetic, and the 32 hashes of the insert must fit the default budget.
+#[test]
+fn test_depth_32_last_leaf() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let contract_id = env.register(IncrementalMerkleTree, (&admin, 32u32));
+    let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+    // Index 2^32 - 1 is a right child at every level, so its root folds the
+    // cached left siblings onto the leaf.
+    let last = (1u64 << 32) - 1;
+    let cached: Vec<BytesN<32>> = (0..32u8)
+        .map(|level| BytesN::from_array(&env, &[level + 1; 32]))
+        .collect();
+    env.as_contract(&contract_id, || {
+        let storage = env.storage();
+        storage.instance().set(&DataKey::NextLeafIndex, &last);
+        storage.persistent().set(
+            &DataKey::Frontier,
+            &soroban_sdk::Vec::from_slice(&env, &cached),
+        );
+    });
+
+    let leaf = BytesN::from_array(&env, &[0xee; 32]);
+    assert_eq!(client.insert(&admin, &leaf), last);
+    assert_eq!(client.get_leaf(&last), leaf);
+    assert_eq!(client.get_next_index(), 1u64 << 32);
+
+    let mut expected = leaf;
+    for node in &cached {
+        expected = client.hash_pair(node, &expected);
+    }
+    assert_eq!(client.get_latest_root(), expected);
+
+    let extra = BytesN::from_array(&env, &[0xef; 32]);
+    assert!(client.try_insert(&admin, &extra).is_err());
+}
+
+#[test]
+#[should_panic(expected = "depth must be 1..=32")]
+fn test_rejects_depth_over_32() {
+    let env = Env::default();
+    env.register(IncrementalMerkleTree, (&Address::generate(&env), 33u32));
+}
+
+// ---------------------------------------------------------------------------
+// Budget regression
+//
+// Resources metered for the most expensive call of each entry point, per
+// depth. Contracts are registered natively, so VM instantiation is not
+// included; the numbers track what this crate's code costs. Limits carry about
+// 10% headroom over the measured values; see `rotor_merkle::testutils`.
+// ---------------------------------------------------------------------------
+
+/// `insert` into an empty tree: every level is a left child, so every
+/// frontier level changes. Entry counts no longer grow with depth, as the
+/// frontier is a single entry.
+const INSERT_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(13_100_000, 820_000, 7, 5, 1_220)),
+    (20, cost(32_600_000, 2_030_000, 7, 5, 1_750)),
+    (32, cost(52_000_000, 3_230_000, 7, 5, 2_280)),
+];
+
+/// `is_known_root` for a root that is not in a full history: all 30 ring
+/// buffer slots are compared, from the one history entry.
+const IS_KNOWN_ROOT_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(83_000, 13_700, 2, 0, 0)),
+    (20, cost(83_000, 13_700, 2, 0, 0)),
+    (32, cost(83_000, 13_700, 2, 0, 0)),
+];
+
+#[test]
+fn test_insert_budget() {
+    for (depth, limit) in INSERT_BUDGET {
+        let env = prop_env();
+        env.mock_all_auths();
+        let admin = Address::generate(&env);
+        let contract_id = env.register(IncrementalMerkleTree, (&admin, depth));
+        let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+
+        client.insert(&admin, &BytesN::from_array(&env, &[1; 32]));
+        Cost::of_last_call(&env).check(&std::format!("insert depth {depth}"), limit);
+    }
+}
+
+#[test]
+fn test_is_known_root_budget() {
+    for (depth, limit) in IS_KNOWN_ROOT_BUDGET {
+        let env = prop_env();
+        env.mock_all_auths();
+        let admin = Address::generate(&env);
+        let contract_id = env.register(IncrementalMerkleTree, (&admin, depth));
+        let client = IncrementalMerkleTreeClient::new(&env, &contract_id);
+        for i in 0..30u8 {
+            client.insert(&admin, &BytesN::from_array(&env, &[i + 1; 32]));
+        }
+
+        let unknown = BytesN::from_array(&env, &[0xff; 32]);
+        assert!(!client.is_known_root(&unknown));
+        Cost::of_last_call(&env).check(&std::format!("is_known_root depth {depth}"), limit);
+    }
+}
@@ -1,7 +1,13 @@
-use soroban_poseidon::poseidon2_hash;
+use rotor_admin::{
+    roles::{self, Role},
+    AdminSet,
+};
+use rotor_merkle::soroban::{SorobanHasher, VecFrontier};
+use rotor_merkle::MAX_DEPTH;
+use rotor_note::{call_field, recipient_field};
 use soroban_sdk::{
-    contract, contractimpl, contracttype, crypto::BnScalar, log, token, vec, Address, BytesN, Env,
-    U256,
+    contract, contractclient, contractevent, contractimpl, contracttype, log, token, xdr::ToXdr,
+    Address, Bytes, BytesN, Env, MuxedAddress, TryFromVal, Val, Vec,
 };
 
 // ---------------------------------------------------------------------------
@@ -12,69 +18,390 @@ pub enum DataKey {
     Relayer,               // Address: authorized relayer (submits withdrawals)
     Token,                 // Address: SAC address for the deposited asset (e.g. native XLM)
     Depth,                 // u32: tree depth (set once in constructor)
-    NextLeafIndex,         // u32: next leaf to insert
+    NextLeafIndex,         // u64: next leaf to insert
     CurrentRootIndex,      // u32: position in root ring buffer
-    CachedSubtree(u32),    // BytesN<32>: cached subtree at level i
-    Root(u32),             // BytesN<32>: root at ring buffer position i
-    Leaf(u32),             // BytesN<32>: commitment at leaf index i
+    CachedSubtree(u32),    // legacy, read only by `migrate`: cached subtree at level i
+    Root(u32),             // legacy, read only by `migrate`: root at ring buffer position i
+    Leaf(u64),             // BytesN<32>: commitment at leaf index i (if StoreLeaves)
     Nullifier(BytesN<32>), // bool: whether a nullifier_hash has been spent
+    RateLimit,             // RateLimit: withdrawal caps (unset = unlimited)
+    RateWindow,            // RateWindow: usage of the current rate-limit window
+    Paused,                // bool: withdrawals and transact paused by the admin
+    Lock(u64),             // NoteLock: locked deposit not yet inserted into the tree
+    NextLockId,            // u64: id of the next locked deposit
+    Frontier,              // Vec<BytesN<32>>: cached subtree at each level
+    RootHistory,           // Vec<BytesN<32>>: root ring buffer
+    StoreLeaves,           // bool: keep commitments in Leaf(i) (unset = true)
+    FeeBps,                // u32: protocol fee on withdrawals, in basis points (unset = 0)
+    Treasury,              // Address: the only destination for collected fees
+    FeesCollected,         // i128: fees held by the pool, not owed to any note
+    MinStake,              // i128: stake a relayer must lock to submit (unset = 0)
+    Stake(Address),        // RelayerStake: stake locked by a registered relayer
+    TotalStaked,           // i128: stakes held by the pool, not owed to any note
+    Payout(BytesN<32>),    // PayoutRecord (temporary): staked relayer's payout for a nullifier_hash
+    NextActionId,          // u64: id of the next queued governance action
+    Action(u64),           // QueuedAction: governance action waiting for its eta
+    LeafMigration,         // LeafMigration: legacy `Leaf(u32)` entries not yet moved
+    Denomination,          // i128: amount every deposit pulls (unset = deposits funded separately)
 }
 
-const ROOT_HISTORY_SIZE: u32 = 30;
-const TREE_DEPTH: u32 = 20;
+/// Keys of the original layout whose payload type has since changed. The
+/// variant names match `DataKey`, so these encode to the original ledger keys.
+#[contracttype]
+pub enum LegacyKey {
+    Leaf(u32), // BytesN<32>: commitment at leaf index i, before indices were u64
+}
 
-// ---------------------------------------------------------------------------
-// Precomputed zero hashes for the empty Merkle tree.
-//
-// zeros(0) = keccak256("cyfrin") % BN254_FIELD_SIZE
-// zeros(i+1) = Poseidon2(zeros(i), zeros(i))
-//
-// These MUST match the hash function used in the Noir circuit.
-// ---------------------------------------------------------------------------
-const ZEROS: [[u8; 32]; 20] = [
-    hex("0d823319708ab99ec915efd4f7e03d11ca1790918e8f04cd14100aceca2aa9ff"),
-    hex("170a9598425eb05eb8dc06986c6afc717811e874326a79576c02d338bdf14f13"),
-    hex("273b1a40397b618dac2fc66ceb71399a3e1a60341e546e053cbfa5995e824caf"),
-    hex("16bf9b1fb2dfa9d88cfb1752d6937a1594d257c2053dff3cb971016bfcffe2a1"),
-    hex("1288271e1f93a29fa6e748b7468a77a9b8fc3db6b216ce5fc2601fc3e9bd6b36"),
-    hex("1d47548adec1068354d163be4ffa348ca89f079b039c9191378584abd79edeca"),
-    hex("0b98a89e6827ef697b8fb2e280a2342d61db1eb5efc229f5f4a77fb333b80bef"),
-    hex("231555e37e6b206f43fdcd4d660c47442d76aab1ef552aef6db45f3f9cf2e955"),
-    hex("03d0dc8c92e2844abcc5fdefe8cb67d93034de0862943990b09c6b8e3fa27a86"),
-    hex("1d51ac275f47f10e592b8e690fd3b28a76106893ac3e60cd7b2a3a443f4e8355"),
-    hex("16b671eb844a8e4e463e820e26560357edee4ecfdbf5d7b0a28799911505088d"),
-    hex("115ea0c2f132c5914d5bb737af6eed04115a3896f0d65e12e761ca560083da15"),
-    hex("139a5b42099806c76efb52da0ec1dde06a836bf6f87ef7ab4bac7d00637e28f0"),
-    hex("0804853482335a6533eb6a4ddfc215a08026db413d247a7695e807e38debea8e"),
-    hex("2f0b264ab5f5630b591af93d93ec2dfed28eef017b251e40905cdf7983689803"),
-    hex("170fc161bf1b9610bf196c173bdae82c4adfd93888dc317f5010822a3ba9ebee"),
-    hex("0b2e7665b17622cc0243b6fa35110aa7dd0ee3cc9409650172aa786ca5971439"),
-    hex("12d5a033cbeff854c5ba0c5628ac4628104be6ab370699a1b2b4209e518b0ac5"),
-    hex("1bc59846eb7eafafc85ba9a99a89562763735322e4255b7c1788a8fe8b90bf5d"),
-    hex("1b9421fbd79f6972a348a3dd4721781ec25a5d8d27342942ae00aba80a3904d4"),
+/// Progress of `migrate_leaves`: legacy leaves `next..end` are still stored
+/// under `LegacyKey::Leaf`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LeafMigration {
+    pub next: u64,
+    pub end: u64,
+}
+
+/// Withdrawal caps enforced per window of `window_ledgers` ledgers.
+/// A zero amount disables the corresponding cap.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    pub window_ledgers: u32,
+    pub max_window_amount: i128,
+    pub max_withdrawal: i128,
+}
+
+/// Value withdrawn since `start_ledger`, the first ledger of the current window.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateWindow {
+    pub start_ledger: u32,
+    pub withdrawn: i128,
+}
+
+/// A funded deposit held back from the tree until `unlock_ledger` (see
+/// `deposit_locked`).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NoteLock {
+    pub commitment: BytesN<32>,
+    pub amount: i128,
+    pub unlock_ledger: u32,
+}
+
+/// Stake locked by a relayer registered with `register_relayer`.
+///
+/// `unbond_ledger` is 0 while the stake is bonded; once the relayer calls
+/// `begin_unbonding` it is the ledger from which `unbond` returns the stake.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RelayerStake {
+    pub amount: i128,
+    pub signing_key: BytesN<32>,
+    pub unbond_ledger: u32,
+}
+
+/// What a staked relayer submitted for a nullifier_hash, kept for
+/// `UNBONDING_DELAY` ledgers as evidence for `slash`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PayoutRecord {
+    pub relayer: Address,
+    /// `recipient_field` of the recipient paid, muxed id included.
+    pub recipient: BytesN<32>,
+    pub relayer_fee: i128,
+}
+
+/// A withdrawal a relayer committed to submit, signed with the ed25519
+/// `signing_key` of its stake over `intent.to_xdr()`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawalIntent {
+    pub pool: Address,
+    pub relayer: Address,
+    pub nullifier_hash: BytesN<32>,
+    pub recipient: MuxedAddress,
+    pub relayer_fee: i128,
+}
+
+/// Order of a proof's public inputs. A circuit change that adds or moves a
+/// public input (a fee, a deadline) gets a new layout, and the withdrawal
+/// path taking it names the layouts it parses. Withdrawals take the layout
+/// as an argument rather than guessing it from the vector's length, which
+/// two layouts share: `WithdrawV2` and `PartialV1` both have 5 inputs.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum PublicInputLayout {
+    /// `[root, nullifier_hash, recipient, amount]`, for `withdraw`. Commits
+    /// to no relayer fee, so none can be paid.
+    WithdrawV1 = 1,
+    /// `[root, nullifier_hash, recipient, withdraw_amount, change_commitment]`,
+    /// for `withdraw_partial`. Commits to no relayer fee, so none can be paid.
+    PartialV1 = 2,
+    /// `WithdrawV1` followed by `relayer_fee`, the most the relayer may take.
+    WithdrawV2 = 3,
+    /// `PartialV1` followed by `relayer_fee`, the most the relayer may take.
+    PartialV2 = 4,
+}
+
+/// Layouts accepted by `withdraw` and `withdraw_and_call`.
+const WITHDRAW_LAYOUTS: [PublicInputLayout; 2] =
+    [PublicInputLayout::WithdrawV1, PublicInputLayout::WithdrawV2];
+/// Layouts accepted by `withdraw_partial`.
+const PARTIAL_LAYOUTS: [PublicInputLayout; 2] =
+    [PublicInputLayout::PartialV1, PublicInputLayout::PartialV2];
+
+impl PublicInputLayout {
+    /// Number of public inputs in the layout.
+    pub fn input_count(self) -> u32 {
+        match self {
+            PublicInputLayout::WithdrawV1 => 4,
+            PublicInputLayout::PartialV1 => 5,
+            PublicInputLayout::WithdrawV2 => 5,
+            PublicInputLayout::PartialV2 => 6,
+        }
+    }
+
+    /// `self`, if it is one of `layouts`, those of the circuits a withdrawal
+    /// path takes proofs from.
+    fn accepted_by(self, layouts: &[PublicInputLayout]) -> Self {
+        assert!(layouts.contains(&self), "layout not accepted here");
+        self
+    }
+}
+
+/// A withdrawal proof's public inputs, parsed from the vector the prover
+/// outputs (see `WithdrawPublicInputs::parse`).
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawPublicInputs {
+    pub layout: PublicInputLayout,
+    pub root: BytesN<32>,
+    pub nullifier_hash: BytesN<32>,
+    /// `rotor_note::Recipient::field` of the address the proof pays.
+    pub recipient: BytesN<32>,
+    /// Amount paid out, decoded from its field element.
+    pub amount: i128,
+    /// Commitment of the note re-deposited with the rest; partial layouts only.
+    pub change_commitment: Option<BytesN<32>>,
+    /// Most the relayer may take, decoded from its field element; 0 for the
+    /// V1 layouts, which do not commit to a fee.
+    pub max_relayer_fee: i128,
+}
+
+impl WithdrawPublicInputs {
+    /// Name the parts of `inputs`, which must follow `layout`.
+    pub fn parse(layout: PublicInputLayout, inputs: &Vec<BytesN<32>>) -> Self {
+        assert!(
+            inputs.len() == layout.input_count(),
+            "public inputs do not match layout"
+        );
+        let amount = RotorCore::field_to_amount(&inputs.get_unchecked(3));
+        assert!(amount > 0, "amount must be positive");
+        let (change_commitment, max_relayer_fee) = match layout {
+            PublicInputLayout::WithdrawV1 => (None, None),
+            PublicInputLayout::PartialV1 => (Some(inputs.get_unchecked(4)), None),
+            PublicInputLayout::WithdrawV2 => (None, Some(inputs.get_unchecked(4))),
+            PublicInputLayout::PartialV2 => {
+                (Some(inputs.get_unchecked(4)), Some(inputs.get_unchecked(5)))
+            }
+        };
+        Self {
+            layout,
+            root: inputs.get_unchecked(0),
+            nullifier_hash: inputs.get_unchecked(1),
+            recipient: inputs.get_unchecked(2),
+            amount,
+            change_commitment,
+            max_relayer_fee: max_relayer_fee.map_or(0, |fee| RotorCore::field_to_amount(&fee)),
+        }
+    }
+}
+
+/// Roles checked by this contract's privileged entry points.
+const POOL_ROLES: [Role; 5] = [
+    Role::Pauser,
+    Role::Upgrader,
+    Role::FeeManager,
+    Role::RelayerManager,
+    Role::LimitManager,
 ];
+/// A governance change that only takes effect through the timelock, so
+/// users can exit before it applies. Role holders queue the actions their
+/// role guards with `queue`; the admin set queues changes to itself and to
+/// the roles with `queue_admin`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Replace the relayer that may submit without a stake.
+    SetRelayer(Address),
+    /// Pay notes in another token. The pool must hold enough of it to back
+    /// every unspent note.
+    SetToken(Address),
+    /// Charge this many basis points of every withdrawn amount, at most
+    /// `MAX_FEE_BPS`. The fee is kept by the pool and counted in
+    /// `get_fees_collected`; the recipient receives the rest. Notes commit
+    /// to their full amount and pay the fee in force when they are
+    /// withdrawn, so the pool always holds what its notes are owed plus the
+    /// fees collected so far.
+    SetFee(u32),
+    /// Replace the contract code, keeping its storage and address.
+    Upgrade(BytesN<32>),
+    /// Send collected fees to this address from now on.
+    SetTreasury(Address),
+    /// Require at least this stake from relayers registering from now on
+    /// and from bonded relayers submitting withdrawals.
+    SetMinStake(i128),
+    /// Propose a new admin set, which takes effect once every one of its
+    /// signers calls `accept_admin`.
+    ProposeAdmin(AdminSet),
+    /// Let an account call the entry points guarded by a role.
+    GrantRole(Role, Address),
+    RevokeRole(Role, Address),
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueuedAction {
+    pub action: Action,
+    pub eta: u32,
+}
+
+/// What a contract implements to receive `withdraw_and_call` payouts.
+///
+/// `withdraw_and_call` takes `data` for this one callback, not a function
+/// name and arguments to invoke on `target`. The pool calls with its own
+/// authority, so a caller-chosen function could be one that acts on the
+/// pool's behalf (an allowance, a transfer) rather than one that receives
+/// funds. A target that wants another entry point wraps it in
+/// `on_rotor_withdraw` and decodes its arguments from `data`.
+#[contractclient(name = "WithdrawTargetClient")]
+pub trait WithdrawTarget {
+    /// Called once `amount` of `token` has been transferred to the target.
+    /// `data` is the call data the proof binds, for the target to decode.
+    fn on_rotor_withdraw(env: Env, token: Address, amount: i128, data: Bytes);
+}
+
+const ROOT_HISTORY_SIZE: u32 = 30;
+/// Ledgers between `begin_unbonding` and `unbond` (about 7 days at 5s per
+/// ledger). Payout records live as long, so a relayer cannot withdraw its
+/// stake while evidence against it can still be submitted.
+pub const UNBONDING_DELAY: u32 = 120_960;
+/// Upper bound on the protocol fee, 1%. Fixed in code so no admin can raise
+/// it far enough to confiscate notes.
+pub const MAX_FEE_BPS: u32 = 100;
+const BPS: i128 = 10_000;
+/// Least number of ledgers between queueing an action and its eta (about
+/// 7 days at 5s per ledger), the time users have to exit.
+pub const MIN_DELAY: u32 = 120_960;
+/// Ledgers after its eta during which an action can still be executed.
+pub const GRACE_PERIOD: u32 = 120_960;
+/// Notes spent and created by every `transact`, fixed by circuits/transact.
+const TRANSACT_INPUTS: u32 = 2;
+const TRANSACT_OUTPUTS: u32 = 2;
 
 // ---------------------------------------------------------------------------
-// Compile-time hex decoder
+// Events
 // ---------------------------------------------------------------------------
-const fn hex(s: &str) -> [u8; 32] {
-    let b = s.as_bytes();
-    let mut out = [0u8; 32];
-    let mut i = 0;
-    while i < 32 {
-        out[i] = (hex_digit(b[i * 2]) << 4) | hex_digit(b[i * 2 + 1]);
-        i += 1;
-    }
-    out
+
+/// Emitted for every commitment inserted into the tree (deposits, change
+/// notes and `transact` outputs), so note holders can locate their leaf index.
+#[contractevent]
+pub struct NewCommitment {
+    pub commitment: BytesN<32>,
+    pub leaf_index: u64,
 }
 
-const fn hex_digit(c: u8) -> u8 {
-    match c {
-        b'0'..=b'9' => c - b'0',
-        b'a'..=b'f' => c - b'a' + 10,
-        b'A'..=b'F' => c - b'A' + 10,
-        _ => panic!("invalid hex"),
-    }
+/// Emitted whenever a nullifier is marked as spent.
+#[contractevent]
+pub struct NewNullifier {
+    pub nullifier_hash: BytesN<32>,
+}
+
+#[contractevent]
+pub struct FeeUpdated {
+    pub fee_bps: u32,
+}
+
+#[contractevent]
+pub struct TreasuryUpdated {
+    pub treasury: Address,
+}
+
+/// Emitted when collected fees leave the pool for the treasury.
+#[contractevent]
+pub struct FeesWithdrawn {
+    pub treasury: Address,
+    pub amount: i128,
+}
+
+#[contractevent]
+pub struct RelayerUpdated {
+    pub relayer: Address,
+}
+
+#[contractevent]
+pub struct TokenUpdated {
+    pub token: Address,
+}
+
+#[contractevent]
+pub struct ActionQueued {
+    #[topic]
+    pub id: u64,
+    pub action: Action,
+    pub eta: u32,
+}
+
+#[contractevent]
+pub struct ActionCancelled {
+    #[topic]
+    pub id: u64,
+}
+
+#[contractevent]
+pub struct ActionExecuted {
+    #[topic]
+    pub id: u64,
+}
+
+#[contractevent]
+pub struct MinStakeUpdated {
+    pub min_stake: i128,
+}
+
+#[contractevent]
+pub struct RelayerRegistered {
+    #[topic]
+    pub relayer: Address,
+    pub amount: i128,
+}
+
+#[contractevent]
+pub struct RelayerUnbonding {
+    #[topic]
+    pub relayer: Address,
+    pub unbond_ledger: u32,
+}
+
+#[contractevent]
+pub struct RelayerUnbonded {
+    #[topic]
+    pub relayer: Address,
+    pub amount: i128,
+}
+
+/// Emitted when a relayer's stake is confiscated. `compensation` went to
+/// `recipient`, the recipient its signed intent named; `forfeited` went to
+/// the pool's fees.
+#[contractevent]
+pub struct RelayerSlashed {
+    #[topic]
+    pub relayer: Address,
+    pub recipient: Address,
+    pub compensation: i128,
+    pub forfeited: i128,
 }
 
 // ---------------------------------------------------------------------------
@@ -87,31 +414,65 @@ pub struct RotorCore;
 impl RotorCore {
     /// Initialize the mixer contract.
     ///
-    /// - `relayer`: address authorized to submit withdrawals on behalf of recipients.
+    /// - `admin`:   the pool's admin set, as a single signer, and the first
+    ///              holder of every role. Through `queue_admin` it can grant
+    ///              roles to other keys, revoke its own and hand over to an
+    ///              M-of-N signer set with `Action::ProposeAdmin`.
+    /// - `relayer`: address authorized to submit withdrawals on behalf of
+    ///              recipients without a stake. Other relayers can join by
+    ///              staking (see `register_relayer`).
     /// - `token`:   SAC address for the deposited asset (native XLM on testnet:
     ///              CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC).
-    pub fn init(env: Env) {
-        let relayer = Address::from_str(
-            &env,
-            "GC2E5CXISHP3DMHIR674RQL3IQQK3AZEQ2EOOCIZR4B5IYA3KXP3VKKT",  // relayer mainnet address
-        );
-        let token = Address::from_str(
-            &env,
-            "CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA",  // XLM mainnet addy
-        );
-
+    /// - `depth`:   tree depth (max 32, determines max leaves = 2^depth). Must
+    ///              equal `TREE_DEPTH` in the circuits proofs are made with
+    ///              (20 in circuit/src/main.nr).
+    /// - `denomination`: when positive, `deposit` pulls exactly this amount
+    ///              and `deposit_locked` accepts no other, so every deposited
+    ///              note is worth it. 0 leaves deposits funded separately, for
+    ///              any amount.
+    /// - `store_leaves`: keep every commitment in contract storage. When
+    ///              false, commitments are only published in `NewCommitment`
+    ///              events: no rent is paid per leaf, `get_leaf` is unavailable
+    ///              and Merkle paths must be built by an indexer.
+    pub fn __constructor(
+        env: Env,
+        admin: Address,
+        relayer: Address,
+        token: Address,
+        depth: u32,
+        denomination: i128,
+        store_leaves: bool,
+    ) {
+        assert!(depth > 0 && depth <= MAX_DEPTH, "depth must be 1..=32");
+        assert!(denomination >= 0, "denomination must not be negative");
+        rotor_admin::init(&env, &AdminSet::single(&env, admin.clone()));
+        for role in POOL_ROLES {
+            roles::grant_unchecked(&env, role, &admin);
+        }
         env.storage().instance().set(&DataKey::Relayer, &relayer);
         env.storage().instance().set(&DataKey::Token, &token);
-        env.storage().instance().set(&DataKey::Depth, &TREE_DEPTH);
-        env.storage().instance().set(&DataKey::NextLeafIndex, &0u32);
+        env.storage()
+            .instance()
+            .set(&DataKey::StoreLeaves, &store_leaves);
+        env.storage().instance().set(&DataKey::Depth, &depth);
+        if denomination > 0 {
+            env.storage()
+                .instance()
+                .set(&DataKey::Denomination, &denomination);
+        }
+        env.storage().instance().set(&DataKey::NextLeafIndex, &0u64);
         env.storage()
             .instance()
             .set(&DataKey::CurrentRootIndex, &0u32);
 
-        let initial_root = BytesN::from_array(&env, &ZEROS[TREE_DEPTH as usize - 1]);
+        let initial_root = BytesN::from_array(&env, &rotor_merkle::initial_root(depth));
         env.storage()
             .persistent()
-            .set(&DataKey::Root(0), &initial_root);
+            .set(&DataKey::Frontier, &VecFrontier::empty(&env, depth).0);
+        env.storage().persistent().set(
+            &DataKey::RootHistory,
+            &Vec::from_array(&env, [initial_root]),
+        );
     }
 
     // -----------------------------------------------------------------------
@@ -120,14 +481,26 @@ impl RotorCore {
 
     /// Record a commitment in the Merkle tree.
     ///
-    /// The token transfer is handled separately by the frontend — the user
-    /// calls the SAC's `transfer()` directly to fund the contract, then
-    /// calls this function to store the commitment on-chain.
-    ///
-    /// This keeps the deposit function free of amount information.
-    pub fn deposit(env: Env, depositor: Address, commitment: BytesN<32>) -> u32 {
+    /// In a denominated pool (see `get_denomination`) this pulls the
+    /// denomination from the depositor in the same call. Otherwise the token
+    /// transfer is handled separately by the frontend — the user calls the
+    /// SAC's `transfer()` directly to fund the contract, then calls this
+    /// function to store the commitment on-chain, which keeps the deposit
+    /// function free of amount information.
+    pub fn deposit(env: Env, depositor: Address, commitment: BytesN<32>) -> u64 {
         depositor.require_auth();
 
+        let denomination = Self::get_denomination(env.clone());
+        if denomination > 0 {
+            let token_addr: Address = env
+                .storage()
+                .instance()
+                .get(&DataKey::Token)
+                .expect("token not set");
+            let token_client = token::Client::new(&env, &token_addr);
+            token_client.transfer(&depositor, env.current_contract_address(), &denomination);
+        }
+
         let leaf_index = Self::insert_leaf(&env, commitment.clone());
 
         log!(&env, "Deposit: leaf={}", leaf_index);
@@ -135,6 +508,95 @@ impl RotorCore {
         leaf_index
     }
 
+    /// Fund the pool with a note that cannot be spent before `unlock_ledger`.
+    ///
+    /// Unlike `deposit`, this pulls `amount` from the depositor in the same
+    /// call, and it does not insert `commitment` yet: the deposit is held
+    /// under the returned lock id until `release_lock` inserts it. No root
+    /// contains the note before then, so no proof of it can exist and every
+    /// spend path (`withdraw`, `withdraw_partial`, `withdraw_and_call` and
+    /// `transact`) is locked out. The lock names only the depositor's own
+    /// commitment, so it can neither lock nor be escaped by any other note.
+    pub fn deposit_locked(
+        env: Env,
+        depositor: Address,
+        commitment: BytesN<32>,
+        amount: i128,
+        unlock_ledger: u32,
+    ) -> u64 {
+        depositor.require_auth();
+
+        assert!(amount > 0, "amount must be positive");
+        let denomination = Self::get_denomination(env.clone());
+        assert!(
+            denomination == 0 || amount == denomination,
+            "amount must equal the pool's denomination"
+        );
+        assert!(
+            unlock_ledger > env.ledger().sequence(),
+            "unlock ledger must be in the future"
+        );
+
+        let token_addr: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Token)
+            .expect("token not set");
+        let token_client = token::Client::new(&env, &token_addr);
+        token_client.transfer(&depositor, env.current_contract_address(), &amount);
+
+        let lock_id: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::NextLockId)
+            .unwrap_or(0);
+        env.storage()
+            .instance()
+            .set(&DataKey::NextLockId, &(lock_id + 1));
+        env.storage().persistent().set(
+            &DataKey::Lock(lock_id),
+            &NoteLock {
+                commitment,
+                amount,
+                unlock_ledger,
+            },
+        );
+
+        log!(
+            &env,
+            "Locked deposit: lock={}, unlock_ledger={}",
+            lock_id,
+            unlock_ledger
+        );
+
+        lock_id
+    }
+
+    /// Insert the commitment of an expired lock into the tree, making its
+    /// note spendable.
+    ///
+    /// Anyone may call this, so the depositor can release from an unrelated
+    /// account. Returns the note's leaf index.
+    pub fn release_lock(env: Env, lock_id: u64) -> u64 {
+        let lock_key = DataKey::Lock(lock_id);
+        let lock: NoteLock = env
+            .storage()
+            .persistent()
+            .get(&lock_key)
+            .expect("lock not found");
+        assert!(
+            env.ledger().sequence() >= lock.unlock_ledger,
+            "note is still locked"
+        );
+
+        env.storage().persistent().remove(&lock_key);
+        let leaf_index = Self::insert_leaf(&env, lock.commitment);
+
+        log!(&env, "Released lock: lock={}, leaf={}", lock_id, leaf_index);
+
+        leaf_index
+    }
+
     // -----------------------------------------------------------------------
     // WITHDRAW
     // -----------------------------------------------------------------------
@@ -143,57 +605,222 @@ impl RotorCore {
     ///
     /// Called by the relayer AFTER verifying the ZK proof off-chain.
     ///
-    /// `proof_amount` is the BN254 field element from the ZK proof's public
-    /// inputs that encodes the withdrawal amount in stroops. The contract
-    /// derives the actual i128 amount by interpreting the lower 16 bytes of
-    /// the 32-byte big-endian field element.
+    /// `public_inputs` are the proof's public inputs as verified, in the
+    /// order `layout` names: `PublicInputLayout::WithdrawV2`
+    /// `[root, nullifier_hash, recipient, amount, relayer_fee]`, or
+    /// `WithdrawV1` without `relayer_fee`. The contract checks the
+    /// payout against them: `recipient` must encode to the proof's
+    /// recipient field (`rotor_note::Recipient::field`), so a verified proof
+    /// pays out only to the address it was made for. `recipient` may be an
+    /// account, a contract or a muxed account; a muxed id is kept on the
+    /// transfer.
+    ///
+    /// The contract does not verify the proof itself. Every check here holds
+    /// only for inputs taken from a proof the relayer verified: a relayer
+    /// that skips or fakes verification can submit inputs of its choosing,
+    /// with any recipient and amount, as long as they name a known root and
+    /// an unspent nullifier. Only the configured relayer and bonded relayers
+    /// can submit, and the pool trusts them with its funds until proofs are
+    /// verified on-chain.
+    ///
+    /// `amount` is the BN254 field element that encodes the withdrawal
+    /// amount in stroops. The contract derives the actual i128 amount by
+    /// interpreting the lower 16 bytes of the 32-byte big-endian field
+    /// element.
+    ///
+    /// `relayer_fee` is paid to `relayer` out of the amount, after the
+    /// protocol fee; the recipient receives the rest. It may not exceed the
+    /// fee the proof commits to, so a `WithdrawV1` proof pays no relayer fee.
     ///
     /// The contract:
-    /// 1. Verifies the caller is the authorized relayer
-    /// 2. Derives the amount from `proof_amount`
-    /// 3. Ensures the nullifier has not been spent (prevents double-withdraw)
-    /// 4. Marks the nullifier as spent
-    /// 5. Transfers XLM from the contract to the recipient via SAC
+    /// 1. Verifies `relayer` signed and is the configured or a bonded relayer
+    /// 2. Checks `recipient` and `relayer_fee` against the proof
+    /// 3. Derives the amount and checks `root` is in the recent root history
+    /// 4. Ensures the nullifier has not been spent (prevents double-withdraw)
+    /// 5. Marks the nullifier as spent
+    /// 6. Applies the withdrawal rate limits (see `set_rate_limit`)
+    /// 7. Records the payout if `relayer` is staked (see `slash`)
+    /// 8. Transfers XLM from the contract to the recipient via SAC, less the
+    ///    protocol fee (see `Action::SetFee`) and the relayer fee
     pub fn withdraw(
         env: Env,
-        nullifier_hash: BytesN<32>,
-        recipient: Address,
-        proof_amount: BytesN<32>,
+        relayer: Address,
+        layout: PublicInputLayout,
+        public_inputs: Vec<BytesN<32>>,
+        recipient: MuxedAddress,
+        relayer_fee: i128,
     ) {
-        let relayer: Address = env
-            .storage()
-            .instance()
-            .get(&DataKey::Relayer)
-            .expect("relayer not set");
-        relayer.require_auth();
+        let staked = Self::require_relayer(&env, &relayer);
+        let inputs =
+            WithdrawPublicInputs::parse(layout.accepted_by(&WITHDRAW_LAYOUTS), &public_inputs);
+        Self::check_recipient(&recipient, &inputs.recipient);
+        Self::check_relayer_fee(&inputs, relayer_fee);
+        let amount = inputs.amount;
+        assert!(
+            Self::is_known_root(&env, &inputs.root),
+            "unknown merkle root"
+        );
 
-        let amount = Self::field_to_amount(&proof_amount);
-        assert!(amount > 0, "amount must be positive");
+        Self::spend_nullifier(&env, &inputs.nullifier_hash);
+        Self::record_withdrawal(&env, amount);
+        if staked {
+            Self::record_payout(
+                &env,
+                &relayer,
+                &inputs.nullifier_hash,
+                &inputs.recipient,
+                relayer_fee,
+            );
+        }
+        Self::pay_out(&env, &recipient, amount, &relayer, relayer_fee);
 
-        let nullifier_key = DataKey::Nullifier(nullifier_hash.clone());
-        let already_spent: bool = env
-            .storage()
-            .persistent()
-            .get(&nullifier_key)
-            .unwrap_or(false);
-        assert!(!already_spent, "nullifier already spent");
+        log!(&env, "Withdraw: recipient={}, amount={}", recipient, amount);
+    }
 
-        env.storage().persistent().set(&nullifier_key, &true);
+    /// Withdraw part of a note and re-deposit the remainder as a change note.
+    ///
+    /// Called by the relayer AFTER verifying the partial-withdrawal proof
+    /// (circuits/withdraw_partial) off-chain. `public_inputs` are the proof's
+    /// public inputs, in the order `layout` names: `PublicInputLayout::PartialV2`
+    /// `[root, nullifier_hash, recipient, withdraw_amount, change_commitment,
+    /// relayer_fee]`, or `PartialV1` without `relayer_fee`;
+    /// the proof shows the spent note binds some `amount >= withdraw_amount`
+    /// and that `change_commitment = H(nullifier', secret', amount - withdraw_amount)`,
+    /// both parts below 2^64.
+    /// `recipient` and `relayer_fee` are checked against the proof as in
+    /// `withdraw`, and as there the proof itself is not verified on-chain.
+    ///
+    /// The contract:
+    /// 1. Verifies `relayer` signed and is the configured or a bonded relayer
+    /// 2. Checks `recipient` and `relayer_fee` against the proof
+    /// 3. Derives the payout from `withdraw_amount` (same encoding as `withdraw`)
+    /// 4. Checks `root` is in the recent root history
+    /// 5. Marks the nullifier as spent
+    /// 6. Applies the withdrawal rate limits to the payout
+    /// 7. Records the payout if `relayer` is staked (see `slash`)
+    /// 8. Inserts `change_commitment` into the Merkle tree
+    /// 9. Transfers the payout, less the protocol and relayer fees, to the
+    ///    recipient via SAC
+    ///
+    /// Returns the leaf index of the change note.
+    pub fn withdraw_partial(
+        env: Env,
+        relayer: Address,
+        layout: PublicInputLayout,
+        public_inputs: Vec<BytesN<32>>,
+        recipient: MuxedAddress,
+        relayer_fee: i128,
+    ) -> u64 {
+        let staked = Self::require_relayer(&env, &relayer);
+        let inputs =
+            WithdrawPublicInputs::parse(layout.accepted_by(&PARTIAL_LAYOUTS), &public_inputs);
+        Self::check_recipient(&recipient, &inputs.recipient);
+        Self::check_relayer_fee(&inputs, relayer_fee);
+        let amount = inputs.amount;
+        assert!(
+            Self::is_known_root(&env, &inputs.root),
+            "unknown merkle root"
+        );
 
-        let token_addr: Address = env
-            .storage()
-            .instance()
-            .get(&DataKey::Token)
-            .expect("token not set");
-        let token_client = token::Client::new(&env, &token_addr);
-        token_client.transfer(&env.current_contract_address(), &recipient, &amount);
+        Self::spend_nullifier(&env, &inputs.nullifier_hash);
+        Self::record_withdrawal(&env, amount);
+        if staked {
+            Self::record_payout(
+                &env,
+                &relayer,
+                &inputs.nullifier_hash,
+                &inputs.recipient,
+                relayer_fee,
+            );
+        }
+        let change_index = Self::insert_leaf(&env, inputs.change_commitment.unwrap());
+        Self::pay_out(&env, &recipient, amount, &relayer, relayer_fee);
 
-        log!(&env, "Withdraw: recipient={}, amount={}", recipient, amount);
+        log!(
+            &env,
+            "Withdraw partial: recipient={}, amount={}, change_leaf={}",
+            recipient,
+            amount,
+            change_index
+        );
+
+        change_index
+    }
+
+    /// Withdraw into a contract call: pay `target` and call its
+    /// `WithdrawTarget::on_rotor_withdraw` in the same transaction.
+    ///
+    /// Lets a note go straight into another protocol (a swap, a lending
+    /// deposit) without first landing at an address the user controls.
+    /// `public_inputs` follow a withdraw `layout`, as in `withdraw`, but the
+    /// recipient field binds the call, `rotor_note::call_field(target,
+    /// data)`, so a relayer can change neither the target nor the call data.
+    /// Such a proof cannot be spent with `withdraw`, nor a plain one here.
+    /// As in `withdraw`, the proof itself is not verified on-chain.
+    ///
+    /// Only the fixed callback is invoked, never an arbitrary function, and
+    /// `target` may be neither the pool's token nor the pool itself, so a
+    /// call proof cannot be turned into a token transfer or a pool call
+    /// made with the pool's own authority.
+    ///
+    /// The payout, less the protocol and relayer fees, is transferred to
+    /// `target` before the callback, which is told the token and amount. If
+    /// the callback fails the whole withdrawal reverts and the note stays
+    /// unspent.
+    pub fn withdraw_and_call(
+        env: Env,
+        relayer: Address,
+        layout: PublicInputLayout,
+        public_inputs: Vec<BytesN<32>>,
+        target: Address,
+        data: Bytes,
+        relayer_fee: i128,
+    ) {
+        let staked = Self::require_relayer(&env, &relayer);
+        let token = Self::get_token(env.clone());
+        assert!(target != token, "target must not be the token");
+        assert!(
+            target != env.current_contract_address(),
+            "target must not be the pool"
+        );
+        let inputs =
+            WithdrawPublicInputs::parse(layout.accepted_by(&WITHDRAW_LAYOUTS), &public_inputs);
+        assert!(
+            call_field(&target, &data) == inputs.recipient,
+            "call does not match proof"
+        );
+        Self::check_relayer_fee(&inputs, relayer_fee);
+        let amount = inputs.amount;
+        assert!(
+            Self::is_known_root(&env, &inputs.root),
+            "unknown merkle root"
+        );
+
+        Self::spend_nullifier(&env, &inputs.nullifier_hash);
+        Self::record_withdrawal(&env, amount);
+        if staked {
+            Self::record_payout(
+                &env,
+                &relayer,
+                &inputs.nullifier_hash,
+                &inputs.recipient,
+                relayer_fee,
+            );
+        }
+        let paid = Self::pay_out(&env, &target.clone().into(), amount, &relayer, relayer_fee);
+
+        log!(
+            &env,
+            "Withdraw and call: target={}, amount={}",
+            target,
+            amount
+        );
+        WithdrawTargetClient::new(&env, &target).on_rotor_withdraw(&token, &paid, &data);
     }
 
     /// Interpret the lower 16 bytes of a 32-byte big-endian field element as i128.
     /// XLM amounts fit comfortably within i128 (max supply ~5×10^17 stroops).
-    fn field_to_amount(field: &BytesN<32>) -> i128 {
+    pub(crate) fn field_to_amount(field: &BytesN<32>) -> i128 {
         let arr = field.to_array();
         let mut v: i128 = 0;
         let mut i = 16usize;
@@ -205,158 +832,1076 @@ impl RotorCore {
     }
 
     // -----------------------------------------------------------------------
-    // VIEW FUNCTIONS
+    // TRANSACT (shielded join-split)
     // -----------------------------------------------------------------------
 
-    pub fn get_latest_root(env: Env) -> BytesN<32> {
-        let idx: u32 = env
-            .storage()
-            .instance()
-            .get(&DataKey::CurrentRootIndex)
-            .unwrap();
-        env.storage().persistent().get(&DataKey::Root(idx)).unwrap()
-    }
+    /// Move value between notes without leaving the pool.
+    ///
+    /// Called by the relayer AFTER verifying the join-split proof
+    /// (circuits/transact) off-chain. The proof's public inputs are
+    /// `[root, input_nullifiers[0..2], output_commitments[0..2]]` and it
+    /// enforces value conservation: the amounts bound in the spent notes equal
+    /// the amounts bound in the new output commitments, each below 2^64. A
+    /// single note is spent alongside a zero-amount padding note, and a
+    /// single output is paired with a zero-amount change note. No token
+    /// transfer happens — the pool balance is unchanged. The proof is not
+    /// verified on-chain; as in `withdraw`, the pool trusts the relayer to
+    /// have verified it.
+    ///
+    /// The contract:
+    /// 1. Verifies `relayer` signed and is the configured or a bonded relayer,
+    ///    and that the admin has not paused the pool (see `pause`)
+    /// 2. Checks `root` is in the recent root history
+    /// 3. Marks both input nullifiers as spent (distinct, neither spent before)
+    /// 4. Inserts both output commitments into the Merkle tree
+    ///
+    /// Returns the leaf indices of the output commitments, in order.
+    pub fn transact(
+        env: Env,
+        relayer: Address,
+        root: BytesN<32>,
+        input_nullifiers: Vec<BytesN<32>>,
+        output_commitments: Vec<BytesN<32>>,
+    ) -> Vec<u64> {
+        Self::require_relayer(&env, &relayer);
+        assert!(!Self::is_admin_paused(&env), "transfers are paused");
 
-    pub fn get_next_index(env: Env) -> u32 {
-        env.storage()
-            .instance()
-            .get(&DataKey::NextLeafIndex)
-            .unwrap()
-    }
+        let n_inputs = input_nullifiers.len();
+        let n_outputs = output_commitments.len();
+        assert!(
+            n_inputs == TRANSACT_INPUTS,
+            "transact takes 2 input nullifiers"
+        );
+        assert!(
+            n_outputs == TRANSACT_OUTPUTS,
+            "transact takes 2 output commitments"
+        );
+        assert!(
+            input_nullifiers.get_unchecked(0) != input_nullifiers.get_unchecked(1),
+            "duplicate input nullifier"
+        );
+        assert!(Self::is_known_root(&env, &root), "unknown merkle root");
 
-    pub fn is_valid_root(env: Env, root: BytesN<32>) -> bool {
-        Self::is_known_root(&env, &root)
-    }
+        for nullifier_hash in input_nullifiers.iter() {
+            Self::spend_nullifier(&env, &nullifier_hash);
+        }
 
-    pub fn is_spent(env: Env, nullifier_hash: BytesN<32>) -> bool {
-        env.storage()
-            .persistent()
-            .get(&DataKey::Nullifier(nullifier_hash))
-            .unwrap_or(false)
-    }
+        let mut leaf_indices = Vec::new(&env);
+        for commitment in output_commitments.iter() {
+            leaf_indices.push_back(Self::insert_leaf(&env, commitment));
+        }
 
-    pub fn get_token(env: Env) -> Address {
-        env.storage()
-            .instance()
-            .get(&DataKey::Token)
-            .expect("token not set")
-    }
+        log!(&env, "Transact: inputs={}, outputs={}", n_inputs, n_outputs);
 
-    pub fn get_balance(env: Env) -> i128 {
-        let token_addr: Address = env
-            .storage()
-            .instance()
-            .get(&DataKey::Token)
-            .expect("token not set");
-        let token_client = token::Client::new(&env, &token_addr);
-        token_client.balance(&env.current_contract_address())
+        leaf_indices
     }
 
     // -----------------------------------------------------------------------
-    // INTERNAL: Merkle tree operations
+    // RELAYER STAKING
     // -----------------------------------------------------------------------
 
-    fn insert_leaf(env: &Env, leaf: BytesN<32>) -> u32 {
-        let next_index: u32 = env
-            .storage()
-            .instance()
-            .get(&DataKey::NextLeafIndex)
-            .unwrap();
+    /// Register `relayer` by locking `amount` of the pool's token, at least
+    /// `get_min_stake`. While bonded it may submit `withdraw`,
+    /// `withdraw_partial` and `transact`.
+    ///
+    /// `signing_key` is the ed25519 public key the relayer signs
+    /// `WithdrawalIntent`s with. An intent it signed but then submitted to a
+    /// different recipient or with a different fee forfeits the whole stake
+    /// (see `slash`).
+    pub fn register_relayer(env: Env, relayer: Address, signing_key: BytesN<32>, amount: i128) {
+        relayer.require_auth();
+        assert!(amount > 0, "amount must be positive");
+        assert!(
+            amount >= Self::get_min_stake(env.clone()),
+            "stake below minimum"
+        );
+        let key = DataKey::Stake(relayer.clone());
+        assert!(
+            !env.storage().persistent().has(&key),
+            "relayer already registered"
+        );
 
-        let max_leaves = 1u32 << TREE_DEPTH;
-        assert!(next_index < max_leaves, "merkle tree is full");
+        let token_client = token::Client::new(&env, &Self::get_token(env.clone()));
+        token_client.transfer(&relayer, env.current_contract_address(), &amount);
 
-        env.storage()
+        env.storage().persistent().set(
+            &key,
+            &RelayerStake {
+                amount,
+                signing_key,
+                unbond_ledger: 0,
+            },
+        );
+        Self::add_total_staked(&env, amount);
+
+        RelayerRegistered { relayer, amount }.publish(&env);
+    }
+
+    /// Stop relaying and start the `UNBONDING_DELAY` after which `unbond`
+    /// returns the stake. The stake can still be slashed until then.
+    pub fn begin_unbonding(env: Env, relayer: Address) {
+        relayer.require_auth();
+        let key = DataKey::Stake(relayer.clone());
+        let mut stake: RelayerStake = env
+            .storage()
             .persistent()
-            .set(&DataKey::Leaf(next_index), &leaf);
+            .get(&key)
+            .expect("relayer not registered");
+        assert!(stake.unbond_ledger == 0, "already unbonding");
 
-        let mut current_index = next_index;
-        let mut current_hash = leaf;
+        stake.unbond_ledger = env.ledger().sequence() + UNBONDING_DELAY;
+        env.storage().persistent().set(&key, &stake);
 
-        for i in 0..TREE_DEPTH {
-            if current_index % 2 == 0 {
-                let right = BytesN::from_array(env, &ZEROS[i as usize]);
-                env.storage()
-                    .persistent()
-                    .set(&DataKey::CachedSubtree(i), &current_hash);
-                current_hash = Self::hash_pair(env, &current_hash, &right);
-            } else {
-                let left: BytesN<32> = env
-                    .storage()
-                    .persistent()
-                    .get(&DataKey::CachedSubtree(i))
-                    .unwrap();
-                current_hash = Self::hash_pair(env, &left, &current_hash);
-            }
-            current_index /= 2;
+        RelayerUnbonding {
+            relayer,
+            unbond_ledger: stake.unbond_ledger,
         }
+        .publish(&env);
+    }
 
-        let current_root_idx: u32 = env
+    /// Return the stake to `relayer` once its unbonding delay has passed.
+    pub fn unbond(env: Env, relayer: Address) {
+        relayer.require_auth();
+        let key = DataKey::Stake(relayer.clone());
+        let stake: RelayerStake = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .expect("relayer not registered");
+        assert!(stake.unbond_ledger != 0, "relayer is not unbonding");
+        assert!(
+            env.ledger().sequence() >= stake.unbond_ledger,
+            "unbonding delay has not passed"
+        );
+
+        env.storage().persistent().remove(&key);
+        Self::add_total_staked(&env, -stake.amount);
+        let token_client = token::Client::new(&env, &Self::get_token(env.clone()));
+        token_client.transfer(&env.current_contract_address(), &relayer, &stake.amount);
+
+        RelayerUnbonded {
+            relayer,
+            amount: stake.amount,
+        }
+        .publish(&env);
+    }
+
+    /// Confiscate a relayer's stake given proof that it broke a promise.
+    ///
+    /// `signature` must be the relayer's ed25519 signature over
+    /// `intent.to_xdr()` with the `signing_key` of its stake, and the payout
+    /// recorded for `intent.nullifier_hash` must show the same relayer paying
+    /// a different recipient or charging a different fee.
+    ///
+    /// The relayer signs its intents itself, so `intent.recipient` proves
+    /// nothing on its own: a relayer could name its own address and slash
+    /// itself to skip `UNBONDING_DELAY`. Only a recipient the payout record
+    /// shows was paid is compensated, and only for the fee charged above the
+    /// intent's. The rest of the stake is forfeited to the pool's fees, which
+    /// `withdraw_fees` sends to the treasury.
+    ///
+    /// Anyone may submit the evidence. Payouts are recorded for
+    /// `UNBONDING_DELAY` ledgers, so evidence must arrive within that time.
+    pub fn slash(env: Env, intent: WithdrawalIntent, signature: BytesN<64>) {
+        assert!(
+            intent.pool == env.current_contract_address(),
+            "intent is for another pool"
+        );
+        let key = DataKey::Stake(intent.relayer.clone());
+        let stake: RelayerStake = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .expect("relayer not registered");
+        env.crypto()
+            .ed25519_verify(&stake.signing_key, &intent.clone().to_xdr(&env), &signature);
+
+        let payout = Self::get_payout(env.clone(), intent.nullifier_hash.clone())
+            .expect("no payout recorded for nullifier");
+        assert!(
+            payout.relayer == intent.relayer,
+            "payout submitted by another relayer"
+        );
+        assert!(
+            payout.recipient != recipient_field(&intent.recipient)
+                || payout.relayer_fee != intent.relayer_fee,
+            "payout matches intent"
+        );
+
+        let compensation = if payout.recipient == recipient_field(&intent.recipient) {
+            (payout.relayer_fee - intent.relayer_fee).clamp(0, stake.amount)
+        } else {
+            0
+        };
+        let forfeited = stake.amount - compensation;
+
+        env.storage().persistent().remove(&key);
+        Self::add_total_staked(&env, -stake.amount);
+        if compensation > 0 {
+            let token_client = token::Client::new(&env, &Self::get_token(env.clone()));
+            token_client.transfer(
+                &env.current_contract_address(),
+                &intent.recipient,
+                &compensation,
+            );
+        }
+        if forfeited > 0 {
+            let collected = Self::get_fees_collected(env.clone());
+            env.storage()
+                .instance()
+                .set(&DataKey::FeesCollected, &(collected + forfeited));
+        }
+
+        RelayerSlashed {
+            relayer: intent.relayer,
+            recipient: intent.recipient.address(),
+            compensation,
+            forfeited,
+        }
+        .publish(&env);
+    }
+
+    // -----------------------------------------------------------------------
+    // ADMIN: Withdrawal circuit breaker
+    // -----------------------------------------------------------------------
+
+    /// Configure per-window withdrawal caps.
+    ///
+    /// Once withdrawals in the current window reach `max_window_amount`, any
+    /// further withdrawal is rejected until the window rolls over after
+    /// `window_ledgers` ledgers, or until the admin resets the window or
+    /// raises the cap. `max_withdrawal` bounds each single withdrawal.
+    pub fn set_rate_limit(env: Env, caller: Address, limit: RateLimit) {
+        roles::require_role(&env, &caller, Role::LimitManager);
+        assert!(
+            limit.window_ledgers > 0,
+            "window must be at least one ledger"
+        );
+        assert!(
+            limit.max_window_amount >= 0 && limit.max_withdrawal >= 0,
+            "limits must not be negative"
+        );
+
+        env.storage().instance().set(&DataKey::RateLimit, &limit);
+    }
+
+    /// Start a fresh window, clearing the value withdrawn so far.
+    pub fn reset_rate_window(env: Env, caller: Address) {
+        roles::require_role(&env, &caller, Role::LimitManager);
+        env.storage().instance().remove(&DataKey::RateWindow);
+    }
+
+    /// Stop all withdrawals, and `transact` with them, until `unpause` is
+    /// called. A transfer spends nullifiers like a withdrawal does, so a
+    /// pause meant to contain a bad proof or relayer must cover it too.
+    /// Rate limits do not apply to `transact`, which moves no tokens.
+    pub fn pause(env: Env, caller: Address) {
+        roles::require_role(&env, &caller, Role::Pauser);
+        env.storage().instance().set(&DataKey::Paused, &true);
+        log!(&env, "Withdrawals paused");
+    }
+
+    pub fn unpause(env: Env, caller: Address) {
+        roles::require_role(&env, &caller, Role::Pauser);
+        env.storage().instance().set(&DataKey::Paused, &false);
+        log!(&env, "Withdrawals unpaused");
+    }
+
+    // -----------------------------------------------------------------------
+    // ADMIN: Protocol fee
+    // -----------------------------------------------------------------------
+
+    /// Send `amount` of the collected fees to the treasury. Only collected
+    /// fees can leave this way, never funds backing a note.
+    pub fn withdraw_fees(env: Env, caller: Address, amount: i128) {
+        roles::require_role(&env, &caller, Role::FeeManager);
+        assert!(amount > 0, "amount must be positive");
+
+        let treasury: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Treasury)
+            .expect("treasury not set");
+        let collected = Self::get_fees_collected(env.clone());
+        assert!(amount <= collected, "amount exceeds collected fees");
+        env.storage()
+            .instance()
+            .set(&DataKey::FeesCollected, &(collected - amount));
+
+        let token_client = token::Client::new(&env, &Self::get_token(env.clone()));
+        token_client.transfer(&env.current_contract_address(), &treasury, &amount);
+
+        FeesWithdrawn { treasury, amount }.publish(&env);
+    }
+
+    // -----------------------------------------------------------------------
+    // ADMIN: Admin set and roles
+    // -----------------------------------------------------------------------
+
+    /// Make the admin set proposed by `Action::ProposeAdmin` current. Every
+    /// proposed signer must authorize.
+    pub fn accept_admin(env: Env) {
+        rotor_admin::accept(&env);
+    }
+
+    // -----------------------------------------------------------------------
+    // GOVERNANCE: Timelocked actions
+    // -----------------------------------------------------------------------
+
+    /// Queue `action` to run at ledger `eta`, at least `MIN_DELAY` ledgers
+    /// from now. `caller` needs the role guarding the action: `Upgrader`
+    /// for `SetToken` and `Upgrade`, `FeeManager` for `SetFee` and
+    /// `SetTreasury`, `RelayerManager` for `SetRelayer` and `SetMinStake`.
+    /// Returns the action's id.
+    pub fn queue(env: Env, caller: Address, action: Action, eta: u32) -> u64 {
+        let role = Self::role_for(&action).expect("action needs the admin set");
+        roles::require_role(&env, &caller, role);
+        Self::enqueue(&env, action, eta)
+    }
+
+    /// Drop a queued action. Needs the role that could queue it.
+    pub fn cancel(env: Env, caller: Address, id: u64) {
+        let queued = Self::get_action(env.clone(), id).expect("action not queued");
+        let role = Self::role_for(&queued.action).expect("action needs the admin set");
+        roles::require_role(&env, &caller, role);
+        Self::dequeue(&env, id);
+        ActionCancelled { id }.publish(&env);
+    }
+
+    /// Apply a queued action once its eta is reached, and no later than
+    /// `GRACE_PERIOD` ledgers after. Needs the role that could queue it.
+    pub fn execute(env: Env, caller: Address, id: u64) {
+        let queued = Self::get_action(env.clone(), id).expect("action not queued");
+        let role = Self::role_for(&queued.action).expect("action needs the admin set");
+        roles::require_role(&env, &caller, role);
+        Self::apply(&env, id, queued);
+    }
+
+    /// Queue `ProposeAdmin`, `GrantRole` or `RevokeRole`, approved by the
+    /// admin set, like `queue`. Changes to the admin set and the roles wait
+    /// out the same delay as the actions those roles guard, so a leaked
+    /// quorum cannot hand itself a role and act before users can exit.
+    pub fn queue_admin(env: Env, approvers: Vec<Address>, action: Action, eta: u32) -> u64 {
+        assert!(
+            Self::role_for(&action).is_none(),
+            "action needs a role holder"
+        );
+        rotor_admin::require_admin(&env, &approvers);
+        Self::enqueue(&env, action, eta)
+    }
+
+    /// Drop an action queued with `queue_admin`, approved by the admin set.
+    pub fn cancel_admin(env: Env, approvers: Vec<Address>, id: u64) {
+        let queued = Self::get_action(env.clone(), id).expect("action not queued");
+        assert!(
+            Self::role_for(&queued.action).is_none(),
+            "action needs a role holder"
+        );
+        rotor_admin::require_admin(&env, &approvers);
+        Self::dequeue(&env, id);
+        ActionCancelled { id }.publish(&env);
+    }
+
+    /// Apply an action queued with `queue_admin`, approved by the admin set
+    /// in force when it runs, like `execute`.
+    pub fn execute_admin(env: Env, approvers: Vec<Address>, id: u64) {
+        let queued = Self::get_action(env.clone(), id).expect("action not queued");
+        assert!(
+            Self::role_for(&queued.action).is_none(),
+            "action needs a role holder"
+        );
+        rotor_admin::require_admin(&env, &approvers);
+        Self::apply(&env, id, queued);
+    }
+
+    // -----------------------------------------------------------------------
+    // ADMIN: Storage migration
+    // -----------------------------------------------------------------------
+
+    /// Give a pool written by the original contract, which had no admin, its
+    /// first admin: the admin set of one `admin` holding every role, as the
+    /// constructor sets up. Authorized by the pool's stored `relayer`, the
+    /// only address that contract trusted, and only while no admin set
+    /// exists, so it cannot be used to take over a pool that has one.
+    pub fn claim_admin(env: Env, relayer: Address, admin: Address) {
+        assert!(
+            rotor_admin::try_get(&env).is_none(),
+            "pool already has an admin"
+        );
+        assert!(
+            relayer == Self::get_relayer(env.clone()),
+            "not the pool's relayer"
+        );
+        relayer.require_auth();
+
+        rotor_admin::init(&env, &AdminSet::single(&env, admin.clone()));
+        for role in POOL_ROLES {
+            roles::grant_unchecked(&env, role, &admin);
+        }
+    }
+
+    /// Move a pool written by an earlier version, which kept one entry per
+    /// frontier level (`CachedSubtree`) and per ring buffer slot (`Root`),
+    /// to the packed `Frontier` and `RootHistory` entries. Run once, right
+    /// after the upgrade; deposits and withdrawals fail until it has.
+    ///
+    /// Ring buffer positions are kept, so `CurrentRootIndex` stays valid and
+    /// every root known before the upgrade is still accepted. The legacy
+    /// entries are left to expire: removing them as well would take the call
+    /// past the per-transaction write limit.
+    ///
+    /// The original layout also counted leaves in a `u32` and stored them
+    /// under `Leaf(u32)`. Such a `NextLeafIndex` is widened to `u64` here,
+    /// and the leaves are moved afterwards by `migrate_leaves`; until they
+    /// are, `get_leaf` fails for the ones not yet moved.
+    ///
+    /// Storage written by the original contract has no admin set, so nobody
+    /// holds `Upgrader` there; its relayer first installs one with
+    /// `claim_admin`.
+    pub fn migrate(env: Env, caller: Address) {
+        roles::require_role(&env, &caller, Role::Upgrader);
+        let storage = env.storage().persistent();
+        assert!(!storage.has(&DataKey::Frontier), "already migrated");
+
+        // Levels never written are never read before they are, so the zero
+        // they start with in a fresh pool is as good as any value.
+        let depth = Self::get_depth(env.clone());
+        let mut frontier = VecFrontier::empty(&env, depth).0;
+        for level in 0..depth {
+            if let Some(node) = storage.get(&DataKey::CachedSubtree(level)) {
+                frontier.set(level, node);
+            }
+        }
+
+        // The old ring buffer filled its slots in order, so the roots present
+        // are exactly slots 0..n.
+        let mut roots: Vec<BytesN<32>> = Vec::new(&env);
+        for i in 0..ROOT_HISTORY_SIZE {
+            match storage.get(&DataKey::Root(i)) {
+                Some(root) => roots.push_back(root),
+                None => break,
+            }
+        }
+        assert!(!roots.is_empty(), "no root history to migrate");
+
+        let next_index: Val = env
+            .storage()
+            .instance()
+            .get(&DataKey::NextLeafIndex)
+            .unwrap();
+        if let Ok(legacy) = u32::try_from_val(&env, &next_index) {
+            let end = u64::from(legacy);
+            env.storage().instance().set(&DataKey::NextLeafIndex, &end);
+            if end > 0 {
+                let migration = LeafMigration { next: 0, end };
+                env.storage()
+                    .instance()
+                    .set(&DataKey::LeafMigration, &migration);
+            }
+        }
+
+        storage.set(&DataKey::Frontier, &frontier);
+        storage.set(&DataKey::RootHistory, &roots);
+        log!(&env, "Migrated {} roots", roots.len());
+    }
+
+    /// Move up to `count` leaves from `LegacyKey::Leaf(u32)` to `Leaf(u64)`,
+    /// in index order, and remove the legacy entries. Called repeatedly
+    /// after `migrate` until it returns 0, the number of leaves left.
+    pub fn migrate_leaves(env: Env, caller: Address, count: u32) -> u64 {
+        roles::require_role(&env, &caller, Role::Upgrader);
+        let mut migration: LeafMigration = env
+            .storage()
+            .instance()
+            .get(&DataKey::LeafMigration)
+            .expect("no leaves to migrate");
+
+        let storage = env.storage().persistent();
+        let stop = migration.end.min(migration.next + u64::from(count));
+        for i in migration.next..stop {
+            let legacy = LegacyKey::Leaf(i as u32);
+            let leaf: BytesN<32> = storage.get(&legacy).expect("legacy leaf not found");
+            storage.set(&DataKey::Leaf(i), &leaf);
+            storage.remove(&legacy);
+        }
+
+        migration.next = stop;
+        if stop == migration.end {
+            env.storage().instance().remove(&DataKey::LeafMigration);
+        } else {
+            env.storage()
+                .instance()
+                .set(&DataKey::LeafMigration, &migration);
+        }
+        migration.end - stop
+    }
+
+    // -----------------------------------------------------------------------
+    // VIEW FUNCTIONS
+    // -----------------------------------------------------------------------
+
+    /// Parse `public_inputs` as `layout` would on withdrawal, so a relayer
+    /// can check a proof's inputs before submitting it.
+    pub fn parse_public_inputs(
+        _env: Env,
+        layout: PublicInputLayout,
+        public_inputs: Vec<BytesN<32>>,
+    ) -> WithdrawPublicInputs {
+        WithdrawPublicInputs::parse(layout, &public_inputs)
+    }
+
+    pub fn get_latest_root(env: Env) -> BytesN<32> {
+        let idx: u32 = env
             .storage()
             .instance()
             .get(&DataKey::CurrentRootIndex)
             .unwrap();
-        let new_root_idx = (current_root_idx + 1) % ROOT_HISTORY_SIZE;
+        Self::root_history(&env).get(idx).unwrap()
+    }
 
+    pub fn get_next_index(env: Env) -> u64 {
         env.storage()
             .instance()
-            .set(&DataKey::CurrentRootIndex, &new_root_idx);
+            .get(&DataKey::NextLeafIndex)
+            .unwrap()
+    }
+
+    /// Amount every deposit into this pool pulls, or 0 if deposits are
+    /// funded separately and may be of any amount.
+    pub fn get_denomination(env: Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::Denomination)
+            .unwrap_or(0)
+    }
+
+    /// Tree depth; Merkle paths for this pool have this many siblings.
+    pub fn get_depth(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::Depth).unwrap()
+    }
+
+    /// Whether commitments are kept in storage (see `get_leaf`) rather than
+    /// only in events.
+    pub fn stores_leaves(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::StoreLeaves)
+            .unwrap_or(true)
+    }
+
+    /// The commitment at `index`. Panics on pools built with
+    /// `store_leaves = false`; read `NewCommitment` events instead.
+    pub fn get_leaf(env: Env, index: u64) -> BytesN<32> {
+        assert!(
+            Self::stores_leaves(env.clone()),
+            "leaves are only kept in events"
+        );
+        env.storage()
+            .persistent()
+            .get(&DataKey::Leaf(index))
+            .expect("leaf not found")
+    }
+
+    pub fn is_valid_root(env: Env, root: BytesN<32>) -> bool {
+        Self::is_known_root(&env, &root)
+    }
+
+    pub fn is_spent(env: Env, nullifier_hash: BytesN<32>) -> bool {
         env.storage()
             .persistent()
-            .set(&DataKey::Root(new_root_idx), &current_hash);
+            .get(&DataKey::Nullifier(nullifier_hash))
+            .unwrap_or(false)
+    }
+
+    pub fn get_admin(env: Env) -> AdminSet {
+        rotor_admin::get(&env)
+    }
+
+    pub fn get_pending_admin(env: Env) -> Option<AdminSet> {
+        rotor_admin::pending(&env)
+    }
+
+    pub fn get_action(env: Env, id: u64) -> Option<QueuedAction> {
+        env.storage().persistent().get(&DataKey::Action(id))
+    }
+
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        roles::has_role(&env, role, &account)
+    }
+
+    pub fn get_relayer(env: Env) -> Address {
         env.storage()
             .instance()
-            .set(&DataKey::NextLeafIndex, &(next_index + 1));
+            .get(&DataKey::Relayer)
+            .expect("relayer not set")
+    }
 
-        next_index
+    pub fn get_rate_limit(env: Env) -> Option<RateLimit> {
+        env.storage().instance().get(&DataKey::RateLimit)
     }
 
-    fn is_known_root(env: &Env, root: &BytesN<32>) -> bool {
-        let zero = BytesN::from_array(env, &[0u8; 32]);
-        if *root == zero {
-            return false;
+    /// Usage of the current window; a window that has rolled over reads as empty.
+    pub fn get_rate_window(env: Env) -> RateWindow {
+        Self::current_window(&env)
+    }
+
+    /// True if the admin paused withdrawals or the current window's cap is used up.
+    pub fn is_paused(env: Env) -> bool {
+        if Self::is_admin_paused(&env) {
+            return true;
+        }
+        match Self::get_rate_limit(env.clone()) {
+            Some(limit) if limit.max_window_amount > 0 => {
+                Self::current_window(&env).withdrawn >= limit.max_window_amount
+            }
+            _ => false,
         }
+    }
 
-        let current_root_idx: u32 = env
+    pub fn get_fee_bps(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::FeeBps).unwrap_or(0)
+    }
+
+    pub fn get_treasury(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Treasury)
+    }
+
+    /// Fees withheld from withdrawals and not yet sent to the treasury.
+    pub fn get_fees_collected(env: Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::FeesCollected)
+            .unwrap_or(0)
+    }
+
+    /// Fee on withdrawing `amount` at the current rate, rounded down.
+    pub fn fee_for(env: Env, amount: i128) -> i128 {
+        amount
+            .checked_mul(Self::get_fee_bps(env) as i128)
+            .expect("amount too large for fee")
+            / BPS
+    }
+
+    pub fn get_min_stake(env: Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::MinStake)
+            .unwrap_or(0)
+    }
+
+    pub fn get_stake(env: Env, relayer: Address) -> Option<RelayerStake> {
+        env.storage().persistent().get(&DataKey::Stake(relayer))
+    }
+
+    /// Relayer stakes held by the pool, not owed to any note.
+    pub fn get_total_staked(env: Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::TotalStaked)
+            .unwrap_or(0)
+    }
+
+    /// The payout a staked relayer submitted for `nullifier_hash`, while it
+    /// is still kept as evidence.
+    pub fn get_payout(env: Env, nullifier_hash: BytesN<32>) -> Option<PayoutRecord> {
+        env.storage()
+            .temporary()
+            .get(&DataKey::Payout(nullifier_hash))
+    }
+
+    /// A locked deposit that has not been released yet.
+    pub fn get_lock(env: Env, lock_id: u64) -> Option<NoteLock> {
+        env.storage().persistent().get(&DataKey::Lock(lock_id))
+    }
+
+    /// Whether `release_lock` may be called for this lock at the current
+    /// ledger. Released locks no longer exist, so this is `true` for them.
+    pub fn is_lock_expired(env: Env, lock_id: u64) -> bool {
+        match Self::get_lock(env.clone(), lock_id) {
+            Some(lock) => env.ledger().sequence() >= lock.unlock_ledger,
+            None => true,
+        }
+    }
+
+    pub fn current_ledger(env: Env) -> u32 {
+        env.ledger().sequence()
+    }
+
+    pub fn get_token(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Token)
+            .expect("token not set")
+    }
+
+    pub fn get_balance(env: Env) -> i128 {
+        let token_addr: Address = env
             .storage()
             .instance()
-            .get(&DataKey::CurrentRootIndex)
-            .unwrap();
+            .get(&DataKey::Token)
+            .expect("token not set");
+        let token_client = token::Client::new(&env, &token_addr);
+        token_client.balance(&env.current_contract_address())
+    }
 
-        let mut i = current_root_idx;
-        loop {
-            let stored: Option<BytesN<32>> = env.storage().persistent().get(&DataKey::Root(i));
-            if let Some(r) = stored {
-                if r == *root {
-                    return true;
-                }
+    // -----------------------------------------------------------------------
+    // INTERNAL: Nullifiers
+    // -----------------------------------------------------------------------
+
+    /// Mark a nullifier as spent, panicking if it already was or if the
+    /// note is still time-locked.
+    fn spend_nullifier(env: &Env, nullifier_hash: &BytesN<32>) {
+        let nullifier_key = DataKey::Nullifier(nullifier_hash.clone());
+        let already_spent: bool = env
+            .storage()
+            .persistent()
+            .get(&nullifier_key)
+            .unwrap_or(false);
+        assert!(!already_spent, "nullifier already spent");
+
+        env.storage().persistent().set(&nullifier_key, &true);
+
+        NewNullifier {
+            nullifier_hash: nullifier_hash.clone(),
+        }
+        .publish(env);
+    }
+
+    // -----------------------------------------------------------------------
+    // INTERNAL: Admin, relayers, rate limiting and fees
+    // -----------------------------------------------------------------------
+
+    /// The role that queues `action`, or `None` for the actions only the
+    /// admin set queues.
+    fn role_for(action: &Action) -> Option<Role> {
+        match action {
+            Action::SetRelayer(_) | Action::SetMinStake(_) => Some(Role::RelayerManager),
+            Action::SetFee(_) | Action::SetTreasury(_) => Some(Role::FeeManager),
+            Action::SetToken(_) | Action::Upgrade(_) => Some(Role::Upgrader),
+            Action::ProposeAdmin(_) | Action::GrantRole(..) | Action::RevokeRole(..) => None,
+        }
+    }
+
+    fn enqueue(env: &Env, action: Action, eta: u32) -> u64 {
+        assert!(
+            eta >= env.ledger().sequence().saturating_add(MIN_DELAY),
+            "eta is before the minimum delay"
+        );
+        match &action {
+            Action::SetFee(fee_bps) => assert!(*fee_bps <= MAX_FEE_BPS, "fee exceeds maximum"),
+            Action::SetMinStake(min_stake) => {
+                assert!(*min_stake >= 0, "minimum stake must not be negative")
+            }
+            Action::ProposeAdmin(admin) => admin.validate(),
+            _ => {}
+        }
+
+        let id: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::NextActionId)
+            .unwrap_or(0);
+        env.storage()
+            .instance()
+            .set(&DataKey::NextActionId, &(id + 1));
+        env.storage().persistent().set(
+            &DataKey::Action(id),
+            &QueuedAction {
+                action: action.clone(),
+                eta,
+            },
+        );
+
+        ActionQueued { id, action, eta }.publish(env);
+        id
+    }
+
+    fn dequeue(env: &Env, id: u64) {
+        env.storage().persistent().remove(&DataKey::Action(id));
+    }
+
+    fn apply(env: &Env, id: u64, queued: QueuedAction) {
+        let now = env.ledger().sequence();
+        assert!(now >= queued.eta, "action is not ready");
+        assert!(
+            now <= queued.eta.saturating_add(GRACE_PERIOD),
+            "action has expired"
+        );
+
+        Self::dequeue(env, id);
+        ActionExecuted { id }.publish(env);
+
+        match queued.action {
+            Action::SetRelayer(relayer) => {
+                env.storage().instance().set(&DataKey::Relayer, &relayer);
+                RelayerUpdated { relayer }.publish(env);
+            }
+            Action::SetToken(token) => {
+                env.storage().instance().set(&DataKey::Token, &token);
+                TokenUpdated { token }.publish(env);
+            }
+            Action::SetFee(fee_bps) => {
+                env.storage().instance().set(&DataKey::FeeBps, &fee_bps);
+                FeeUpdated { fee_bps }.publish(env);
             }
-            if i == 0 {
-                i = ROOT_HISTORY_SIZE;
+            Action::Upgrade(new_wasm_hash) => {
+                env.deployer().update_current_contract_wasm(new_wasm_hash);
             }
-            i -= 1;
-            if i == current_root_idx {
-                break;
+            Action::SetTreasury(treasury) => {
+                env.storage().instance().set(&DataKey::Treasury, &treasury);
+                TreasuryUpdated { treasury }.publish(env);
+            }
+            Action::SetMinStake(min_stake) => {
+                env.storage().instance().set(&DataKey::MinStake, &min_stake);
+                MinStakeUpdated { min_stake }.publish(env);
+            }
+            Action::ProposeAdmin(admin) => rotor_admin::propose_unchecked(env, &admin),
+            Action::GrantRole(role, account) => {
+                assert!(
+                    !roles::has_role(env, role, &account),
+                    "role already granted"
+                );
+                roles::grant_unchecked(env, role, &account);
+            }
+            Action::RevokeRole(role, account) => {
+                assert!(roles::has_role(env, role, &account), "role not granted");
+                roles::revoke_unchecked(env, role, &account);
+            }
+        }
+    }
+
+    /// Require `relayer`'s auth and that it is the configured relayer or a
+    /// bonded one staking at least the minimum. Returns whether it is staked.
+    fn require_relayer(env: &Env, relayer: &Address) -> bool {
+        relayer.require_auth();
+
+        if *relayer == Self::get_relayer(env.clone()) {
+            return false;
+        }
+
+        let stake = Self::get_stake(env.clone(), relayer.clone()).expect("unauthorized relayer");
+        assert!(stake.unbond_ledger == 0, "relayer is unbonding");
+        assert!(
+            stake.amount >= Self::get_min_stake(env.clone()),
+            "stake below minimum"
+        );
+        true
+    }
+
+    fn record_payout(
+        env: &Env,
+        relayer: &Address,
+        nullifier_hash: &BytesN<32>,
+        recipient: &BytesN<32>,
+        relayer_fee: i128,
+    ) {
+        let key = DataKey::Payout(nullifier_hash.clone());
+        env.storage().temporary().set(
+            &key,
+            &PayoutRecord {
+                relayer: relayer.clone(),
+                recipient: recipient.clone(),
+                relayer_fee,
+            },
+        );
+        env.storage()
+            .temporary()
+            .extend_ttl(&key, UNBONDING_DELAY, UNBONDING_DELAY);
+    }
+
+    fn add_total_staked(env: &Env, delta: i128) {
+        let total = Self::get_total_staked(env.clone());
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalStaked, &(total + delta));
+    }
+
+    fn is_admin_paused(env: &Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::Paused)
+            .unwrap_or(false)
+    }
+
+    /// The stored window, or a fresh one if it has expired (or never existed).
+    fn current_window(env: &Env) -> RateWindow {
+        let now = env.ledger().sequence();
+        let fresh = RateWindow {
+            start_ledger: now,
+            withdrawn: 0,
+        };
+        let limit: Option<RateLimit> = env.storage().instance().get(&DataKey::RateLimit);
+        let window: Option<RateWindow> = env.storage().instance().get(&DataKey::RateWindow);
+        match (limit, window) {
+            (Some(limit), Some(window))
+                if now < window.start_ledger.saturating_add(limit.window_ledgers) =>
+            {
+                window
             }
+            _ => fresh,
         }
-        false
     }
 
-    fn hash_pair(env: &Env, left: &BytesN<32>, right: &BytesN<32>) -> BytesN<32> {
-        let left_bytes = soroban_sdk::Bytes::from_slice(env, &left.to_array());
-        let right_bytes = soroban_sdk::Bytes::from_slice(env, &right.to_array());
+    /// Check `amount` against the pause flag and rate limits, and count it
+    /// towards the current window.
+    fn record_withdrawal(env: &Env, amount: i128) {
+        assert!(!Self::is_admin_paused(env), "withdrawals are paused");
+
+        let limit: RateLimit = match env.storage().instance().get(&DataKey::RateLimit) {
+            Some(limit) => limit,
+            None => return,
+        };
+        if limit.max_withdrawal > 0 {
+            assert!(
+                amount <= limit.max_withdrawal,
+                "withdrawal exceeds per-withdrawal maximum"
+            );
+        }
+
+        let mut window = Self::current_window(env);
+        window.withdrawn += amount;
+        if limit.max_window_amount > 0 {
+            assert!(
+                window.withdrawn <= limit.max_window_amount,
+                "withdrawal cap reached for this window"
+            );
+        }
+        env.storage().instance().set(&DataKey::RateWindow, &window);
+    }
 
-        let left_u256 = U256::from_be_bytes(env, &left_bytes);
-        let right_u256 = U256::from_be_bytes(env, &right_bytes);
+    /// The payout must go to the recipient the proof was made for.
+    fn check_recipient(recipient: &MuxedAddress, field: &BytesN<32>) {
+        assert!(
+            recipient_field(recipient) == *field,
+            "recipient does not match proof"
+        );
+    }
+
+    /// The relayer takes at most the fee the prover agreed to.
+    fn check_relayer_fee(inputs: &WithdrawPublicInputs, relayer_fee: i128) {
+        assert!(
+            relayer_fee <= inputs.max_relayer_fee,
+            "relayer fee exceeds proof"
+        );
+    }
 
-        let inputs = vec![env, left_u256, right_u256];
-        let result: U256 = poseidon2_hash::<3, BnScalar>(env, &inputs);
+    /// Pay a withdrawn `amount` to `recipient`, keeping the protocol fee and
+    /// paying `relayer_fee` to `relayer`. Returns what `recipient` received.
+    fn pay_out(
+        env: &Env,
+        recipient: &MuxedAddress,
+        amount: i128,
+        relayer: &Address,
+        relayer_fee: i128,
+    ) -> i128 {
+        let fee = Self::fee_for(env.clone(), amount);
+        assert!(
+            relayer_fee >= 0 && relayer_fee <= amount - fee,
+            "relayer fee exceeds payout"
+        );
+        if fee > 0 {
+            let collected = Self::get_fees_collected(env.clone());
+            env.storage()
+                .instance()
+                .set(&DataKey::FeesCollected, &(collected + fee));
+        }
 
-        let result_bytes = result.to_be_bytes();
-        let mut arr = [0u8; 32];
-        for i in 0..32 {
-            arr[i] = result_bytes.get(i as u32).unwrap();
+        let token_client = token::Client::new(env, &Self::get_token(env.clone()));
+        if relayer_fee > 0 {
+            token_client.transfer(&env.current_contract_address(), relayer, &relayer_fee);
         }
-        BytesN::from_array(env, &arr)
+        let paid = amount - fee - relayer_fee;
+        token_client.transfer(&env.current_contract_address(), recipient, &paid);
+        paid
+    }
+
+    // -----------------------------------------------------------------------
+    // INTERNAL: Merkle tree operations
+    // -----------------------------------------------------------------------
+
+    fn insert_leaf(env: &Env, leaf: BytesN<32>) -> u64 {
+        let next_index: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::NextLeafIndex)
+            .unwrap();
+
+        let depth = Self::get_depth(env.clone());
+        let max_leaves = 1u64 << depth;
+        assert!(next_index < max_leaves, "merkle tree is full");
+
+        if Self::stores_leaves(env.clone()) {
+            env.storage()
+                .persistent()
+                .set(&DataKey::Leaf(next_index), &leaf);
+        }
+
+        NewCommitment {
+            commitment: leaf.clone(),
+            leaf_index: next_index,
+        }
+        .publish(env);
+
+        // The frontier and root history are one entry each: read once,
+        // update in memory, write once.
+        let mut frontier = VecFrontier(
+            env.storage()
+                .persistent()
+                .get(&DataKey::Frontier)
+                .expect("storage not migrated"),
+        );
+        let root = rotor_merkle::insert(
+            &SorobanHasher::new(env),
+            &mut frontier,
+            depth,
+            next_index,
+            leaf.to_array(),
+        );
+        env.storage()
+            .persistent()
+            .set(&DataKey::Frontier, &frontier.0);
+        let current_hash = BytesN::from_array(env, &root);
+
+        let current_root_idx: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::CurrentRootIndex)
+            .unwrap();
+        let new_root_idx = (current_root_idx + 1) % ROOT_HISTORY_SIZE;
+
+        let mut roots = Self::root_history(env);
+        if new_root_idx == roots.len() {
+            roots.push_back(current_hash);
+        } else {
+            roots.set(new_root_idx, current_hash);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::CurrentRootIndex, &new_root_idx);
+        env.storage()
+            .persistent()
+            .set(&DataKey::RootHistory, &roots);
+        env.storage()
+            .instance()
+            .set(&DataKey::NextLeafIndex, &(next_index + 1));
+
+        next_index
+    }
+
+    fn is_known_root(env: &Env, root: &BytesN<32>) -> bool {
+        let zero = BytesN::from_array(env, &[0u8; 32]);
+        if *root == zero {
+            return false;
+        }
+
+        Self::root_history(env).contains(root)
+    }
+
+    fn root_history(env: &Env) -> Vec<BytesN<32>> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::RootHistory)
+            .expect("storage not migrated")
     }
 }
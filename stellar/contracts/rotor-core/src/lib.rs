@@ -1,5 +1,5 @@
 #![no_std]
 
-mod contract;
+pub mod contract;
 #[cfg(test)]
 mod test;
\ No newline at end of file
@@ -0,0 +1,3680 @@
+#![cfg(test)]
+extern crate alloc;
+extern crate std;
+
+use crate::contract::{
+    Action, ActionCancelled, ActionExecuted, ActionQueued, DataKey, FeeUpdated, FeesWithdrawn,
+    NewCommitment, NoteLock, PayoutRecord, PublicInputLayout, QueuedAction, RateLimit, RateWindow,
+    RelayerSlashed, RelayerStake, RelayerUpdated, RotorCore, RotorCoreClient, TreasuryUpdated,
+    WithdrawPublicInputs, WithdrawTarget, WithdrawalIntent, GRACE_PERIOD, MAX_FEE_BPS, MIN_DELAY,
+    UNBONDING_DELAY,
+};
+use ed25519_dalek::{Signer, SigningKey};
+use incremental_merkle_tree::merkle_tree::{IncrementalMerkleTree, IncrementalMerkleTreeClient};
+use proptest::prelude::*;
+use rotor_admin::{
+    roles::{Role, RoleGranted, RoleKey},
+    AdminKey, AdminSet,
+};
+use rotor_merkle::testutils::{cost, Cost};
+use rotor_note::{call_field, recipient_field, Note};
+use soroban_poseidon::poseidon2_hash;
+use soroban_sdk::{
+    contract, contractimpl, contracttype,
+    crypto::BnScalar,
+    symbol_short,
+    testutils::{
+        Address as _, EnvTestConfig, Events as _, Ledger, MockAuth, MockAuthInvoke,
+        MuxedAddress as _,
+    },
+    token::{StellarAssetClient, TokenClient},
+    vec,
+    xdr::ToXdr,
+    Address, Bytes, BytesN, Env, Event as _, IntoVal, MuxedAddress, Vec, U256,
+};
+
+const PROVER_TOML: &str = include_str!("../../../../circuit/Prover.toml");
+
+struct Setup<'a> {
+    /// The pool's single admin, holding every role.
+    admin: Address,
+    relayer: Address,
+    client: RotorCoreClient<'a>,
+    token: TokenClient<'a>,
+    asset: StellarAssetClient<'a>,
+    /// The asset's issuer, the one account that can hold it without a
+    /// trustline.
+    issuer: Address,
+}
+
+/// Depth of the circuits' Merkle paths, and of the pools the tests use.
+const DEPTH: u32 = 20;
+
+/// Register a SAC and a pool using it, and fund the pool with `funding` stroops.
+fn setup(env: &Env, funding: i128) -> Setup<'_> {
+    setup_with(env, funding, DEPTH, 0, true)
+}
+
+fn setup_with(
+    env: &Env,
+    funding: i128,
+    depth: u32,
+    denomination: i128,
+    store_leaves: bool,
+) -> Setup<'_> {
+    env.mock_all_auths();
+
+    let admin = Address::generate(env);
+    let relayer = Address::generate(env);
+    let sac = env.register_stellar_asset_contract_v2(Address::generate(env));
+    let contract_id = env.register(
+        RotorCore,
+        (
+            &admin,
+            &relayer,
+            &sac.address(),
+            depth,
+            denomination,
+            store_leaves,
+        ),
+    );
+
+    let asset = StellarAssetClient::new(env, &sac.address());
+    asset.mint(&contract_id, &funding);
+
+    Setup {
+        admin,
+        relayer,
+        client: RotorCoreClient::new(env, &contract_id),
+        token: TokenClient::new(env, &sac.address()),
+        asset,
+        issuer: sac.issuer().address(),
+    }
+}
+
+/// Encode a stroop amount as the 32-byte big-endian field element from the proof.
+fn amount_field(env: &Env, amount: i128) -> BytesN<32> {
+    let mut arr = [0u8; 32];
+    arr[16..].copy_from_slice(&amount.to_be_bytes());
+    BytesN::from_array(env, &arr)
+}
+
+/// `withdraw`'s public inputs `[root, nullifier_hash, recipient, amount]`
+/// for a proof against the pool's latest root.
+fn withdraw_inputs(
+    client: &RotorCoreClient,
+    nullifier_hash: &BytesN<32>,
+    recipient: impl Into<MuxedAddress>,
+    amount: &BytesN<32>,
+) -> Vec<BytesN<32>> {
+    vec![
+        &client.env,
+        client.get_latest_root(),
+        nullifier_hash.clone(),
+        recipient_field(&recipient.into()),
+        amount.clone(),
+    ]
+}
+
+/// `withdraw_partial`'s public inputs
+/// `[root, nullifier_hash, recipient, withdraw_amount, change_commitment]`.
+fn partial_inputs(
+    root: &BytesN<32>,
+    nullifier_hash: &BytesN<32>,
+    recipient: impl Into<MuxedAddress>,
+    withdraw_amount: &BytesN<32>,
+    change_commitment: &BytesN<32>,
+) -> Vec<BytesN<32>> {
+    vec![
+        root.env(),
+        root.clone(),
+        nullifier_hash.clone(),
+        recipient_field(&recipient.into()),
+        withdraw_amount.clone(),
+        change_commitment.clone(),
+    ]
+}
+
+/// `inputs` in the V2 layout of their kind: followed by the relayer fee the
+/// proof commits to.
+fn with_relayer_fee(mut inputs: Vec<BytesN<32>>, max_relayer_fee: i128) -> Vec<BytesN<32>> {
+    let fee = amount_field(inputs.env(), max_relayer_fee);
+    inputs.push_back(fee);
+    inputs
+}
+
+fn bytes(env: &Env, b: u8) -> BytesN<32> {
+    BytesN::from_array(env, &[b; 32])
+}
+
+/// Read a single field element from the circuit's Prover.toml.
+fn prover_field(env: &Env, key: &str) -> BytesN<32> {
+    let value = PROVER_TOML
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().trim_matches('"').trim_start_matches("0x"))
+        .unwrap_or_else(|| panic!("{key} missing from Prover.toml"));
+    let mut arr = [0u8; 32];
+    for (i, byte) in arr.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    BytesN::from_array(env, &arr)
+}
+
+#[test]
+fn test_constructor_initial_state() {
+    let env = Env::default();
+    let Setup { client, token, .. } = setup(&env, 10_000_000);
+
+    assert_eq!(client.get_next_index(), 0);
+    assert_eq!(client.get_depth(), 20);
+    assert_eq!(client.get_token(), token.address);
+    assert_eq!(client.get_balance(), 10_000_000);
+    assert!(client.is_valid_root(&client.get_latest_root()));
+}
+
+#[test]
+#[should_panic(expected = "depth must be 1..=32")]
+fn test_constructor_rejects_depth_over_32() {
+    let env = Env::default();
+    setup_with(&env, 0, 33, 0, true);
+}
+
+#[test]
+#[should_panic(expected = "depth must be 1..=32")]
+fn test_constructor_rejects_depth_0() {
+    let env = Env::default();
+    setup_with(&env, 0, 0, 0, true);
+}
+
+#[test]
+#[should_panic(expected = "denomination must not be negative")]
+fn test_constructor_rejects_negative_denomination() {
+    let env = Env::default();
+    setup_with(&env, 0, DEPTH, -1, true);
+}
+
+#[test]
+fn test_denominated_deposit_pulls_denomination() {
+    let env = Env::default();
+    let Setup {
+        client,
+        token,
+        asset,
+        ..
+    } = setup_with(&env, 0, DEPTH, 10_000_000, true);
+    assert_eq!(client.get_denomination(), 10_000_000);
+    assert_eq!(setup(&env, 0).client.get_denomination(), 0);
+
+    let depositor = Address::generate(&env);
+    asset.mint(&depositor, &25_000_000);
+    assert_eq!(client.deposit(&depositor, &bytes(&env, 1)), 0);
+    assert_eq!(client.deposit(&depositor, &bytes(&env, 2)), 1);
+    assert_eq!(token.balance(&client.address), 20_000_000);
+    assert_eq!(token.balance(&depositor), 5_000_000);
+
+    // Too little left for another note: nothing is inserted.
+    assert!(client.try_deposit(&depositor, &bytes(&env, 3)).is_err());
+    assert_eq!(client.get_next_index(), 2);
+}
+
+#[test]
+fn test_denominated_deposit_locked_takes_only_denomination() {
+    let env = Env::default();
+    let s = setup_with(&env, 0, DEPTH, 10_000_000, true);
+    let depositor = Address::generate(&env);
+    s.asset.mint(&depositor, &5_000_000);
+
+    assert!(s
+        .client
+        .try_deposit_locked(&depositor, &bytes(&env, 1), &5_000_000, &100)
+        .is_err());
+    lock_note(&env, &s, 1, 10_000_000, 100);
+    assert_eq!(s.token.balance(&s.client.address), 10_000_000);
+}
+
+/// A depth-32 pool agrees with the reference tree at that depth, and its last
+/// slot, 2^32 - 1, is still insertable.
+#[test]
+fn test_depth_32_pool() {
+    let env = Env::default();
+    let client = setup_with(&env, 0, 32, 0, true).client;
+    let depositor = Address::generate(&env);
+    assert_eq!(client.get_depth(), 32);
+
+    let admin = Address::generate(&env);
+    let tree_id = env.register(IncrementalMerkleTree, (&admin, 32u32));
+    let tree = IncrementalMerkleTreeClient::new(&env, &tree_id);
+    assert_eq!(client.get_latest_root(), tree.get_latest_root());
+    for i in 1..=3 {
+        let leaf = bytes(&env, i);
+        assert_eq!(
+            client.deposit(&depositor, &leaf),
+            tree.insert(&admin, &leaf)
+        );
+        assert_eq!(client.get_latest_root(), tree.get_latest_root());
+    }
+
+    // Index 2^32 - 1 is a right child at every level, so its root folds the
+    // cached left siblings onto the leaf.
+    let last = (1u64 << 32) - 1;
+    let cached: alloc::vec::Vec<BytesN<32>> =
+        (0..32u8).map(|level| bytes(&env, level + 1)).collect();
+    env.as_contract(&client.address, || {
+        let storage = env.storage();
+        storage.instance().set(&DataKey::NextLeafIndex, &last);
+        storage
+            .persistent()
+            .set(&DataKey::Frontier, &Vec::from_slice(&env, &cached));
+    });
+
+    let leaf = bytes(&env, 0xee);
+    assert_eq!(client.deposit(&depositor, &leaf), last);
+    let mut expected = leaf;
+    for node in &cached {
+        expected = tree.hash_pair(node, &expected);
+    }
+    assert_eq!(client.get_latest_root(), expected);
+    assert_eq!(client.get_next_index(), 1u64 << 32);
+    assert!(client.try_deposit(&depositor, &bytes(&env, 0xef)).is_err());
+}
+
+#[test]
+fn test_deposit_inserts_commitments_in_order() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+    let empty_root = client.get_latest_root();
+
+    assert_eq!(client.deposit(&depositor, &bytes(&env, 1)), 0);
+    let first_root = client.get_latest_root();
+    assert_eq!(client.deposit(&depositor, &bytes(&env, 2)), 1);
+    let second_root = client.get_latest_root();
+
+    assert_eq!(client.get_next_index(), 2);
+    assert_ne!(first_root, empty_root);
+    assert_ne!(second_root, first_root);
+}
+
+#[test]
+fn test_get_leaf() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    client.deposit(&depositor, &bytes(&env, 1));
+    client.deposit(&depositor, &bytes(&env, 2));
+
+    assert!(client.stores_leaves());
+    assert_eq!(client.get_leaf(&1), bytes(&env, 2));
+    assert!(client.try_get_leaf(&2).is_err());
+}
+
+#[test]
+fn test_event_only_pool_keeps_leaves_in_events() {
+    let env = Env::default();
+    let client = setup_with(&env, 0, DEPTH, 0, false).client;
+    let control = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    for i in 0..3u64 {
+        let commitment = bytes(&env, i as u8 + 1);
+        assert_eq!(client.deposit(&depositor, &commitment), i);
+        assert_eq!(
+            env.events().all().filter_by_contract(&client.address),
+            [NewCommitment {
+                commitment: commitment.clone(),
+                leaf_index: i,
+            }
+            .to_xdr(&env, &client.address)]
+        );
+
+        control.deposit(&depositor, &commitment);
+        assert_eq!(client.get_latest_root(), control.get_latest_root());
+    }
+
+    assert!(!client.stores_leaves());
+    assert!(client.try_get_leaf(&0).is_err());
+    env.as_contract(&client.address, || {
+        assert!(!env.storage().persistent().has(&DataKey::Leaf(0)));
+    });
+}
+
+#[test]
+fn test_deposit_root_depends_on_commitment() {
+    let env = Env::default();
+    let a = setup(&env, 0).client;
+    let b = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    a.deposit(&depositor, &bytes(&env, 1));
+    b.deposit(&depositor, &bytes(&env, 1));
+    assert_eq!(a.get_latest_root(), b.get_latest_root());
+
+    a.deposit(&depositor, &bytes(&env, 2));
+    b.deposit(&depositor, &bytes(&env, 3));
+    assert_ne!(a.get_latest_root(), b.get_latest_root());
+}
+
+#[test]
+fn test_deposit_root_matches_circuit() {
+    let env = Env::default();
+    let Setup { client, .. } = setup(&env, 0);
+    let depositor = Address::generate(&env);
+
+    // commitment = Poseidon2(nullifier, secret, amount), as in circuit/src/main.nr
+    let mut inputs = vec![&env];
+    for key in ["nullifier", "secret", "amount"] {
+        let field = prover_field(&env, key);
+        inputs.push_back(U256::from_be_bytes(
+            &env,
+            &Bytes::from_array(&env, &field.to_array()),
+        ));
+    }
+    let commitment: BytesN<32> = poseidon2_hash::<4, BnScalar>(&env, &inputs)
+        .to_be_bytes()
+        .try_into()
+        .unwrap();
+
+    client.deposit(&depositor, &commitment);
+    assert_eq!(client.get_latest_root(), prover_field(&env, "root"));
+}
+
+/// Property cases run many times; skip writing a ledger snapshot for each one.
+fn prop_env() -> Env {
+    Env::new_with_config(EnvTestConfig {
+        capture_snapshot_at_drop: false,
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(8))]
+
+    #[test]
+    fn prop_roots_match_incremental_merkle_tree(
+        raw in proptest::collection::vec(any::<[u8; 32]>(), 1..=8),
+    ) {
+        let env = prop_env();
+        let client = setup(&env, 0).client;
+        let depositor = Address::generate(&env);
+
+        let admin = Address::generate(&env);
+        let tree_id = env.register(IncrementalMerkleTree, (&admin, 20u32));
+        let tree = IncrementalMerkleTreeClient::new(&env, &tree_id);
+
+        for mut leaf in raw {
+            leaf[0] &= 0x1f; // keep below the BN254 modulus
+            let leaf = BytesN::from_array(&env, &leaf);
+            prop_assert_eq!(client.deposit(&depositor, &leaf), tree.insert(&admin, &leaf));
+            prop_assert_eq!(client.get_latest_root(), tree.get_latest_root());
+        }
+    }
+}
+
+#[test]
+fn test_deposit_at_capacity() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    // Fast-forward to the last free slot. Index 2^20 - 1 is a right child at
+    // every level, so each level needs a cached left sibling.
+    let last = (1u64 << 20) - 1;
+    env.as_contract(&client.address, || {
+        let storage = env.storage();
+        storage.instance().set(&DataKey::NextLeafIndex, &last);
+        for level in 0..20 {
+            storage
+                .persistent()
+                .set(&DataKey::CachedSubtree(level), &bytes(&env, level as u8));
+        }
+    });
+
+    assert_eq!(client.deposit(&depositor, &bytes(&env, 0xee)), last);
+    assert_eq!(client.get_next_index(), 1 << 20);
+    assert!(client.try_deposit(&depositor, &bytes(&env, 0xef)).is_err());
+}
+
+#[test]
+fn test_deposit_requires_depositor_auth() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    env.set_auths(&[]);
+    assert!(client.try_deposit(&depositor, &bytes(&env, 1)).is_err());
+    assert_eq!(client.get_next_index(), 0);
+}
+
+#[test]
+fn test_withdraw_transfers_to_recipient() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let depositor = Address::generate(&env);
+    let recipient = Address::generate(&env);
+
+    client.deposit(&depositor, &bytes(&env, 1));
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    assert_eq!(token.balance(&recipient), 10_000_000);
+    assert_eq!(client.get_balance(), 0);
+    assert!(client.is_spent(&bytes(&env, 0xa1)));
+    assert!(!client.is_spent(&bytes(&env, 0xa2)));
+}
+
+/// A token that only remembers where its last `transfer` went.
+#[contract]
+struct RecordingToken;
+
+#[contractimpl]
+impl RecordingToken {
+    pub fn transfer(env: Env, _from: Address, to: MuxedAddress, amount: i128) {
+        env.storage()
+            .instance()
+            .set(&symbol_short!("last"), &(to.address(), to.id(), amount));
+    }
+}
+
+#[test]
+fn test_withdraw_to_muxed_account_keeps_id() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let relayer = Address::generate(&env);
+    let token = env.register(RecordingToken, ());
+    let client = RotorCoreClient::new(
+        &env,
+        &env.register(
+            RotorCore,
+            (
+                &Address::generate(&env),
+                &relayer,
+                &token,
+                DEPTH,
+                0i128,
+                true,
+            ),
+        ),
+    );
+    let recipient = MuxedAddress::new(MuxedAddress::generate(&env), 42);
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    let last: (Address, Option<u64>, i128) = env.as_contract(&token, || {
+        env.storage()
+            .instance()
+            .get(&symbol_short!("last"))
+            .unwrap()
+    });
+    assert_eq!(last, (recipient.address(), Some(42), 10_000_000));
+}
+
+/// A protocol a note can be withdrawn into. Its callback records its balance
+/// of `token`, the amount it was told of and the call data; empty call data
+/// is refused.
+#[contract]
+struct MockTarget;
+
+#[contractimpl]
+impl WithdrawTarget for MockTarget {
+    fn on_rotor_withdraw(env: Env, token: Address, amount: i128, data: Bytes) {
+        assert!(!data.is_empty(), "empty data refused");
+        let balance = TokenClient::new(&env, &token).balance(&env.current_contract_address());
+        env.storage()
+            .instance()
+            .set(&symbol_short!("deposit"), &(balance, amount, data));
+    }
+}
+
+/// `withdraw_and_call`'s public inputs: `withdraw_inputs` with the call bound
+/// in place of the recipient.
+fn call_inputs(
+    client: &RotorCoreClient,
+    nullifier_hash: &BytesN<32>,
+    target: &Address,
+    data: &Bytes,
+    amount: &BytesN<32>,
+) -> Vec<BytesN<32>> {
+    let mut inputs = withdraw_inputs(client, nullifier_hash, target.clone(), amount);
+    inputs.set(2, call_field(target, data));
+    inputs
+}
+
+#[test]
+fn test_withdraw_and_call() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let target = env.register(MockTarget, ());
+    let data = Bytes::from_array(&env, &[7]);
+    let nullifier_hash = bytes(&env, 0xa1);
+    let inputs = call_inputs(
+        &client,
+        &nullifier_hash,
+        &target,
+        &data,
+        &amount_field(&env, 10_000_000),
+    );
+
+    client.withdraw_and_call(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &inputs,
+        &target,
+        &data,
+        &0,
+    );
+    let deposit: (i128, i128, Bytes) = env.as_contract(&target, || {
+        env.storage()
+            .instance()
+            .get(&symbol_short!("deposit"))
+            .unwrap()
+    });
+    assert_eq!(deposit, (10_000_000, 10_000_000, data));
+    assert_eq!(token.balance(&client.address), 0);
+    assert!(client.is_spent(&nullifier_hash));
+}
+
+#[test]
+fn test_withdraw_and_call_rejects_other_call() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let target = env.register(MockTarget, ());
+    let data = Bytes::from_array(&env, &[7]);
+    let amount = amount_field(&env, 10_000_000);
+    let inputs = call_inputs(&client, &bytes(&env, 0xa1), &target, &data, &amount);
+
+    let other_target = env.register(MockTarget, ());
+    let other_data = Bytes::from_array(&env, &[8]);
+    for (target, data) in [(&other_target, &data), (&target, &other_data)] {
+        assert!(client
+            .try_withdraw_and_call(
+                &relayer,
+                &PublicInputLayout::WithdrawV1,
+                &inputs,
+                target,
+                data,
+                &0
+            )
+            .is_err());
+    }
+
+    // A call proof is not a payment to the target, nor the reverse.
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &target,
+            &0
+        )
+        .is_err());
+    let plain = withdraw_inputs(&client, &bytes(&env, 0xa1), target.clone(), &amount);
+    assert!(client
+        .try_withdraw_and_call(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &plain,
+            &target,
+            &data,
+            &0
+        )
+        .is_err());
+
+    assert!(client
+        .try_withdraw_and_call(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &target,
+            &data,
+            &0
+        )
+        .is_ok());
+}
+
+#[test]
+fn test_failed_call_reverts_withdrawal() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let target = env.register(MockTarget, ());
+    let data = Bytes::new(&env);
+    let nullifier_hash = bytes(&env, 0xa1);
+    let inputs = call_inputs(
+        &client,
+        &nullifier_hash,
+        &target,
+        &data,
+        &amount_field(&env, 10_000_000),
+    );
+
+    assert!(client
+        .try_withdraw_and_call(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &target,
+            &data,
+            &0
+        )
+        .is_err());
+    assert!(!client.is_spent(&nullifier_hash));
+    assert_eq!(token.balance(&client.address), 10_000_000);
+    assert_eq!(token.balance(&target), 0);
+}
+
+/// A proof bound to the token as its target would let the relayer move the
+/// pool's funds through a token call; it is refused outright.
+#[test]
+#[should_panic(expected = "target must not be the token")]
+fn test_withdraw_and_call_rejects_token_target() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let data = Bytes::from_array(&env, &[7]);
+    let inputs = call_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        &token.address,
+        &data,
+        &amount_field(&env, 10_000_000),
+    );
+
+    client.withdraw_and_call(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &inputs,
+        &token.address,
+        &data,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "target must not be the pool")]
+fn test_withdraw_and_call_rejects_pool_target() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let data = Bytes::from_array(&env, &[7]);
+    let inputs = call_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        &client.address,
+        &data,
+        &amount_field(&env, 10_000_000),
+    );
+
+    client.withdraw_and_call(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &inputs,
+        &client.address,
+        &data,
+        &0,
+    );
+}
+
+#[test]
+fn test_note_round_trips_through_contract() {
+    let env = Env::default();
+    env.cost_estimate().budget().reset_unlimited();
+    env.ledger()
+        .set_network_id(rotor_note::network_id("Test SDF Network ; September 2015"));
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    let minted = Note::new(
+        env.ledger().network_id().to_array(),
+        rotor_note::contract_id(&client.address).unwrap(),
+        10_000_000,
+        prover_field(&env, "nullifier").to_array(),
+        prover_field(&env, "secret").to_array(),
+    )
+    .unwrap();
+    let note = Note::decode(minted.encode().as_str()).unwrap();
+    assert!(note.is_for(&env, &client.address));
+
+    // The host commitment is what the client deposits and the circuit proves.
+    let commitment = note.commitment_on(&env);
+    assert_eq!(commitment.to_array(), note.commitment());
+    client.deposit(&Address::generate(&env), &commitment);
+    assert_eq!(client.get_latest_root(), prover_field(&env, "root"));
+
+    let amount = BytesN::from_array(&env, &note.amount_field());
+    assert_eq!(RotorCore::field_to_amount(&amount), note.denomination);
+    // The fixture's proof lets the relayer take up to its relayer_fee.
+    let mut inputs = withdraw_inputs(&client, &note.nullifier_hash_on(&env), &recipient, &amount);
+    let relayer_fee = prover_field(&env, "relayer_fee");
+    inputs.push_back(relayer_fee.clone());
+    let relayer_fee = RotorCore::field_to_amount(&relayer_fee);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &inputs,
+        &recipient,
+        &relayer_fee,
+    );
+    assert_eq!(token.balance(&recipient), note.denomination - relayer_fee);
+    assert_eq!(token.balance(&relayer), relayer_fee);
+}
+
+#[test]
+#[should_panic(expected = "nullifier already spent")]
+fn test_withdraw_rejects_double_spend() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+fn test_withdraw_rejects_other_recipient() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = withdraw_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        &recipient,
+        &amount_field(&env, 10_000_000),
+    );
+
+    // A relayer cannot redirect the payout, not even to another muxed id of
+    // the same account.
+    let thief = Address::generate(&env);
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &thief,
+            &0
+        )
+        .is_err());
+    let muxed = MuxedAddress::generate(&env);
+    let muxed_inputs = withdraw_inputs(
+        &client,
+        &bytes(&env, 0xa2),
+        &muxed,
+        &amount_field(&env, 10_000_000),
+    );
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &muxed_inputs,
+            MuxedAddress::new(muxed.clone(), muxed.id().unwrap() + 1),
+            &0
+        )
+        .is_err());
+    assert!(!client.is_spent(&bytes(&env, 0xa1)));
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &inputs,
+        &recipient,
+        &0,
+    );
+    assert_eq!(token.balance(&recipient), 10_000_000);
+}
+
+#[test]
+fn test_withdraw_checks_public_inputs() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = withdraw_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        &recipient,
+        &amount_field(&env, 10_000_000),
+    );
+
+    let mut unknown_root = inputs.clone();
+    unknown_root.set(0, bytes(&env, 9));
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &unknown_root,
+            &recipient,
+            &0
+        )
+        .is_err());
+
+    let mut short = inputs.clone();
+    short.pop_back();
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &short,
+            &recipient,
+            &0
+        )
+        .is_err());
+
+    let mut long = with_relayer_fee(inputs.clone(), 0);
+    long.push_back(bytes(&env, 2));
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV2,
+            &long,
+            &recipient,
+            &0
+        )
+        .is_err());
+
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0
+        )
+        .is_ok());
+}
+
+#[test]
+fn test_parse_public_inputs() {
+    let env = Env::default();
+    let Setup { client, .. } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = withdraw_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        recipient.clone(),
+        &amount_field(&env, 10_000_000),
+    );
+
+    assert_eq!(
+        client.parse_public_inputs(&PublicInputLayout::WithdrawV1, &inputs),
+        WithdrawPublicInputs {
+            layout: PublicInputLayout::WithdrawV1,
+            root: client.get_latest_root(),
+            nullifier_hash: bytes(&env, 0xa1),
+            recipient: recipient_field(&recipient.into()),
+            amount: 10_000_000,
+            change_commitment: None,
+            max_relayer_fee: 0,
+        }
+    );
+    assert!(client
+        .try_parse_public_inputs(&PublicInputLayout::PartialV1, &inputs)
+        .is_err());
+
+    let mut partial = inputs.clone();
+    partial.push_back(bytes(&env, 7));
+    let parsed = client.parse_public_inputs(&PublicInputLayout::PartialV1, &partial);
+    assert_eq!(parsed.layout, PublicInputLayout::PartialV1);
+    assert_eq!(parsed.change_commitment, Some(bytes(&env, 7)));
+    assert!(client
+        .try_parse_public_inputs(&PublicInputLayout::WithdrawV1, &partial)
+        .is_err());
+
+    let parsed = client.parse_public_inputs(
+        &PublicInputLayout::WithdrawV2,
+        &with_relayer_fee(inputs.clone(), 50_000),
+    );
+    assert_eq!(parsed.change_commitment, None);
+    assert_eq!(parsed.max_relayer_fee, 50_000);
+    let parsed = client.parse_public_inputs(
+        &PublicInputLayout::PartialV2,
+        &with_relayer_fee(partial, 50_000),
+    );
+    assert_eq!(parsed.change_commitment, Some(bytes(&env, 7)));
+    assert_eq!(parsed.max_relayer_fee, 50_000);
+}
+
+/// The relayer fee is capped by the one the proof commits to; V1 proofs
+/// commit to none.
+#[test]
+fn test_relayer_fee_bound_by_proof() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 30_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = |nullifier_hash: u8| {
+        withdraw_inputs(
+            &client,
+            &bytes(&env, nullifier_hash),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        )
+    };
+
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs(0xa1),
+            &recipient,
+            &1
+        )
+        .is_err());
+    let capped = with_relayer_fee(inputs(0xa1), 100_000);
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV2,
+            &capped,
+            &recipient,
+            &100_001
+        )
+        .is_err());
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &capped,
+        &recipient,
+        &100_000,
+    );
+    assert_eq!(token.balance(&relayer), 100_000);
+
+    let partial = with_relayer_fee(
+        partial_inputs(
+            &client.get_latest_root(),
+            &bytes(&env, 0xa2),
+            &recipient,
+            &amount_field(&env, 4_000_000),
+            &bytes(&env, 7),
+        ),
+        10_000,
+    );
+    assert!(client
+        .try_withdraw_partial(
+            &relayer,
+            &PublicInputLayout::PartialV2,
+            &partial,
+            &recipient,
+            &10_001
+        )
+        .is_err());
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV2,
+        &partial,
+        &recipient,
+        &10_000,
+    );
+    assert_eq!(token.balance(&relayer), 110_000);
+}
+
+#[test]
+#[should_panic(expected = "relayer fee exceeds proof")]
+fn test_withdraw_rejects_fee_above_committed() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = with_relayer_fee(
+        withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        100_000,
+    );
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &inputs,
+        &recipient,
+        &9_000_000,
+    );
+}
+
+/// A partial-withdrawal proof's inputs have as many entries as a
+/// `WithdrawV2` proof's. Sent to `withdraw` under their own layout they are
+/// refused, rather than spending the note without its change and reading
+/// the change commitment as the relayer fee.
+#[test]
+#[should_panic(expected = "layout not accepted here")]
+fn test_withdraw_rejects_partial_layout() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = partial_inputs(
+        &client.get_latest_root(),
+        &bytes(&env, 0xa1),
+        &recipient,
+        &amount_field(&env, 4_000_000),
+        &bytes(&env, 7),
+    );
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &inputs,
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "public inputs do not match layout")]
+fn test_withdraw_partial_rejects_withdraw_layout() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let inputs = withdraw_inputs(
+        &client,
+        &bytes(&env, 0xa1),
+        recipient.clone(),
+        &amount_field(&env, 4_000_000),
+    );
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &inputs,
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "amount must be positive")]
+fn test_withdraw_rejects_zero_amount() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 0),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "amount must be positive")]
+fn test_withdraw_rejects_negative_amount() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    // Lower 16 bytes all 0xff decode to -1 as i128.
+    let mut arr = [0u8; 32];
+    arr[16..].copy_from_slice(&[0xff; 16]);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &BytesN::from_array(&env, &arr),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+fn test_withdraw_fails_when_pool_underfunded() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 1_000_000);
+    let recipient = Address::generate(&env);
+
+    let result = client.try_withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 2_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert!(result.is_err());
+    assert!(
+        !client.is_spent(&bytes(&env, 0xa1)),
+        "A failed transfer must not burn the nullifier"
+    );
+}
+
+#[test]
+fn test_withdraw_requires_relayer_auth() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let nullifier = bytes(&env, 0xa1);
+    let inputs = withdraw_inputs(
+        &client,
+        &nullifier,
+        &recipient,
+        &amount_field(&env, 1_000_000),
+    );
+
+    // The attacker signs as itself, but is neither the configured relayer
+    // nor staked.
+    env.mock_auths(&[MockAuth {
+        address: &attacker,
+        invoke: &MockAuthInvoke {
+            contract: &client.address,
+            fn_name: "withdraw",
+            args: (
+                &attacker,
+                PublicInputLayout::WithdrawV1,
+                &inputs,
+                &recipient,
+                0i128,
+            )
+                .into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(client
+        .try_withdraw(
+            &attacker,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0
+        )
+        .is_err());
+
+    // Naming the relayer without its signature fails too.
+    assert!(client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0
+        )
+        .is_err());
+    assert!(!client.is_spent(&nullifier));
+
+    // The same call signed by the relayer goes through.
+    env.mock_auths(&[MockAuth {
+        address: &relayer,
+        invoke: &MockAuthInvoke {
+            contract: &client.address,
+            fn_name: "withdraw",
+            args: (
+                &relayer,
+                PublicInputLayout::WithdrawV1,
+                &inputs,
+                &recipient,
+                0i128,
+            )
+                .into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &inputs,
+        &recipient,
+        &0,
+    );
+    assert!(client.is_spent(&nullifier));
+}
+
+#[test]
+fn test_transact_requires_relayer_auth() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+    let root = client.get_latest_root();
+
+    env.set_auths(&[]);
+    let result = client.try_transact(
+        &relayer,
+        &root,
+        &vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)],
+        &vec![&env, bytes(&env, 2), bytes(&env, 3)],
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_withdraw_partial_requires_relayer_auth() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let root = client.get_latest_root();
+
+    env.set_auths(&[]);
+    let result = client.try_withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_root_validation() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+    let empty_root = client.get_latest_root();
+
+    assert!(client.is_valid_root(&empty_root));
+    assert!(
+        !client.is_valid_root(&bytes(&env, 0)),
+        "Zero root is never valid"
+    );
+    assert!(!client.is_valid_root(&bytes(&env, 9)));
+
+    client.deposit(&depositor, &bytes(&env, 1));
+    assert!(client.is_valid_root(&client.get_latest_root()));
+    assert!(
+        client.is_valid_root(&empty_root),
+        "Previous root stays valid"
+    );
+}
+
+#[test]
+fn test_root_history_evicts_old_roots() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+    let empty_root = client.get_latest_root();
+
+    client.deposit(&depositor, &bytes(&env, 0));
+    let first_root = client.get_latest_root();
+
+    // 29 more inserts fill the 30-slot history, overwriting the empty root.
+    for i in 1..30u8 {
+        client.deposit(&depositor, &bytes(&env, i));
+    }
+    assert!(!client.is_valid_root(&empty_root));
+    assert!(client.is_valid_root(&first_root));
+
+    client.deposit(&depositor, &bytes(&env, 30));
+    assert!(!client.is_valid_root(&first_root));
+    assert!(client.is_valid_root(&client.get_latest_root()));
+}
+
+#[test]
+fn test_field_to_amount() {
+    let env = Env::default();
+
+    let field = |arr: [u8; 32]| BytesN::from_array(&env, &arr);
+    assert_eq!(RotorCore::field_to_amount(&amount_field(&env, 0)), 0);
+    assert_eq!(
+        RotorCore::field_to_amount(&amount_field(&env, 10_000_000)),
+        10_000_000
+    );
+    assert_eq!(
+        RotorCore::field_to_amount(&amount_field(&env, i128::MAX)),
+        i128::MAX
+    );
+
+    // Matches the circuit's Prover.toml encoding of 1 XLM.
+    let mut one_xlm = [0u8; 32];
+    one_xlm[29..].copy_from_slice(&[0x98, 0x96, 0x80]);
+    assert_eq!(RotorCore::field_to_amount(&field(one_xlm)), 10_000_000);
+
+    // Only the lower 16 bytes are read.
+    let mut high = [0u8; 32];
+    high[0] = 0x01;
+    high[31] = 0x05;
+    assert_eq!(RotorCore::field_to_amount(&field(high)), 5);
+}
+
+#[test]
+fn test_transact_spends_inputs_and_inserts_outputs() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+    let depositor = Address::generate(&env);
+
+    client.deposit(&depositor, &bytes(&env, 1));
+    client.deposit(&depositor, &bytes(&env, 2));
+    let root = client.get_latest_root();
+
+    let inputs = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+    let indices = client.transact(&relayer, &root, &inputs, &outputs);
+
+    assert_eq!(
+        indices,
+        vec![&env, 2u64, 3u64],
+        "Outputs follow the deposits"
+    );
+    assert_eq!(client.get_next_index(), 4);
+    assert!(client.is_spent(&bytes(&env, 0xa1)));
+    assert!(client.is_spent(&bytes(&env, 0xa2)));
+    assert_ne!(client.get_latest_root(), root, "Root should change");
+    assert!(client.is_valid_root(&root), "Old root stays in history");
+}
+
+#[test]
+#[should_panic(expected = "nullifier already spent")]
+fn test_transact_rejects_spent_nullifier() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+    let root = client.get_latest_root();
+
+    let inputs = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+    client.transact(&relayer, &root, &inputs, &outputs);
+    client.transact(&relayer, &root, &inputs, &outputs);
+}
+
+#[test]
+#[should_panic(expected = "duplicate input nullifier")]
+fn test_transact_rejects_duplicate_inputs() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+    let root = client.get_latest_root();
+
+    let inputs = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa1)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+    client.transact(&relayer, &root, &inputs, &outputs);
+}
+
+#[test]
+#[should_panic(expected = "unknown merkle root")]
+fn test_transact_rejects_unknown_root() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+
+    let inputs = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+    client.transact(&relayer, &bytes(&env, 9), &inputs, &outputs);
+}
+
+#[test]
+fn test_transact_takes_two_inputs_and_two_outputs() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 0);
+    let root = client.get_latest_root();
+
+    // The join-split circuit has exactly two of each; a single note is
+    // padded with a zero-amount one.
+    let one = vec![&env, bytes(&env, 0xa1)];
+    let two = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)];
+    let three = vec![&env, bytes(&env, 3), bytes(&env, 4), bytes(&env, 5)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+    assert!(client
+        .try_transact(&relayer, &root, &one, &outputs)
+        .is_err());
+    assert!(client
+        .try_transact(&relayer, &root, &three, &outputs)
+        .is_err());
+    assert!(client.try_transact(&relayer, &root, &two, &one).is_err());
+    assert!(client.try_transact(&relayer, &root, &two, &three).is_err());
+    assert!(!client.is_spent(&bytes(&env, 0xa1)));
+
+    assert_eq!(
+        client.transact(&relayer, &root, &two, &outputs),
+        vec![&env, 0u64, 1u64]
+    );
+}
+
+#[test]
+fn test_withdraw_partial_pays_out_and_inserts_change() {
+    let env = Env::default();
+    let Setup {
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let depositor = Address::generate(&env);
+    let recipient = Address::generate(&env);
+
+    client.deposit(&depositor, &bytes(&env, 1));
+    let root = client.get_latest_root();
+
+    let change_index = client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 3_000_000),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+
+    assert_eq!(change_index, 1, "Change note goes in the next leaf");
+    assert_eq!(client.get_next_index(), 2);
+    assert!(client.is_spent(&bytes(&env, 0xa1)));
+    assert_eq!(token.balance(&recipient), 3_000_000);
+    assert_eq!(
+        client.get_balance(),
+        7_000_000,
+        "Remainder stays in the pool"
+    );
+}
+
+#[test]
+#[should_panic(expected = "nullifier already spent")]
+fn test_withdraw_partial_rejects_spent_nullifier() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let root = client.get_latest_root();
+
+    let amount = amount_field(&env, 1_000_000);
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount,
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount,
+            &bytes(&env, 3),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "recipient does not match proof")]
+fn test_withdraw_partial_rejects_other_recipient() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let root = client.get_latest_root();
+
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            Address::generate(&env),
+            &amount_field(&env, 1_000_000),
+            &bytes(&env, 2),
+        ),
+        Address::generate(&env),
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "unknown merkle root")]
+fn test_withdraw_partial_rejects_unknown_root() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &bytes(&env, 9),
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "amount must be positive")]
+fn test_withdraw_partial_rejects_zero_amount() {
+    let env = Env::default();
+    let Setup {
+        relayer, client, ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let root = client.get_latest_root();
+
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 0),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+fn limit(window_ledgers: u32, max_window_amount: i128, max_withdrawal: i128) -> RateLimit {
+    RateLimit {
+        window_ledgers,
+        max_window_amount,
+        max_withdrawal,
+    }
+}
+
+#[test]
+fn test_rate_limit_tracks_window_usage() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    assert_eq!(client.get_rate_limit(), None);
+    client.set_rate_limit(&admin, &limit(100, 5_000_000, 0));
+    assert_eq!(client.get_rate_limit(), Some(limit(100, 5_000_000, 0)));
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 2_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa2),
+            &recipient,
+            &amount_field(&env, 3_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    let start = env.ledger().sequence();
+    assert_eq!(
+        client.get_rate_window(),
+        RateWindow {
+            start_ledger: start,
+            withdrawn: 5_000_000,
+        }
+    );
+    assert!(client.is_paused(), "Cap used up pauses withdrawals");
+}
+
+#[test]
+#[should_panic(expected = "withdrawal cap reached for this window")]
+fn test_rate_limit_rejects_withdrawal_over_window_cap() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.set_rate_limit(&admin, &limit(100, 5_000_000, 0));
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 4_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa2),
+            &recipient,
+            &amount_field(&env, 2_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "withdrawal exceeds per-withdrawal maximum")]
+fn test_rate_limit_rejects_withdrawal_over_max() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.set_rate_limit(&admin, &limit(100, 0, 1_000_000));
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_001),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+fn test_rate_limit_window_rolls_over() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.set_rate_limit(&admin, &limit(100, 5_000_000, 0));
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert!(client.is_paused());
+
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    assert!(!client.is_paused(), "New window is open");
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa2),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert_eq!(token.balance(&recipient), 10_000_000);
+}
+
+#[test]
+fn test_reset_rate_window_reopens_withdrawals() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.set_rate_limit(&admin, &limit(100, 5_000_000, 0));
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert!(client.is_paused());
+
+    client.reset_rate_window(&admin);
+    assert!(!client.is_paused());
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa2),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "withdrawal cap reached for this window")]
+fn test_rate_limit_applies_to_partial_withdrawals() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    let root = client.get_latest_root();
+
+    client.set_rate_limit(&admin, &limit(100, 1_000_000, 0));
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &root,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 2_000_000),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+#[should_panic(expected = "withdrawals are paused")]
+fn test_pause_blocks_withdrawals() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.pause(&admin);
+    assert!(client.is_paused());
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+}
+
+#[test]
+fn test_pause_blocks_transact() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 0);
+    let root = client.get_latest_root();
+    let inputs = vec![&env, bytes(&env, 0xa1), bytes(&env, 0xa2)];
+    let outputs = vec![&env, bytes(&env, 3), bytes(&env, 4)];
+
+    client.pause(&admin);
+    assert!(client
+        .try_transact(&relayer, &root, &inputs, &outputs)
+        .is_err());
+    assert!(!client.is_spent(&bytes(&env, 0xa1)));
+
+    client.unpause(&admin);
+    client.transact(&relayer, &root, &inputs, &outputs);
+    assert!(client.is_spent(&bytes(&env, 0xa1)));
+}
+
+#[test]
+fn test_unpause_resumes_withdrawals() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+
+    client.pause(&admin);
+    client.unpause(&admin);
+    assert!(!client.is_paused());
+
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert_eq!(token.balance(&recipient), 1_000_000);
+}
+
+#[test]
+fn test_set_rate_limit_requires_admin() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    env.set_auths(&[]);
+    assert!(client
+        .try_set_rate_limit(&admin, &limit(100, 5_000_000, 0))
+        .is_err());
+    assert_eq!(client.get_rate_limit(), None);
+}
+
+// ---------------------------------------------------------------------------
+// Protocol fee
+// ---------------------------------------------------------------------------
+
+#[test]
+fn test_withdraw_charges_fee() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    client.deposit(&Address::generate(&env), &bytes(&env, 1));
+
+    govern(&env, &client, &admin, Action::SetFee(50));
+    assert_eq!(client.fee_for(&10_000_000), 50_000);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    assert_eq!(token.balance(&recipient), 9_950_000);
+    assert_eq!(client.get_fees_collected(), 50_000);
+    assert_eq!(client.get_balance(), 50_000, "Only the fee stays behind");
+}
+
+#[test]
+fn test_withdraw_partial_charges_fee_on_payout() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let recipient = Address::generate(&env);
+    client.deposit(&Address::generate(&env), &bytes(&env, 1));
+
+    govern(&env, &client, &admin, Action::SetFee(MAX_FEE_BPS));
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &client.get_latest_root(),
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 3_000_000),
+            &bytes(&env, 2),
+        ),
+        &recipient,
+        &0,
+    );
+
+    assert_eq!(token.balance(&recipient), 2_970_000);
+    assert_eq!(client.get_fees_collected(), 30_000);
+    // The change note is owed its full 7_000_000.
+    assert_eq!(client.get_balance(), 7_030_000);
+}
+
+#[test]
+fn test_fee_rounds_down() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    assert_eq!(client.fee_for(&10_000_000), 0, "No fee until one is set");
+    govern(&env, &client, &admin, Action::SetFee(1));
+    assert_eq!(client.fee_for(&9_999), 0);
+    assert_eq!(client.fee_for(&10_000), 1);
+    assert_eq!(client.fee_for(&19_999), 1);
+}
+
+#[test]
+#[should_panic(expected = "amount too large for fee")]
+fn test_fee_for_rejects_overflowing_amount() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    govern(&env, &client, &admin, Action::SetFee(MAX_FEE_BPS));
+    let largest = i128::MAX / MAX_FEE_BPS as i128;
+    assert_eq!(client.fee_for(&largest), largest / 100);
+    client.fee_for(&(largest + 1));
+}
+
+#[test]
+#[should_panic(expected = "fee exceeds maximum")]
+fn test_set_fee_rejects_above_max() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    client.queue(&admin, &Action::SetFee(MAX_FEE_BPS + 1), &MIN_DELAY);
+}
+
+#[test]
+fn test_fee_settings_require_admin() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    env.set_auths(&[]);
+    assert!(client
+        .try_queue(&admin, &Action::SetFee(10), &MIN_DELAY)
+        .is_err());
+    assert!(client
+        .try_queue(
+            &admin,
+            &Action::SetTreasury(Address::generate(&env)),
+            &MIN_DELAY
+        )
+        .is_err());
+    assert!(client.try_withdraw_fees(&admin, &1).is_err());
+    assert_eq!(client.get_fee_bps(), 0);
+    assert_eq!(client.get_treasury(), None);
+}
+
+#[test]
+fn test_withdraw_fees_pays_treasury() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 10_000_000);
+    let treasury = Address::generate(&env);
+    client.deposit(&Address::generate(&env), &bytes(&env, 1));
+
+    govern(&env, &client, &admin, Action::SetFee(100));
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [
+            ActionExecuted { id: 0 }.to_xdr(&env, &client.address),
+            FeeUpdated { fee_bps: 100 }.to_xdr(&env, &client.address)
+        ]
+    );
+    let recipient = Address::generate(&env);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    // Nowhere to send fees until a treasury is set.
+    assert!(client.try_withdraw_fees(&admin, &100_000).is_err());
+    let id = govern(&env, &client, &admin, Action::SetTreasury(treasury.clone()));
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [
+            ActionExecuted { id }.to_xdr(&env, &client.address),
+            TreasuryUpdated {
+                treasury: treasury.clone()
+            }
+            .to_xdr(&env, &client.address)
+        ]
+    );
+
+    client.withdraw_fees(&admin, &40_000);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [FeesWithdrawn {
+            treasury: treasury.clone(),
+            amount: 40_000,
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert_eq!(token.balance(&treasury), 40_000);
+    assert_eq!(client.get_fees_collected(), 60_000);
+}
+
+#[test]
+fn test_withdraw_fees_cannot_take_note_funds() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 20_000_000);
+    let depositor = Address::generate(&env);
+    client.deposit(&depositor, &bytes(&env, 1));
+    client.deposit(&depositor, &bytes(&env, 2));
+    govern(
+        &env,
+        &client,
+        &admin,
+        Action::SetTreasury(Address::generate(&env)),
+    );
+    govern(&env, &client, &admin, Action::SetFee(100));
+
+    let recipient = Address::generate(&env);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert!(client.try_withdraw_fees(&admin, &100_001).is_err());
+    assert!(client.try_withdraw_fees(&admin, &0).is_err());
+    client.withdraw_fees(&admin, &100_000);
+    assert!(client.try_withdraw_fees(&admin, &1).is_err());
+
+    // The second note is untouched.
+    assert_eq!(client.get_balance(), 10_000_000);
+}
+
+/// Notes deposited before, during and after fee changes all pay out in full,
+/// less the fee at the time, and the treasury takes exactly what is left.
+#[test]
+fn test_notes_stay_redeemable_with_fee() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        token,
+        ..
+    } = setup(&env, 40_000_000);
+    let depositor = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    govern(&env, &client, &admin, Action::SetTreasury(treasury.clone()));
+    for note in 1..=4 {
+        client.deposit(&depositor, &bytes(&env, note));
+    }
+
+    let withdraw = |nullifier: u8, amount: i128| {
+        let recipient = Address::generate(&env);
+        let fee = client.fee_for(&amount);
+        client.withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &withdraw_inputs(
+                &client,
+                &bytes(&env, nullifier),
+                &recipient,
+                &amount_field(&env, amount),
+            ),
+            &recipient,
+            &0,
+        );
+        assert_eq!(token.balance(&recipient), amount - fee);
+        amount - fee
+    };
+
+    let mut paid = withdraw(0xa1, 10_000_000);
+    govern(&env, &client, &admin, Action::SetFee(MAX_FEE_BPS));
+    paid += withdraw(0xa2, 10_000_000);
+
+    // A partial withdrawal and its change note, across a fee change.
+    let recipient = Address::generate(&env);
+    client.withdraw_partial(
+        &relayer,
+        &PublicInputLayout::PartialV1,
+        &partial_inputs(
+            &client.get_latest_root(),
+            &bytes(&env, 0xa3),
+            &recipient,
+            &amount_field(&env, 2_500_000),
+            &bytes(&env, 5),
+        ),
+        &recipient,
+        &0,
+    );
+    paid += token.balance(&recipient);
+    govern(&env, &client, &admin, Action::SetFee(7));
+    paid += withdraw(0xa5, 7_500_000);
+
+    // A note moved by transact pays on its way out like any other.
+    client.transact(
+        &relayer,
+        &client.get_latest_root(),
+        &vec![&env, bytes(&env, 0xa4), bytes(&env, 0xb4)],
+        &vec![&env, bytes(&env, 6), bytes(&env, 7)],
+    );
+    govern(&env, &client, &admin, Action::SetFee(0));
+    paid += withdraw(0xa6, 10_000_000);
+
+    let fees = client.get_fees_collected();
+    assert_eq!(paid + fees, 40_000_000);
+    assert_eq!(client.get_balance(), fees);
+    client.withdraw_fees(&admin, &fees);
+    assert_eq!(token.balance(&treasury), fees);
+    assert_eq!(client.get_balance(), 0);
+}
+
+/// Keys of the original rotor-core layout with their original payload
+/// types; `NextLeafIndex` held a `u32` there.
+#[contracttype]
+enum BaselineKey {
+    NextLeafIndex,
+    CachedSubtree(u32),
+    Root(u32),
+    Leaf(u32),
+}
+
+/// Rewrite a pool into the original layout: a `u32` leaf count, leaves under
+/// `Leaf(u32)`, one `CachedSubtree` entry per level written so far and one
+/// `Root` entry per ring buffer slot filled.
+fn unpack_to_baseline(env: &Env, client: &RotorCoreClient) {
+    // Several calls, as a single one would go over the per-call write limit.
+    let next_index = client.get_next_index();
+    env.as_contract(&client.address, || {
+        let storage = env.storage().persistent();
+        let frontier: soroban_sdk::Vec<BytesN<32>> = storage.get(&DataKey::Frontier).unwrap();
+        for (level, node) in frontier.iter().enumerate() {
+            if node.to_array() != rotor_merkle::zero(level as u32) {
+                storage.set(&BaselineKey::CachedSubtree(level as u32), &node);
+            }
+        }
+        storage.remove(&DataKey::Frontier);
+        env.storage()
+            .instance()
+            .set(&BaselineKey::NextLeafIndex, &(next_index as u32));
+    });
+    env.as_contract(&client.address, || {
+        let storage = env.storage().persistent();
+        let roots: soroban_sdk::Vec<BytesN<32>> = storage.get(&DataKey::RootHistory).unwrap();
+        for (i, root) in roots.iter().enumerate() {
+            storage.set(&BaselineKey::Root(i as u32), &root);
+        }
+        storage.remove(&DataKey::RootHistory);
+    });
+    for i in 0..next_index {
+        env.as_contract(&client.address, || {
+            let storage = env.storage().persistent();
+            let leaf: BytesN<32> = storage.get(&DataKey::Leaf(i)).unwrap();
+            storage.set(&BaselineKey::Leaf(i as u32), &leaf);
+            storage.remove(&DataKey::Leaf(i));
+        });
+    }
+}
+
+#[test]
+fn test_migrate_from_baseline_layout() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let control = setup(&env, 0).client;
+    let depositor = Address::generate(&env);
+
+    // Enough deposits to wrap the ring buffer.
+    let mut roots = alloc::vec::Vec::new();
+    for i in 0..33u8 {
+        client.deposit(&depositor, &bytes(&env, i + 1));
+        control.deposit(&depositor, &bytes(&env, i + 1));
+        roots.push(client.get_latest_root());
+    }
+
+    unpack_to_baseline(&env, &client);
+    assert!(client.try_get_latest_root().is_err());
+    assert!(client.try_get_next_index().is_err());
+    assert!(client.try_deposit(&depositor, &bytes(&env, 99)).is_err());
+
+    client.migrate(&admin);
+    assert_eq!(client.get_next_index(), 33);
+    assert_eq!(client.get_latest_root(), control.get_latest_root());
+    for root in &roots[3..] {
+        assert!(client.is_valid_root(root));
+    }
+    assert!(!client.is_valid_root(&roots[2]));
+    assert!(client.try_get_leaf(&0).is_err());
+
+    assert_eq!(client.migrate_leaves(&admin, &20), 13);
+    assert!(client.try_get_leaf(&20).is_err());
+    assert_eq!(client.migrate_leaves(&admin, &20), 0);
+    for i in 0..33u8 {
+        assert_eq!(client.get_leaf(&u64::from(i)), bytes(&env, i + 1));
+    }
+    env.as_contract(&client.address, || {
+        for i in 0..33 {
+            assert!(!env.storage().persistent().has(&BaselineKey::Leaf(i)));
+        }
+    });
+    assert!(client.try_migrate_leaves(&admin, &20).is_err());
+
+    for i in 33..36u8 {
+        assert_eq!(
+            client.deposit(&depositor, &bytes(&env, i + 1)),
+            control.deposit(&depositor, &bytes(&env, i + 1))
+        );
+        assert_eq!(client.get_latest_root(), control.get_latest_root());
+        assert_eq!(client.get_leaf(&u64::from(i)), bytes(&env, i + 1));
+    }
+
+    assert!(client.try_migrate(&admin).is_err(), "migration runs once");
+}
+
+#[test]
+fn test_upgrade_and_migrate_require_admin() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    client.deposit(&Address::generate(&env), &bytes(&env, 1));
+    unpack_to_baseline(&env, &client);
+
+    env.set_auths(&[]);
+    assert!(client
+        .try_queue(&admin, &Action::Upgrade(bytes(&env, 1)), &MIN_DELAY)
+        .is_err());
+    assert!(client.try_migrate(&admin).is_err());
+    assert!(client.try_get_latest_root().is_err());
+
+    env.mock_all_auths();
+    client.migrate(&admin);
+    env.set_auths(&[]);
+    assert!(client.try_migrate_leaves(&admin, &1).is_err());
+}
+
+/// Rewrite a pool into what the original contract left behind: the
+/// `unpack_to_baseline` layout with no admin set, no roles and no
+/// `StoreLeaves` flag.
+fn strip_to_baseline(env: &Env, setup: &Setup) {
+    unpack_to_baseline(env, &setup.client);
+    env.as_contract(&setup.client.address, || {
+        env.storage().instance().remove(&AdminKey::AdminSet);
+        env.storage().instance().remove(&DataKey::StoreLeaves);
+        for role in [
+            Role::Pauser,
+            Role::Upgrader,
+            Role::FeeManager,
+            Role::RelayerManager,
+            Role::LimitManager,
+        ] {
+            env.storage()
+                .persistent()
+                .remove(&RoleKey::Role(role, setup.admin.clone()));
+        }
+    });
+}
+
+#[test]
+fn test_baseline_pool_migrates_under_its_relayer() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    let control = setup_with(&env, 0, DEPTH, 0, true).client;
+    let depositor = Address::generate(&env);
+    for i in 0..3u8 {
+        setup.client.deposit(&depositor, &bytes(&env, i + 1));
+        control.deposit(&depositor, &bytes(&env, i + 1));
+    }
+    strip_to_baseline(&env, &setup);
+    let client = &setup.client;
+    let admin = Address::generate(&env);
+
+    // Nobody holds a role, so nobody can migrate yet.
+    assert!(client.try_migrate(&setup.admin).is_err());
+    assert!(client.try_migrate(&admin).is_err());
+
+    // Only the stored relayer, signing, can install the first admin.
+    assert!(client.try_claim_admin(&admin, &admin).is_err());
+    env.set_auths(&[]);
+    assert!(client.try_claim_admin(&setup.relayer, &admin).is_err());
+    env.mock_all_auths();
+    client.claim_admin(&setup.relayer, &admin);
+    assert!(client.has_role(&Role::Upgrader, &admin));
+    assert!(client
+        .try_claim_admin(&setup.relayer, &setup.relayer)
+        .is_err());
+
+    client.migrate(&admin);
+    assert_eq!(client.migrate_leaves(&admin, &10), 0);
+    assert_eq!(client.get_next_index(), 3);
+    assert_eq!(client.get_latest_root(), control.get_latest_root());
+    assert_eq!(client.get_leaf(&2), bytes(&env, 3));
+    assert_eq!(
+        client.deposit(&depositor, &bytes(&env, 4)),
+        control.deposit(&depositor, &bytes(&env, 4))
+    );
+    assert_eq!(client.get_latest_root(), control.get_latest_root());
+}
+
+#[test]
+fn test_admin_hands_over_to_multisig() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let signers = vec![
+        &env,
+        Address::generate(&env),
+        Address::generate(&env),
+        Address::generate(&env),
+    ];
+    let multisig = AdminSet {
+        signers: signers.clone(),
+        threshold: 2,
+    };
+
+    let old = vec![&env, admin.clone()];
+
+    govern_admin(&env, &client, &old, Action::ProposeAdmin(multisig.clone()));
+    assert_eq!(client.get_pending_admin(), Some(multisig.clone()));
+    client.accept_admin();
+    assert_eq!(client.get_admin(), multisig);
+    let revoke = Action::RevokeRole(Role::Upgrader, admin.clone());
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    assert!(client.try_queue_admin(&old, &revoke, &eta).is_err());
+
+    // One signer's key alone cannot change roles.
+    let approvers = vec![&env, signers.get(0).unwrap(), signers.get(2).unwrap()];
+    let invoke = MockAuthInvoke {
+        contract: &client.address,
+        fn_name: "queue_admin",
+        args: (&approvers, revoke.clone(), eta).into_val(&env),
+        sub_invokes: &[],
+    };
+    env.mock_auths(&[MockAuth {
+        address: &signers.get(0).unwrap(),
+        invoke: &invoke,
+    }]);
+    assert!(client.try_queue_admin(&approvers, &revoke, &eta).is_err());
+    env.mock_auths(&[
+        MockAuth {
+            address: &signers.get(0).unwrap(),
+            invoke: &invoke,
+        },
+        MockAuth {
+            address: &signers.get(2).unwrap(),
+            invoke: &invoke,
+        },
+    ]);
+    let id = client.queue_admin(&approvers, &revoke, &eta);
+
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    client.execute_admin(&approvers, &id);
+    assert!(!client.has_role(&Role::Upgrader, &admin));
+
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    assert!(client
+        .try_queue(&admin, &Action::Upgrade(bytes(&env, 1)), &eta)
+        .is_err());
+    client.pause(&admin);
+}
+
+#[test]
+fn test_roles_guard_privileged_calls() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let approvers = vec![&env, admin.clone()];
+    let on_call = Address::generate(&env);
+
+    for role in [
+        Role::Pauser,
+        Role::Upgrader,
+        Role::FeeManager,
+        Role::RelayerManager,
+        Role::LimitManager,
+    ] {
+        assert!(client.has_role(&role, &admin));
+        assert!(!client.has_role(&role, &on_call));
+    }
+
+    // The pause role lives on a hot key; it can pause and nothing else.
+    govern_admin(
+        &env,
+        &client,
+        &approvers,
+        Action::GrantRole(Role::Pauser, on_call.clone()),
+    );
+    client.pause(&on_call);
+    assert!(client.is_paused());
+    client.unpause(&on_call);
+    for action in [
+        Action::SetFee(10),
+        Action::SetRelayer(Address::generate(&env)),
+        Action::SetToken(Address::generate(&env)),
+        Action::Upgrade(bytes(&env, 1)),
+    ] {
+        assert!(client.try_queue(&on_call, &action, &MIN_DELAY).is_err());
+    }
+    assert!(client
+        .try_set_rate_limit(&on_call, &limit(100, 5_000_000, 0))
+        .is_err());
+    assert!(client
+        .try_queue(&on_call, &Action::SetMinStake(1), &MIN_DELAY)
+        .is_err());
+
+    govern_admin(
+        &env,
+        &client,
+        &approvers,
+        Action::RevokeRole(Role::Pauser, on_call.clone()),
+    );
+    assert!(client.try_pause(&on_call).is_err());
+
+    // Only the admin set grants roles.
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    assert!(client
+        .try_queue_admin(
+            &vec![&env, on_call.clone()],
+            &Action::GrantRole(Role::Pauser, on_call.clone()),
+            &eta
+        )
+        .is_err());
+}
+
+// ---------------------------------------------------------------------------
+// Timelock
+// ---------------------------------------------------------------------------
+
+/// Queue `action` with the shortest delay, wait for its eta and execute it.
+/// Returns the action's id.
+fn govern(env: &Env, client: &RotorCoreClient, caller: &Address, action: Action) -> u64 {
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    let id = client.queue(caller, &action, &eta);
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    client.execute(caller, &id);
+    id
+}
+
+/// Like `govern`, for the actions the admin set queues.
+fn govern_admin(env: &Env, client: &RotorCoreClient, approvers: &Vec<Address>, action: Action) {
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    let id = client.queue_admin(approvers, &action, &eta);
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    client.execute_admin(approvers, &id);
+}
+
+#[test]
+fn test_set_relayer_waits_for_eta() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    let next = Address::generate(&env);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+    let eta = 100 + MIN_DELAY;
+
+    let action = Action::SetRelayer(next.clone());
+    let id = client.queue(&admin, &action, &eta);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [ActionQueued {
+            id,
+            action: action.clone(),
+            eta,
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert_eq!(client.get_action(&id), Some(QueuedAction { action, eta }));
+
+    // Nothing changes before the eta.
+    env.ledger().with_mut(|li| li.sequence_number = eta - 1);
+    assert!(client.try_execute(&admin, &id).is_err());
+    assert_eq!(client.get_relayer(), relayer);
+
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    client.execute(&admin, &id);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [
+            ActionExecuted { id }.to_xdr(&env, &client.address),
+            RelayerUpdated {
+                relayer: next.clone()
+            }
+            .to_xdr(&env, &client.address)
+        ]
+    );
+    assert_eq!(client.get_relayer(), next);
+    assert_eq!(client.get_action(&id), None);
+    assert!(client.try_execute(&admin, &id).is_err(), "runs once");
+
+    let withdraw = |relayer: &Address, nullifier: u8| {
+        let recipient = Address::generate(&env);
+        let inputs = withdraw_inputs(
+            &client,
+            &bytes(&env, nullifier),
+            &recipient,
+            &amount_field(&env, 1_000_000),
+        );
+        client.try_withdraw(
+            relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0,
+        )
+    };
+    assert!(withdraw(&relayer, 0xa1).is_err());
+    assert!(withdraw(&next, 0xa1).is_ok());
+}
+
+#[test]
+#[should_panic(expected = "eta is before the minimum delay")]
+fn test_queue_rejects_short_delay() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    client.queue(&admin, &Action::SetFee(10), &(99 + MIN_DELAY));
+}
+
+#[test]
+fn test_cancel_action() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let approvers = vec![&env, admin.clone()];
+    let fee_manager = Address::generate(&env);
+    govern_admin(
+        &env,
+        &client,
+        &approvers,
+        Action::GrantRole(Role::FeeManager, fee_manager.clone()),
+    );
+
+    let eta = env.ledger().sequence() + MIN_DELAY;
+    let id = client.queue(&fee_manager, &Action::SetFee(10), &eta);
+    let upgrade = client.queue(&admin, &Action::Upgrade(bytes(&env, 1)), &eta);
+    assert_eq!(upgrade, id + 1);
+
+    // Cancelling needs the role guarding the action.
+    assert!(client.try_cancel(&fee_manager, &upgrade).is_err());
+    client.cancel(&fee_manager, &id);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [ActionCancelled { id }.to_xdr(&env, &client.address)]
+    );
+    assert_eq!(client.get_action(&id), None);
+
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    assert!(client.try_execute(&fee_manager, &id).is_err());
+    assert_eq!(client.get_fee_bps(), 0);
+}
+
+#[test]
+fn test_role_changes_wait_for_eta() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let approvers = vec![&env, admin.clone()];
+    let on_call = Address::generate(&env);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+    let eta = 100 + MIN_DELAY;
+
+    // Role holders cannot queue admin actions, nor the admin set role ones.
+    let grant = Action::GrantRole(Role::Pauser, on_call.clone());
+    assert!(client.try_queue(&admin, &grant, &eta).is_err());
+    assert!(client
+        .try_queue_admin(&approvers, &Action::SetFee(10), &eta)
+        .is_err());
+
+    let id = client.queue_admin(&approvers, &grant, &eta);
+    let propose = client.queue_admin(
+        &approvers,
+        &Action::ProposeAdmin(AdminSet::single(&env, on_call.clone())),
+        &eta,
+    );
+
+    // Nothing changes before the eta.
+    env.ledger().with_mut(|li| li.sequence_number = eta - 1);
+    assert!(client.try_execute_admin(&approvers, &id).is_err());
+    assert!(!client.has_role(&Role::Pauser, &on_call));
+    assert!(client.try_pause(&on_call).is_err());
+
+    client.cancel_admin(&approvers, &propose);
+    assert_eq!(client.get_action(&propose), None);
+
+    env.ledger().with_mut(|li| li.sequence_number = eta);
+    assert!(client.try_execute(&admin, &id).is_err());
+    client.execute_admin(&approvers, &id);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [
+            ActionExecuted { id }.to_xdr(&env, &client.address),
+            RoleGranted {
+                role: Role::Pauser,
+                account: on_call.clone(),
+            }
+            .to_xdr(&env, &client.address)
+        ]
+    );
+    client.pause(&on_call);
+    assert!(client.try_execute_admin(&approvers, &propose).is_err());
+    assert_eq!(client.get_pending_admin(), None);
+}
+
+#[test]
+fn test_action_expires_after_grace_period() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+
+    let id = client.queue(&admin, &Action::SetFee(10), &MIN_DELAY);
+    env.ledger()
+        .with_mut(|li| li.sequence_number = MIN_DELAY + GRACE_PERIOD + 1);
+    assert!(client.try_execute(&admin, &id).is_err());
+
+    let id = client.queue(
+        &admin,
+        &Action::SetFee(10),
+        &(2 * MIN_DELAY + GRACE_PERIOD + 1),
+    );
+    env.ledger()
+        .with_mut(|li| li.sequence_number = 2 * MIN_DELAY + 2 * GRACE_PERIOD + 1);
+    client.execute(&admin, &id);
+    assert_eq!(client.get_fee_bps(), 10);
+}
+
+#[test]
+fn test_set_token() {
+    let env = Env::default();
+    let Setup { admin, client, .. } = setup(&env, 0);
+    let next = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    govern(&env, &client, &admin, Action::SetToken(next.clone()));
+    assert_eq!(client.get_token(), next);
+    assert_eq!(client.get_balance(), 0);
+}
+
+/// Mint `amount` to a fresh depositor and lock commitment `bytes(note)` until
+/// `unlock_ledger`. Returns the depositor and the lock id.
+fn lock_note(
+    env: &Env,
+    setup: &Setup,
+    note: u8,
+    amount: i128,
+    unlock_ledger: u32,
+) -> (Address, u64) {
+    let depositor = Address::generate(env);
+    setup.asset.mint(&depositor, &amount);
+    let lock_id =
+        setup
+            .client
+            .deposit_locked(&depositor, &bytes(env, note), &amount, &unlock_ledger);
+    (depositor, lock_id)
+}
+
+#[test]
+fn test_current_ledger() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+    assert_eq!(client.current_ledger(), 100);
+}
+
+#[test]
+fn test_deposit_locked() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+    let empty_root = setup.client.get_latest_root();
+
+    let (depositor, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+
+    assert_eq!(lock_id, 0);
+    assert_eq!(setup.token.balance(&depositor), 0);
+    assert_eq!(setup.client.get_balance(), 5_000_000);
+    // Held back from the tree until released.
+    assert_eq!(setup.client.get_next_index(), 0);
+    assert_eq!(setup.client.get_latest_root(), empty_root);
+    assert_eq!(
+        setup.client.get_lock(&lock_id),
+        Some(NoteLock {
+            commitment: bytes(&env, 1),
+            amount: 5_000_000,
+            unlock_ledger: 200,
+        })
+    );
+}
+
+#[test]
+fn test_multiple_locked_notes() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    assert_eq!(lock_note(&env, &setup, 1, 5_000_000, 200).1, 0);
+    assert_eq!(lock_note(&env, &setup, 2, 3_000_000, 300).1, 1);
+
+    assert_eq!(setup.client.get_balance(), 8_000_000);
+    assert_eq!(setup.client.get_next_index(), 0);
+
+    env.ledger().with_mut(|li| li.sequence_number = 250);
+    assert!(setup.client.is_lock_expired(&0));
+    assert!(!setup.client.is_lock_expired(&1));
+}
+
+#[test]
+#[should_panic(expected = "amount must be positive")]
+fn test_deposit_locked_fails_with_zero_amount() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    lock_note(&env, &setup, 1, 0, 200);
+}
+
+#[test]
+#[should_panic(expected = "amount must be positive")]
+fn test_deposit_locked_fails_with_negative_amount() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    let depositor = Address::generate(&env);
+    client.deposit_locked(&depositor, &bytes(&env, 1), &-1, &200);
+}
+
+#[test]
+#[should_panic(expected = "unlock ledger must be in the future")]
+fn test_deposit_locked_fails_with_past_unlock_ledger() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    lock_note(&env, &setup, 1, 5_000_000, 50);
+}
+
+#[test]
+#[should_panic(expected = "unlock ledger must be in the future")]
+fn test_deposit_locked_fails_with_current_unlock_ledger() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    lock_note(&env, &setup, 1, 5_000_000, 100);
+}
+
+#[test]
+fn test_is_lock_expired() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    let (_, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+    assert!(!setup.client.is_lock_expired(&lock_id));
+
+    env.ledger().with_mut(|li| li.sequence_number = 199);
+    assert!(!setup.client.is_lock_expired(&lock_id));
+
+    env.ledger().with_mut(|li| li.sequence_number = 200);
+    assert!(setup.client.is_lock_expired(&lock_id));
+}
+
+#[test]
+fn test_is_lock_expired_no_lock() {
+    let env = Env::default();
+    let client = setup(&env, 0).client;
+
+    assert_eq!(client.get_lock(&0), None);
+    assert!(client.is_lock_expired(&0));
+}
+
+#[test]
+#[should_panic(expected = "note is still locked")]
+fn test_release_fails_before_unlock() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    let (_, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+    env.ledger().with_mut(|li| li.sequence_number = 199);
+    setup.client.release_lock(&lock_id);
+}
+
+#[test]
+#[should_panic(expected = "lock not found")]
+fn test_release_lock_only_once() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    let (_, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+    env.ledger().with_mut(|li| li.sequence_number = 200);
+    setup.client.release_lock(&lock_id);
+    setup.client.release_lock(&lock_id);
+}
+
+#[test]
+fn test_locked_note_has_no_root_before_release() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    let control = self::setup(&env, 0).client;
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    // The only roots a proof of the locked note could use are those of a
+    // tree containing its commitment.
+    control.deposit(&Address::generate(&env), &bytes(&env, 1));
+    let note_root = control.get_latest_root();
+
+    let (_, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+    setup
+        .client
+        .deposit(&Address::generate(&env), &bytes(&env, 2));
+    assert!(!setup.client.is_valid_root(&note_root));
+    assert!(setup.client.try_release_lock(&lock_id).is_err());
+
+    // Anyone can release it once the lock expires.
+    env.ledger().with_mut(|li| li.sequence_number = 200);
+    env.set_auths(&[]);
+    assert_eq!(setup.client.release_lock(&lock_id), 1);
+    assert_eq!(setup.client.get_lock(&lock_id), None);
+    assert_eq!(setup.client.get_leaf(&1), bytes(&env, 1));
+}
+
+#[test]
+fn test_third_party_cannot_lock_existing_note() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    let recipient = Address::generate(&env);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    setup
+        .client
+        .deposit(&Address::generate(&env), &bytes(&env, 1));
+    setup.asset.mint(&setup.client.address, &5_000_000);
+
+    // Locking the same commitment holds back only the attacker's own copy;
+    // the deposited note stays spendable against the current root.
+    lock_note(&env, &setup, 1, 5_000_000, 200);
+    setup.client.withdraw(
+        &setup.relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+    assert_eq!(setup.token.balance(&recipient), 5_000_000);
+}
+
+#[test]
+fn test_withdraw_after_release() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    let recipient = Address::generate(&env);
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    let (_, lock_id) = lock_note(&env, &setup, 1, 5_000_000, 200);
+
+    env.ledger().with_mut(|li| li.sequence_number = 200);
+    setup.client.release_lock(&lock_id);
+    setup.client.withdraw(
+        &setup.relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 5_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    assert_eq!(setup.token.balance(&recipient), 5_000_000);
+    assert!(setup.client.is_spent(&bytes(&env, 0xa1)));
+}
+
+// ---------------------------------------------------------------------------
+// Relayer staking
+// ---------------------------------------------------------------------------
+
+const STAKE: i128 = 50_000_000;
+
+/// Fund a new relayer and register it with `STAKE`.
+fn stake_relayer(env: &Env, setup: &Setup, key: &SigningKey) -> Address {
+    let relayer = Address::generate(env);
+    setup.asset.mint(&relayer, &STAKE);
+    setup.client.register_relayer(
+        &relayer,
+        &BytesN::from_array(env, &key.verifying_key().to_bytes()),
+        &STAKE,
+    );
+    relayer
+}
+
+/// Sign `intent` the way `slash` expects.
+fn sign_intent(env: &Env, key: &SigningKey, intent: &WithdrawalIntent) -> BytesN<64> {
+    let message: alloc::vec::Vec<u8> = intent.clone().to_xdr(env).iter().collect();
+    BytesN::from_array(env, &key.sign(&message).to_bytes())
+}
+
+#[test]
+fn test_register_relayer_locks_stake() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+
+    let relayer = stake_relayer(&env, &setup, &key);
+    assert_eq!(setup.token.balance(&relayer), 0);
+    assert_eq!(setup.client.get_balance(), 10_000_000 + STAKE);
+    assert_eq!(setup.client.get_total_staked(), STAKE);
+    assert_eq!(
+        setup.client.get_stake(&relayer),
+        Some(RelayerStake {
+            amount: STAKE,
+            signing_key: BytesN::from_array(&env, &key.verifying_key().to_bytes()),
+            unbond_ledger: 0,
+        })
+    );
+}
+
+#[test]
+#[should_panic(expected = "stake below minimum")]
+fn test_register_relayer_rejects_stake_below_minimum() {
+    let env = Env::default();
+    let setup = setup(&env, 0);
+    govern(
+        &env,
+        &setup.client,
+        &setup.admin,
+        Action::SetMinStake(STAKE + 1),
+    );
+
+    stake_relayer(&env, &setup, &SigningKey::from_bytes(&[7; 32]));
+}
+
+#[test]
+fn test_staked_relayer_withdraws_and_earns_fee() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let relayer = stake_relayer(&env, &setup, &SigningKey::from_bytes(&[7; 32]));
+    let recipient = Address::generate(&env);
+
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &with_relayer_fee(
+            withdraw_inputs(
+                &setup.client,
+                &bytes(&env, 0xa1),
+                &recipient,
+                &amount_field(&env, 10_000_000),
+            ),
+            100_000,
+        ),
+        &recipient,
+        &100_000,
+    );
+    assert_eq!(setup.token.balance(&recipient), 9_900_000);
+    assert_eq!(setup.token.balance(&relayer), 100_000);
+    assert_eq!(setup.client.get_balance(), STAKE);
+    assert_eq!(
+        setup.client.get_payout(&bytes(&env, 0xa1)),
+        Some(PayoutRecord {
+            relayer,
+            recipient: recipient_field(&recipient.into()),
+            relayer_fee: 100_000,
+        })
+    );
+}
+
+#[test]
+#[should_panic(expected = "relayer fee exceeds payout")]
+fn test_relayer_fee_cannot_exceed_payout() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        relayer,
+        client,
+        ..
+    } = setup(&env, 10_000_000);
+    govern(&env, &client, &admin, Action::SetFee(MAX_FEE_BPS));
+
+    let recipient = Address::generate(&env);
+    client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &with_relayer_fee(
+            withdraw_inputs(
+                &client,
+                &bytes(&env, 0xa1),
+                &recipient,
+                &amount_field(&env, 10_000_000),
+            ),
+            10_000_000,
+        ),
+        &recipient,
+        &9_950_000,
+    );
+}
+
+#[test]
+fn test_unstaked_or_underbonded_relayer_cannot_withdraw() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let stranger = Address::generate(&env);
+    let relayer = stake_relayer(&env, &setup, &SigningKey::from_bytes(&[7; 32]));
+    let withdraw = |relayer: &Address| {
+        let recipient = Address::generate(&env);
+        let inputs = withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        );
+        setup.client.try_withdraw(
+            relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0,
+        )
+    };
+
+    assert!(withdraw(&stranger).is_err());
+
+    // Raising the minimum applies to relayers already registered.
+    govern(
+        &env,
+        &setup.client,
+        &setup.admin,
+        Action::SetMinStake(STAKE + 1),
+    );
+    assert!(withdraw(&relayer).is_err());
+    govern(
+        &env,
+        &setup.client,
+        &setup.admin,
+        Action::SetMinStake(STAKE),
+    );
+    assert!(withdraw(&relayer).is_ok());
+}
+
+#[test]
+fn test_unbonding_waits_for_delay() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let relayer = stake_relayer(&env, &setup, &SigningKey::from_bytes(&[7; 32]));
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+
+    assert!(setup.client.try_unbond(&relayer).is_err());
+    setup.client.begin_unbonding(&relayer);
+    assert_eq!(
+        setup.client.get_stake(&relayer).unwrap().unbond_ledger,
+        100 + UNBONDING_DELAY
+    );
+
+    // An unbonding relayer can no longer submit.
+    let recipient = Address::generate(&env);
+    let inputs = withdraw_inputs(
+        &setup.client,
+        &bytes(&env, 0xa1),
+        &recipient,
+        &amount_field(&env, 10_000_000),
+    );
+    assert!(setup
+        .client
+        .try_withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &inputs,
+            &recipient,
+            &0
+        )
+        .is_err());
+
+    env.ledger()
+        .with_mut(|li| li.sequence_number = 99 + UNBONDING_DELAY);
+    assert!(setup.client.try_unbond(&relayer).is_err());
+
+    env.ledger()
+        .with_mut(|li| li.sequence_number = 100 + UNBONDING_DELAY);
+    setup.client.unbond(&relayer);
+    assert_eq!(setup.token.balance(&relayer), STAKE);
+    assert_eq!(setup.client.get_stake(&relayer), None);
+    assert_eq!(setup.client.get_total_staked(), 0);
+    assert_eq!(setup.client.get_balance(), 10_000_000);
+}
+
+#[test]
+fn test_slash_misrouted_withdrawal() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let relayer = stake_relayer(&env, &setup, &key);
+    let promised = Address::generate(&env);
+    let diverted = Address::generate(&env);
+
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: promised.clone().into(),
+        relayer_fee: 0,
+    };
+    let signature = sign_intent(&env, &key, &intent);
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &diverted,
+            &amount_field(&env, 10_000_000),
+        ),
+        &diverted,
+        &0,
+    );
+
+    // Slashing needs no authorization: the signed intent is the evidence.
+    // It works while unbonding too.
+    setup.client.begin_unbonding(&relayer);
+    env.set_auths(&[]);
+    setup.client.slash(&intent, &signature);
+    assert_eq!(
+        env.events()
+            .all()
+            .filter_by_contract(&setup.client.address)
+            .events()
+            .last()
+            .unwrap(),
+        &RelayerSlashed {
+            relayer: relayer.clone(),
+            recipient: promised.clone(),
+            compensation: 0,
+            forfeited: STAKE,
+        }
+        .to_xdr(&env, &setup.client.address)
+    );
+
+    // The proof named `diverted`, so nothing shows `promised` was owed: the
+    // stake is forfeited to the fees rather than paid to the intent.
+    assert_eq!(setup.token.balance(&promised), 0);
+    assert_eq!(setup.client.get_stake(&relayer), None);
+    assert_eq!(setup.client.get_total_staked(), 0);
+    assert_eq!(setup.client.get_fees_collected(), STAKE);
+    assert_eq!(setup.client.get_balance(), STAKE);
+}
+
+#[test]
+fn test_slash_overcharged_fee() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let relayer = stake_relayer(&env, &setup, &key);
+    let recipient = Address::generate(&env);
+
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: recipient.clone().into(),
+        relayer_fee: 10_000,
+    };
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &with_relayer_fee(
+            withdraw_inputs(
+                &setup.client,
+                &bytes(&env, 0xa1),
+                &recipient,
+                &amount_field(&env, 10_000_000),
+            ),
+            500_000,
+        ),
+        &recipient,
+        &500_000,
+    );
+
+    setup
+        .client
+        .slash(&intent, &sign_intent(&env, &key, &intent));
+    // Refunded the 490_000 overcharge; the rest of the stake is forfeited.
+    assert_eq!(setup.token.balance(&recipient), 9_990_000);
+    assert_eq!(setup.client.get_fees_collected(), STAKE - 490_000);
+}
+
+#[test]
+fn test_slash_self_named_intent_forfeits_stake() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let relayer = stake_relayer(&env, &setup, &key);
+    let recipient = Address::generate(&env);
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    // The relayer fabricates an intent naming itself to take its stake back
+    // without waiting out UNBONDING_DELAY.
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: relayer.clone().into(),
+        relayer_fee: 0,
+    };
+    setup
+        .client
+        .slash(&intent, &sign_intent(&env, &key, &intent));
+
+    assert_eq!(setup.token.balance(&relayer), 0);
+    assert_eq!(setup.client.get_stake(&relayer), None);
+    assert_eq!(setup.client.get_fees_collected(), STAKE);
+}
+
+#[test]
+fn test_slash_wrong_muxed_id() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let relayer = stake_relayer(&env, &setup, &key);
+    let promised = MuxedAddress::new(setup.issuer.clone(), 1);
+
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: promised.clone(),
+        relayer_fee: 0,
+    };
+    // Same account, different memo id: the funds are misrouted all the same.
+    let diverted = MuxedAddress::new(promised.clone(), 2);
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &diverted,
+            &amount_field(&env, 10_000_000),
+        ),
+        &diverted,
+        &0,
+    );
+
+    setup
+        .client
+        .slash(&intent, &sign_intent(&env, &key, &intent));
+    assert_eq!(setup.client.get_stake(&relayer), None);
+    assert_eq!(setup.client.get_fees_collected(), STAKE);
+}
+
+#[test]
+#[should_panic(expected = "payout matches intent")]
+fn test_slash_rejects_honest_payout() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let relayer = stake_relayer(&env, &setup, &key);
+    let recipient = Address::generate(&env);
+
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: recipient.clone().into(),
+        relayer_fee: 10_000,
+    };
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV2,
+        &with_relayer_fee(
+            withdraw_inputs(
+                &setup.client,
+                &bytes(&env, 0xa1),
+                &recipient,
+                &amount_field(&env, 10_000_000),
+            ),
+            10_000,
+        ),
+        &recipient,
+        &10_000,
+    );
+
+    setup
+        .client
+        .slash(&intent, &sign_intent(&env, &key, &intent));
+}
+
+#[test]
+fn test_slash_rejects_forged_intent() {
+    let env = Env::default();
+    let setup = setup(&env, 10_000_000);
+    let relayer = stake_relayer(&env, &setup, &SigningKey::from_bytes(&[7; 32]));
+    let recipient = Address::generate(&env);
+    setup.client.withdraw(
+        &relayer,
+        &PublicInputLayout::WithdrawV1,
+        &withdraw_inputs(
+            &setup.client,
+            &bytes(&env, 0xa1),
+            &recipient,
+            &amount_field(&env, 10_000_000),
+        ),
+        &recipient,
+        &0,
+    );
+
+    // An intent the relayer never signed.
+    let intent = WithdrawalIntent {
+        pool: setup.client.address.clone(),
+        relayer: relayer.clone(),
+        nullifier_hash: bytes(&env, 0xa1),
+        recipient: Address::generate(&env).into(),
+        relayer_fee: 0,
+    };
+    let forged = sign_intent(&env, &SigningKey::from_bytes(&[8; 32]), &intent);
+    assert!(setup.client.try_slash(&intent, &forged).is_err());
+
+    // A signed intent for a withdrawal the relayer did not submit.
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let unsubmitted = WithdrawalIntent {
+        nullifier_hash: bytes(&env, 0xa2),
+        ..intent
+    };
+    assert!(setup
+        .client
+        .try_slash(&unsubmitted, &sign_intent(&env, &key, &unsubmitted))
+        .is_err());
+    assert_eq!(setup.client.get_total_staked(), STAKE);
+}
+
+// ---------------------------------------------------------------------------
+// Budget regression
+//
+// Resources metered for each entry point, per pool depth. Contracts are
+// registered natively, so VM instantiation is not included; the numbers
+// track what this crate's code costs. Limits carry about 10% headroom over the
+// measured values; see `rotor_merkle::testutils`.
+// ---------------------------------------------------------------------------
+
+/// First deposit: every level is a left child, so every frontier level
+/// changes. The frontier and root history are one entry each; with an entry
+/// per level and per slot this was 25 reads, 24 writes and 3572 bytes at
+/// depth 20.
+const DEPOSIT_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(13_200_000, 840_000, 6, 5, 1_500)),
+    (20, cost(32_600_000, 2_040_000, 6, 5, 1_900)),
+    (32, cost(52_100_000, 3_250_000, 6, 5, 2_540)),
+];
+/// First deposit into a pool built with `store_leaves = false`: one entry
+/// fewer, and none left behind to pay rent on.
+const DEPOSIT_EVENT_ONLY_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(13_200_000, 840_000, 5, 4, 1_340)),
+    (20, cost(32_600_000, 2_040_000, 5, 4, 1_800)),
+    (32, cost(52_100_000, 3_250_000, 5, 4, 2_400)),
+];
+/// Full withdrawal with a protocol fee, including the SAC transfer. Counting
+/// the fee rewrites the instance entry: one write and ~500 bytes more than
+/// without a fee. Withdrawals hash nothing, so depth does not change the cost.
+const WITHDRAW_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(385_000, 66_000, 10, 5, 1_360)),
+    (20, cost(385_000, 66_000, 10, 5, 1_360)),
+    (32, cost(385_000, 66_000, 10, 5, 1_360)),
+];
+/// A root missing from a full history: all 30 ring buffer slots are compared,
+/// from one entry rather than the 30 it used to take.
+const IS_VALID_ROOT_BUDGET: [(u32, Cost); 3] = [
+    (8, cost(93_000, 17_000, 2, 0, 0)),
+    (20, cost(93_000, 17_000, 2, 0, 0)),
+    (32, cost(93_000, 17_000, 2, 0, 0)),
+];
+
+#[test]
+fn test_deposit_budget() {
+    for (depth, limit) in DEPOSIT_BUDGET {
+        let env = prop_env();
+        let client = setup_with(&env, 0, depth, 0, true).client;
+
+        client.deposit(&Address::generate(&env), &bytes(&env, 1));
+        Cost::of_last_call(&env).check(&std::format!("deposit depth {depth}"), limit);
+    }
+}
+
+#[test]
+fn test_deposit_event_only_budget() {
+    for (depth, limit) in DEPOSIT_EVENT_ONLY_BUDGET {
+        let env = prop_env();
+        let client = setup_with(&env, 0, depth, 0, false).client;
+
+        client.deposit(&Address::generate(&env), &bytes(&env, 1));
+        Cost::of_last_call(&env).check(&std::format!("deposit (event only) depth {depth}"), limit);
+    }
+}
+
+#[test]
+fn test_withdraw_budget() {
+    for (depth, limit) in WITHDRAW_BUDGET {
+        let env = prop_env();
+        let Setup {
+            admin,
+            relayer,
+            client,
+            ..
+        } = setup_with(&env, 10_000_000, depth, 0, true);
+        client.deposit(&Address::generate(&env), &bytes(&env, 1));
+        govern(&env, &client, &admin, Action::SetFee(MAX_FEE_BPS));
+
+        let recipient = Address::generate(&env);
+        client.withdraw(
+            &relayer,
+            &PublicInputLayout::WithdrawV1,
+            &withdraw_inputs(
+                &client,
+                &bytes(&env, 0xa1),
+                &recipient,
+                &amount_field(&env, 10_000_000),
+            ),
+            &recipient,
+            &0,
+        );
+        Cost::of_last_call(&env).check(&std::format!("withdraw depth {depth}"), limit);
+    }
+}
+
+#[test]
+fn test_is_valid_root_budget() {
+    for (depth, limit) in IS_VALID_ROOT_BUDGET {
+        let env = prop_env();
+        let client = setup_with(&env, 0, depth, 0, true).client;
+        let depositor = Address::generate(&env);
+        for i in 0..30 {
+            client.deposit(&depositor, &bytes(&env, i + 1));
+        }
+
+        assert!(!client.is_valid_root(&bytes(&env, 0xff)));
+        Cost::of_last_call(&env).check(&std::format!("is_valid_root depth {depth}"), limit);
+    }
+}
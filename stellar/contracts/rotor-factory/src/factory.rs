@@ -0,0 +1,187 @@
+use rotor_admin::AdminSet;
+use soroban_sdk::{
+    contract, contractevent, contractimpl, contracttype, log, xdr::ToXdr, Address, Bytes, BytesN,
+    Env, Vec,
+};
+
+// ---------------------------------------------------------------------------
+// Storage keys
+// ---------------------------------------------------------------------------
+#[contracttype]
+pub enum DataKey {
+    PoolWasm,            // BytesN<32>: hash of the uploaded rotor-core WASM
+    Pool(Address, i128), // Address: pool deployed for (token, denomination)
+    Pools,               // Vec<PoolInfo>: every pool deployed, in order
+}
+
+/// A pool in the registry. The pool was constructed with `denomination`, so
+/// every deposit into it pulls exactly that amount.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolInfo {
+    pub token: Address,
+    pub denomination: i128,
+    pub pool: Address,
+}
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+/// Emitted for every pool deployed, so indexers can follow the registry.
+#[contractevent]
+pub struct PoolDeployed {
+    #[topic]
+    pub token: Address,
+    #[topic]
+    pub denomination: i128,
+    pub pool: Address,
+}
+
+// ---------------------------------------------------------------------------
+// Contract
+// ---------------------------------------------------------------------------
+#[contract]
+pub struct RotorFactory;
+
+#[contractimpl]
+impl RotorFactory {
+    /// - `admin`:     the factory's admin set, as a single signer. It deploys
+    ///                pools and sets the pool WASM, and can hand over to an
+    ///                M-of-N signer set with `propose_admin`.
+    /// - `pool_wasm`: hash of the rotor-core WASM, uploaded beforehand with
+    ///                `stellar contract upload`.
+    pub fn __constructor(env: Env, admin: Address, pool_wasm: BytesN<32>) {
+        rotor_admin::init(&env, &AdminSet::single(&env, admin));
+        env.storage().instance().set(&DataKey::PoolWasm, &pool_wasm);
+    }
+
+    // -----------------------------------------------------------------------
+    // ADMIN
+    // -----------------------------------------------------------------------
+
+    /// Deploy a rotor-core pool for `(token, denomination)` and register it.
+    ///
+    /// The pool's address depends only on this factory, the token and the
+    /// denomination (see `pool_address`), so it is known before deployment.
+    /// At most one pool exists per pair. `admin` becomes the pool's admin
+    /// and first holder of its roles, `depth` is its tree depth, as in the
+    /// rotor-core constructor, and the pool enforces `denomination` on every
+    /// deposit (see rotor-core's `get_denomination`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn deploy_pool(
+        env: Env,
+        approvers: Vec<Address>,
+        token: Address,
+        denomination: i128,
+        admin: Address,
+        relayer: Address,
+        depth: u32,
+        store_leaves: bool,
+    ) -> Address {
+        rotor_admin::require_admin(&env, &approvers);
+        assert!(denomination > 0, "denomination must be positive");
+
+        let key = DataKey::Pool(token.clone(), denomination);
+        assert!(!env.storage().persistent().has(&key), "pool already exists");
+
+        let wasm: BytesN<32> = env.storage().instance().get(&DataKey::PoolWasm).unwrap();
+        let pool = env
+            .deployer()
+            .with_current_contract(Self::salt(&env, &token, denomination))
+            .deploy_v2(
+                wasm,
+                (
+                    admin,
+                    relayer,
+                    token.clone(),
+                    depth,
+                    denomination,
+                    store_leaves,
+                ),
+            );
+
+        env.storage().persistent().set(&key, &pool);
+        let mut pools = Self::list_pools(env.clone());
+        pools.push_back(PoolInfo {
+            token: token.clone(),
+            denomination,
+            pool: pool.clone(),
+        });
+        env.storage().persistent().set(&DataKey::Pools, &pools);
+
+        PoolDeployed {
+            token,
+            denomination,
+            pool: pool.clone(),
+        }
+        .publish(&env);
+        log!(&env, "Pool deployed: {}", pool);
+
+        pool
+    }
+
+    /// Use `pool_wasm` for pools deployed from now on. Existing pools keep
+    /// their code.
+    pub fn set_pool_wasm(env: Env, approvers: Vec<Address>, pool_wasm: BytesN<32>) {
+        rotor_admin::require_admin(&env, &approvers);
+        env.storage().instance().set(&DataKey::PoolWasm, &pool_wasm);
+    }
+
+    /// Propose `admin` to replace the current admin set. Nothing changes
+    /// until every signer of `admin` calls `accept_admin`.
+    pub fn propose_admin(env: Env, approvers: Vec<Address>, admin: AdminSet) {
+        rotor_admin::propose(&env, &approvers, &admin);
+    }
+
+    pub fn accept_admin(env: Env) {
+        rotor_admin::accept(&env);
+    }
+
+    // -----------------------------------------------------------------------
+    // VIEW FUNCTIONS
+    // -----------------------------------------------------------------------
+
+    pub fn get_pool(env: Env, token: Address, denomination: i128) -> Option<Address> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Pool(token, denomination))
+    }
+
+    pub fn list_pools(env: Env) -> Vec<PoolInfo> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Pools)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Address the pool for `(token, denomination)` has or will have.
+    pub fn pool_address(env: Env, token: Address, denomination: i128) -> Address {
+        env.deployer()
+            .with_current_contract(Self::salt(&env, &token, denomination))
+            .deployed_address()
+    }
+
+    pub fn get_pool_wasm(env: Env) -> BytesN<32> {
+        env.storage().instance().get(&DataKey::PoolWasm).unwrap()
+    }
+
+    pub fn get_admin(env: Env) -> AdminSet {
+        rotor_admin::get(&env)
+    }
+
+    pub fn get_pending_admin(env: Env) -> Option<AdminSet> {
+        rotor_admin::pending(&env)
+    }
+
+    // -----------------------------------------------------------------------
+    // INTERNAL
+    // -----------------------------------------------------------------------
+
+    /// `sha256(xdr(token) || denomination as 16 big-endian bytes)`.
+    fn salt(env: &Env, token: &Address, denomination: i128) -> BytesN<32> {
+        let mut preimage: Bytes = token.clone().to_xdr(env);
+        preimage.extend_from_array(&denomination.to_be_bytes());
+        env.crypto().sha256(&preimage).into()
+    }
+}
@@ -0,0 +1,5 @@
+#![no_std]
+
+pub mod factory;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,276 @@
+#![cfg(test)]
+extern crate std;
+
+use crate::factory::{PoolDeployed, PoolInfo, RotorFactory, RotorFactoryClient};
+use rotor_admin::{roles::Role, AdminSet};
+use rotor_core::contract::RotorCoreClient;
+use soroban_sdk::{
+    testutils::{Address as _, Events as _, MockAuth, MockAuthInvoke},
+    vec, Address, Bytes, BytesN, Env, Event as _, IntoVal, Vec,
+};
+
+const ROTOR_CORE_WASM: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../target/wasm32v1-none/release/rotor_core.wasm"
+);
+const NEEDS_WASM: &str = "needs the rotor-core WASM; run `make test`";
+
+struct Setup<'a> {
+    admin: Address,
+    /// `admin` alone, as approvers of the factory's admin calls.
+    approvers: Vec<Address>,
+    relayer: Address,
+    factory: RotorFactoryClient<'a>,
+}
+
+fn setup<'a>(env: &'a Env, pool_wasm: &BytesN<32>) -> Setup<'a> {
+    env.mock_all_auths();
+
+    let admin = Address::generate(env);
+    let factory_id = env.register(RotorFactory, (&admin, pool_wasm));
+
+    Setup {
+        approvers: vec![env, admin.clone()],
+        admin,
+        relayer: Address::generate(env),
+        factory: RotorFactoryClient::new(env, &factory_id),
+    }
+}
+
+/// Upload the rotor-core WASM built by `stellar contract build`.
+fn upload_pool_wasm(env: &Env) -> BytesN<32> {
+    let wasm = std::fs::read(ROTOR_CORE_WASM)
+        .unwrap_or_else(|_| panic!("{ROTOR_CORE_WASM} missing; {NEEDS_WASM}"));
+    env.deployer()
+        .upload_contract_wasm(Bytes::from_slice(env, &wasm))
+}
+
+fn token(env: &Env) -> Address {
+    env.register_stellar_asset_contract_v2(Address::generate(env))
+        .address()
+}
+
+#[test]
+fn test_constructor_initial_state() {
+    let env = Env::default();
+    let wasm = BytesN::from_array(&env, &[1; 32]);
+    let Setup { admin, factory, .. } = setup(&env, &wasm);
+
+    assert_eq!(factory.get_admin(), AdminSet::single(&env, admin));
+    assert_eq!(factory.get_pending_admin(), None);
+    assert_eq!(factory.get_pool_wasm(), wasm);
+    assert_eq!(factory.list_pools(), vec![&env]);
+    assert_eq!(factory.get_pool(&token(&env), &10_000_000), None);
+}
+
+#[test]
+fn test_pool_address_is_deterministic() {
+    let env = Env::default();
+    let wasm = BytesN::from_array(&env, &[1; 32]);
+    let a = setup(&env, &wasm).factory;
+    let b = setup(&env, &wasm).factory;
+    let (xlm, usdc) = (token(&env), token(&env));
+
+    let pool = a.pool_address(&xlm, &10_000_000);
+    assert_eq!(a.pool_address(&xlm, &10_000_000), pool);
+    assert_ne!(a.pool_address(&xlm, &100_000_000), pool);
+    assert_ne!(a.pool_address(&usdc, &10_000_000), pool);
+    assert_ne!(b.pool_address(&xlm, &10_000_000), pool);
+}
+
+#[test]
+fn test_deploy_pool_requires_admin() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        approvers,
+        relayer,
+        factory,
+    } = setup(&env, &BytesN::from_array(&env, &[1; 32]));
+
+    env.set_auths(&[]);
+    assert!(factory
+        .try_deploy_pool(
+            &approvers,
+            &token(&env),
+            &10_000_000,
+            &admin,
+            &relayer,
+            &20,
+            &true
+        )
+        .is_err());
+    assert_eq!(factory.list_pools(), vec![&env]);
+}
+
+#[test]
+#[should_panic(expected = "denomination must be positive")]
+fn test_deploy_pool_rejects_zero_denomination() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        approvers,
+        relayer,
+        factory,
+    } = setup(&env, &BytesN::from_array(&env, &[1; 32]));
+
+    factory.deploy_pool(&approvers, &token(&env), &0, &admin, &relayer, &20, &true);
+}
+
+#[test]
+fn test_set_pool_wasm() {
+    let env = Env::default();
+    let Setup {
+        approvers, factory, ..
+    } = setup(&env, &BytesN::from_array(&env, &[1; 32]));
+
+    let next = BytesN::from_array(&env, &[2; 32]);
+    factory.set_pool_wasm(&approvers, &next);
+    assert_eq!(factory.get_pool_wasm(), next);
+
+    env.set_auths(&[]);
+    assert!(factory
+        .try_set_pool_wasm(&approvers, &BytesN::from_array(&env, &[3; 32]))
+        .is_err());
+    assert_eq!(factory.get_pool_wasm(), next);
+}
+
+#[test]
+fn test_admin_hands_over_to_multisig() {
+    let env = Env::default();
+    let Setup {
+        admin,
+        approvers,
+        factory,
+        ..
+    } = setup(&env, &BytesN::from_array(&env, &[1; 32]));
+    let signers = vec![
+        &env,
+        Address::generate(&env),
+        Address::generate(&env),
+        Address::generate(&env),
+    ];
+    let multisig = AdminSet {
+        signers: signers.clone(),
+        threshold: 2,
+    };
+
+    factory.propose_admin(&approvers, &multisig);
+    assert_eq!(factory.get_pending_admin(), Some(multisig.clone()));
+    factory.accept_admin();
+    assert_eq!(factory.get_admin(), multisig);
+    assert_eq!(factory.get_pending_admin(), None);
+
+    // The old admin is out, and one signer alone is not enough.
+    let next = BytesN::from_array(&env, &[2; 32]);
+    assert!(factory.try_set_pool_wasm(&approvers, &next).is_err());
+    assert!(factory
+        .try_set_pool_wasm(&vec![&env, signers.get(0).unwrap()], &next)
+        .is_err());
+    factory.set_pool_wasm(
+        &vec![&env, signers.get(0).unwrap(), signers.get(2).unwrap()],
+        &next,
+    );
+    assert_eq!(factory.get_pool_wasm(), next);
+    assert!(!multisig.signers.contains(&admin));
+}
+
+#[test]
+#[ignore = "needs the rotor-core WASM; run `make test`"]
+fn test_deploy_pool() {
+    let env = Env::default();
+    let wasm = upload_pool_wasm(&env);
+    let Setup {
+        admin,
+        approvers,
+        relayer,
+        factory,
+    } = setup(&env, &wasm);
+    let xlm = token(&env);
+
+    let pool_admin = Address::generate(&env);
+    let expected = factory.pool_address(&xlm, &10_000_000);
+    let pool = factory.deploy_pool(
+        &approvers,
+        &xlm,
+        &10_000_000,
+        &pool_admin,
+        &relayer,
+        &20,
+        &false,
+    );
+    assert_eq!(pool, expected);
+    assert_eq!(
+        env.events().all().filter_by_contract(&factory.address),
+        [PoolDeployed {
+            token: xlm.clone(),
+            denomination: 10_000_000,
+            pool: pool.clone(),
+        }
+        .to_xdr(&env, &factory.address)]
+    );
+
+    assert_eq!(factory.get_pool(&xlm, &10_000_000), Some(pool.clone()));
+    assert_eq!(
+        factory.list_pools(),
+        vec![
+            &env,
+            PoolInfo {
+                token: xlm.clone(),
+                denomination: 10_000_000,
+                pool: pool.clone(),
+            }
+        ]
+    );
+
+    // The pool is a working rotor-core instance built with the given options.
+    let core = RotorCoreClient::new(&env, &pool);
+    assert_eq!(core.get_token(), xlm);
+    assert_eq!(core.get_depth(), 20);
+    assert_eq!(core.get_denomination(), 10_000_000);
+    assert!(!core.stores_leaves());
+    assert_eq!(core.get_next_index(), 0);
+    assert!(core.is_valid_root(&core.get_latest_root()));
+
+    // The admin named at deployment administers the pool, not the factory's.
+    assert_eq!(core.get_admin(), AdminSet::single(&env, pool_admin.clone()));
+    assert!(!core.has_role(&Role::Pauser, &admin));
+    env.mock_auths(&[MockAuth {
+        address: &pool_admin,
+        invoke: &MockAuthInvoke {
+            contract: &pool,
+            fn_name: "pause",
+            args: (&pool_admin,).into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    core.pause(&pool_admin);
+    assert!(core.is_paused());
+}
+
+#[test]
+#[ignore = "needs the rotor-core WASM; run `make test`"]
+fn test_one_pool_per_token_and_denomination() {
+    let env = Env::default();
+    let wasm = upload_pool_wasm(&env);
+    let Setup {
+        admin,
+        approvers,
+        relayer,
+        factory,
+    } = setup(&env, &wasm);
+    let (xlm, usdc) = (token(&env), token(&env));
+
+    let small = factory.deploy_pool(&approvers, &xlm, &10_000_000, &admin, &relayer, &20, &true);
+    let large = factory.deploy_pool(&approvers, &xlm, &100_000_000, &admin, &relayer, &20, &true);
+    let other = factory.deploy_pool(&approvers, &usdc, &10_000_000, &admin, &relayer, &20, &true);
+    assert_ne!(small, large);
+    assert_ne!(small, other);
+
+    assert!(factory
+        .try_deploy_pool(&approvers, &xlm, &10_000_000, &admin, &relayer, &20, &false)
+        .is_err());
+    assert_eq!(factory.get_pool(&xlm, &10_000_000), Some(small));
+    assert_eq!(factory.list_pools().len(), 3);
+    assert_eq!(factory.get_pool(&usdc, &100_000_000), None);
+}
@@ -0,0 +1,158 @@
+//! Admin set shared by the Rotor contracts.
+//!
+//! A contract's admin is a set of signers and a threshold. Privileged entry
+//! points take the `approvers` signing the call and check with
+//! [`require_admin`] that at least `threshold` distinct signers are among
+//! them, calling `require_auth` on each. A single admin is a set of one with
+//! threshold 1.
+//!
+//! The set changes in two steps: the current admin proposes a new set
+//! ([`propose`]), then every signer of the new set authorizes [`accept`].
+//! A mistyped or lost key in a proposal therefore cannot lock the contract.
+//!
+//! State lives in the contract's instance storage under [`AdminKey`].
+//!
+//! The admin set governs the [`roles`] registry; day-to-day privileged calls
+//! need a role, not the admin set.
+#![no_std]
+
+use soroban_sdk::{contractevent, contracttype, Address, Env, Vec};
+
+pub mod roles;
+
+#[cfg(test)]
+mod test;
+
+/// Largest signer set, which bounds the auth checks per privileged call.
+pub const MAX_SIGNERS: u32 = 10;
+
+#[contracttype]
+pub enum AdminKey {
+    AdminSet,        // AdminSet: current signers and threshold
+    PendingAdminSet, // AdminSet: proposed by the admin, not yet accepted
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdminSet {
+    pub signers: Vec<Address>,
+    pub threshold: u32,
+}
+
+impl AdminSet {
+    /// A single admin.
+    pub fn single(env: &Env, admin: Address) -> Self {
+        Self {
+            signers: Vec::from_array(env, [admin]),
+            threshold: 1,
+        }
+    }
+
+    /// Panic unless this is a usable admin set: 1 to `MAX_SIGNERS` distinct
+    /// signers and a threshold no larger than the signer count.
+    pub fn validate(&self) {
+        let n = self.signers.len();
+        assert!(n > 0 && n <= MAX_SIGNERS, "admin takes 1..=10 signers");
+        assert!(
+            self.threshold > 0 && self.threshold <= n,
+            "threshold must be 1..=signers"
+        );
+        assert!(!has_duplicates(&self.signers), "duplicate admin signer");
+    }
+}
+
+#[contractevent]
+pub struct AdminProposed {
+    pub signers: Vec<Address>,
+    pub threshold: u32,
+}
+
+#[contractevent]
+pub struct AdminChanged {
+    pub signers: Vec<Address>,
+    pub threshold: u32,
+}
+
+/// Set the initial admin. Call once, from the constructor.
+pub fn init(env: &Env, admin: &AdminSet) {
+    admin.validate();
+    env.storage().instance().set(&AdminKey::AdminSet, admin);
+}
+
+pub fn get(env: &Env) -> AdminSet {
+    try_get(env).expect("admin not set")
+}
+
+pub fn try_get(env: &Env) -> Option<AdminSet> {
+    env.storage().instance().get(&AdminKey::AdminSet)
+}
+
+pub fn pending(env: &Env) -> Option<AdminSet> {
+    env.storage().instance().get(&AdminKey::PendingAdminSet)
+}
+
+/// Require that `approvers` are at least `threshold` distinct admin signers,
+/// and their auth.
+pub fn require_admin(env: &Env, approvers: &Vec<Address>) {
+    let admin = get(env);
+    assert!(
+        approvers.len() >= admin.threshold,
+        "not enough admin approvals"
+    );
+    assert!(!has_duplicates(approvers), "duplicate approver");
+    for approver in approvers.iter() {
+        assert!(
+            admin.signers.contains(&approver),
+            "approver is not an admin signer"
+        );
+        approver.require_auth();
+    }
+}
+
+/// Propose `next` as the admin, replacing any earlier proposal. It takes
+/// effect once accepted.
+pub fn propose(env: &Env, approvers: &Vec<Address>, next: &AdminSet) {
+    require_admin(env, approvers);
+    propose_unchecked(env, next);
+}
+
+/// Propose `next` without an admin check, for callers that checked the
+/// admin set earlier, such as a timelock executing an approved action.
+pub fn propose_unchecked(env: &Env, next: &AdminSet) {
+    next.validate();
+
+    env.storage()
+        .instance()
+        .set(&AdminKey::PendingAdminSet, next);
+    AdminProposed {
+        signers: next.signers.clone(),
+        threshold: next.threshold,
+    }
+    .publish(env);
+}
+
+/// Make the pending proposal the admin. Every proposed signer must authorize,
+/// not just a threshold of them.
+pub fn accept(env: &Env) {
+    let next = pending(env).expect("no pending admin");
+    for signer in next.signers.iter() {
+        signer.require_auth();
+    }
+
+    env.storage().instance().set(&AdminKey::AdminSet, &next);
+    env.storage().instance().remove(&AdminKey::PendingAdminSet);
+    AdminChanged {
+        signers: next.signers,
+        threshold: next.threshold,
+    }
+    .publish(env);
+}
+
+fn has_duplicates(addresses: &Vec<Address>) -> bool {
+    for (i, a) in addresses.iter().enumerate() {
+        if addresses.iter().skip(i + 1).any(|b| a == b) {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,106 @@
+//! Role registry.
+//!
+//! Privileged entry points each need one [`Role`] rather than the admin set,
+//! so the key that pauses a pool need not be the one that upgrades it. The
+//! admin set grants and revokes roles; an account may hold several, and a
+//! role any number of accounts.
+//!
+//! Grants live in persistent storage under [`RoleKey`].
+
+use soroban_sdk::{contractevent, contracttype, Address, Env, Vec};
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Role {
+    Pauser = 0,         // pause and unpause withdrawals
+    Upgrader = 1,       // replace the contract code and migrate storage
+    FeeManager = 2,     // set the fee and treasury, send fees to the treasury
+    RelayerManager = 3, // choose the relayer and the minimum relayer stake
+    LimitManager = 4,   // set and reset withdrawal rate limits
+    Inserter = 5,       // insert leaves into a standalone tree
+}
+
+/// Every role, e.g. to grant them all to a contract's first admin.
+pub const ALL_ROLES: [Role; 6] = [
+    Role::Pauser,
+    Role::Upgrader,
+    Role::FeeManager,
+    Role::RelayerManager,
+    Role::LimitManager,
+    Role::Inserter,
+];
+
+#[contracttype]
+pub enum RoleKey {
+    Role(Role, Address), // bool: account holds role
+}
+
+#[contractevent]
+pub struct RoleGranted {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+}
+
+#[contractevent]
+pub struct RoleRevoked {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+}
+
+pub fn has_role(env: &Env, role: Role, account: &Address) -> bool {
+    env.storage()
+        .persistent()
+        .get(&RoleKey::Role(role, account.clone()))
+        .unwrap_or(false)
+}
+
+/// Require that `caller` holds `role`, and its auth.
+pub fn require_role(env: &Env, caller: &Address, role: Role) {
+    assert!(has_role(env, role, caller), "caller lacks role");
+    caller.require_auth();
+}
+
+/// Grant `role` to `account` without an admin check. For constructors and
+/// migrations; entry points use [`grant_role`].
+pub fn grant_unchecked(env: &Env, role: Role, account: &Address) {
+    env.storage()
+        .persistent()
+        .set(&RoleKey::Role(role, account.clone()), &true);
+    RoleGranted {
+        role,
+        account: account.clone(),
+    }
+    .publish(env);
+}
+
+/// Grant `role` to `account`, approved by the admin set.
+pub fn grant_role(env: &Env, approvers: &Vec<Address>, role: Role, account: &Address) {
+    crate::require_admin(env, approvers);
+    assert!(!has_role(env, role, account), "role already granted");
+    grant_unchecked(env, role, account);
+}
+
+/// Revoke `role` from `account`, approved by the admin set.
+pub fn revoke_role(env: &Env, approvers: &Vec<Address>, role: Role, account: &Address) {
+    crate::require_admin(env, approvers);
+    assert!(has_role(env, role, account), "role not granted");
+    revoke_unchecked(env, role, account);
+}
+
+/// Revoke `role` from `account` without an admin check. Entry points use
+/// [`revoke_role`].
+pub fn revoke_unchecked(env: &Env, role: Role, account: &Address) {
+    env.storage()
+        .persistent()
+        .remove(&RoleKey::Role(role, account.clone()));
+    RoleRevoked {
+        role,
+        account: account.clone(),
+    }
+    .publish(env);
+}
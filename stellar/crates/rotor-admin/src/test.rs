@@ -0,0 +1,355 @@
+#![cfg(test)]
+extern crate std;
+
+use crate::{
+    roles::{Role, RoleGranted, RoleRevoked},
+    AdminChanged, AdminProposed, AdminSet,
+};
+use soroban_sdk::{
+    contract, contractimpl,
+    testutils::{Address as _, AuthorizedFunction, Events as _, MockAuth, MockAuthInvoke},
+    vec, Address, Env, Event as _, IntoVal, Vec,
+};
+
+/// A contract with one privileged entry point, to exercise the module.
+#[contract]
+struct Guarded;
+
+#[contractimpl]
+impl Guarded {
+    pub fn __constructor(env: Env, admin: AdminSet) {
+        crate::init(&env, &admin);
+    }
+
+    pub fn guarded(env: Env, approvers: Vec<Address>) {
+        crate::require_admin(&env, &approvers);
+    }
+
+    pub fn propose_admin(env: Env, approvers: Vec<Address>, admin: AdminSet) {
+        crate::propose(&env, &approvers, &admin);
+    }
+
+    pub fn accept_admin(env: Env) {
+        crate::accept(&env);
+    }
+
+    pub fn get_admin(env: Env) -> AdminSet {
+        crate::get(&env)
+    }
+
+    pub fn get_pending_admin(env: Env) -> Option<AdminSet> {
+        crate::pending(&env)
+    }
+
+    pub fn pause(env: Env, caller: Address) {
+        crate::roles::require_role(&env, &caller, Role::Pauser);
+    }
+
+    pub fn grant_role(env: Env, approvers: Vec<Address>, role: Role, account: Address) {
+        crate::roles::grant_role(&env, &approvers, role, &account);
+    }
+
+    pub fn revoke_role(env: Env, approvers: Vec<Address>, role: Role, account: Address) {
+        crate::roles::revoke_role(&env, &approvers, role, &account);
+    }
+
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        crate::roles::has_role(&env, role, &account)
+    }
+}
+
+fn signers(env: &Env, n: usize) -> Vec<Address> {
+    let mut signers = Vec::new(env);
+    for _ in 0..n {
+        signers.push_back(Address::generate(env));
+    }
+    signers
+}
+
+fn register<'a>(env: &'a Env, admin: &AdminSet) -> GuardedClient<'a> {
+    GuardedClient::new(env, &env.register(Guarded, (admin,)))
+}
+
+#[test]
+fn test_single_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, admin.clone()));
+
+    client.guarded(&vec![&env, admin.clone()]);
+    assert_eq!(
+        env.auths()[0].1.function,
+        AuthorizedFunction::Contract((
+            client.address.clone(),
+            "guarded".into_val(&env),
+            (vec![&env, admin.clone()],).into_val(&env),
+        ))
+    );
+
+    assert!(client.try_guarded(&vec![&env]).is_err());
+    assert!(client
+        .try_guarded(&vec![&env, Address::generate(&env)])
+        .is_err());
+}
+
+#[test]
+fn test_threshold_requires_distinct_signers() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let s = signers(&env, 3);
+    let client = register(
+        &env,
+        &AdminSet {
+            signers: s.clone(),
+            threshold: 2,
+        },
+    );
+    let (a, b, c) = (s.get(0).unwrap(), s.get(1).unwrap(), s.get(2).unwrap());
+
+    assert!(client.try_guarded(&vec![&env, a.clone()]).is_err());
+    assert!(client
+        .try_guarded(&vec![&env, a.clone(), a.clone()])
+        .is_err());
+    assert!(client
+        .try_guarded(&vec![&env, a.clone(), Address::generate(&env)])
+        .is_err());
+
+    client.guarded(&vec![&env, a.clone(), c.clone()]);
+    client.guarded(&vec![&env, c.clone(), b.clone(), a.clone()]);
+}
+
+#[test]
+fn test_threshold_requires_each_approvers_auth() {
+    let env = Env::default();
+    let s = signers(&env, 3);
+    let client = register(
+        &env,
+        &AdminSet {
+            signers: s.clone(),
+            threshold: 2,
+        },
+    );
+    let approvers = vec![&env, s.get(0).unwrap(), s.get(1).unwrap()];
+    let invoke = MockAuthInvoke {
+        contract: &client.address,
+        fn_name: "guarded",
+        args: (&approvers,).into_val(&env),
+        sub_invokes: &[],
+    };
+    let (first, second) = (s.get(0).unwrap(), s.get(1).unwrap());
+
+    // One leaked key is not enough.
+    env.mock_auths(&[MockAuth {
+        address: &first,
+        invoke: &invoke,
+    }]);
+    assert!(client.try_guarded(&approvers).is_err());
+
+    env.mock_auths(&[
+        MockAuth {
+            address: &first,
+            invoke: &invoke,
+        },
+        MockAuth {
+            address: &second,
+            invoke: &invoke,
+        },
+    ]);
+    client.guarded(&approvers);
+}
+
+#[test]
+fn test_propose_and_accept() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let old = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, old.clone()));
+    let next = AdminSet {
+        signers: signers(&env, 2),
+        threshold: 2,
+    };
+
+    client.propose_admin(&vec![&env, old.clone()], &next);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [AdminProposed {
+            signers: next.signers.clone(),
+            threshold: 2,
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert_eq!(client.get_pending_admin(), Some(next.clone()));
+
+    // The old admin stays in charge until the proposal is accepted.
+    client.guarded(&vec![&env, old.clone()]);
+
+    // Accepting needs every new signer, not just the threshold.
+    let first = next.signers.get(0).unwrap();
+    env.mock_auths(&[MockAuth {
+        address: &first,
+        invoke: &MockAuthInvoke {
+            contract: &client.address,
+            fn_name: "accept_admin",
+            args: ().into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(client.try_accept_admin().is_err());
+
+    env.mock_all_auths();
+    client.accept_admin();
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [AdminChanged {
+            signers: next.signers.clone(),
+            threshold: 2,
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert_eq!(client.get_admin(), next);
+    assert_eq!(client.get_pending_admin(), None);
+    assert!(client.try_guarded(&vec![&env, old]).is_err());
+    client.guarded(&next.signers);
+}
+
+#[test]
+fn test_propose_requires_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, admin));
+
+    assert!(client
+        .try_propose_admin(
+            &vec![&env, attacker.clone()],
+            &AdminSet::single(&env, attacker)
+        )
+        .is_err());
+    assert_eq!(client.get_pending_admin(), None);
+    assert!(client.try_accept_admin().is_err());
+}
+
+#[test]
+fn test_rejects_invalid_sets() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, admin.clone()));
+    let approvers = vec![&env, admin.clone()];
+    let s = signers(&env, 3);
+
+    for invalid in [
+        AdminSet {
+            signers: vec![&env],
+            threshold: 0,
+        },
+        AdminSet {
+            signers: s.clone(),
+            threshold: 0,
+        },
+        AdminSet {
+            signers: s.clone(),
+            threshold: 4,
+        },
+        AdminSet {
+            signers: vec![&env, admin.clone(), admin.clone()],
+            threshold: 1,
+        },
+        AdminSet {
+            signers: signers(&env, 11),
+            threshold: 6,
+        },
+    ] {
+        assert!(client.try_propose_admin(&approvers, &invalid).is_err());
+    }
+    assert_eq!(client.get_pending_admin(), None);
+}
+
+#[test]
+fn test_grant_and_revoke_role() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, admin.clone()));
+    let approvers = vec![&env, admin.clone()];
+    let on_call = Address::generate(&env);
+
+    // The admin set grants roles but holds none itself.
+    assert!(client.try_pause(&admin).is_err());
+    assert!(client.try_pause(&on_call).is_err());
+
+    client.grant_role(&approvers, &Role::Pauser, &on_call);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [RoleGranted {
+            role: Role::Pauser,
+            account: on_call.clone(),
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert!(client.has_role(&Role::Pauser, &on_call));
+    assert!(!client.has_role(&Role::Upgrader, &on_call));
+    client.pause(&on_call);
+
+    client.revoke_role(&approvers, &Role::Pauser, &on_call);
+    assert_eq!(
+        env.events().all().filter_by_contract(&client.address),
+        [RoleRevoked {
+            role: Role::Pauser,
+            account: on_call.clone(),
+        }
+        .to_xdr(&env, &client.address)]
+    );
+    assert!(!client.has_role(&Role::Pauser, &on_call));
+    assert!(client.try_pause(&on_call).is_err());
+}
+
+#[test]
+fn test_role_holder_must_sign() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let client = register(&env, &AdminSet::single(&env, admin.clone()));
+    let on_call = Address::generate(&env);
+    client.grant_role(&vec![&env, admin], &Role::Pauser, &on_call);
+
+    env.set_auths(&[]);
+    assert!(client.try_pause(&on_call).is_err());
+}
+
+#[test]
+fn test_roles_are_granted_by_admin_set() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let s = signers(&env, 2);
+    let client = register(
+        &env,
+        &AdminSet {
+            signers: s.clone(),
+            threshold: 2,
+        },
+    );
+    let attacker = Address::generate(&env);
+
+    let one = vec![&env, s.get(0).unwrap()];
+    assert!(client
+        .try_grant_role(&one, &Role::Upgrader, &attacker)
+        .is_err());
+    assert!(client
+        .try_grant_role(&vec![&env, attacker.clone()], &Role::Upgrader, &attacker)
+        .is_err());
+
+    client.grant_role(&s, &Role::Upgrader, &attacker);
+    assert!(client
+        .try_grant_role(&s, &Role::Upgrader, &attacker)
+        .is_err());
+    assert!(client
+        .try_revoke_role(&one, &Role::Upgrader, &attacker)
+        .is_err());
+    client.revoke_role(&s, &Role::Upgrader, &attacker);
+    assert!(client
+        .try_revoke_role(&s, &Role::Upgrader, &attacker)
+        .is_err());
+}
@@ -0,0 +1,348 @@
+//! `rotor` — command-line client for the Rotor shielded pool.
+//!
+//! Mirrors what the web client and relayer do, for scripting against a pool:
+//!
+//! ```text
+//! rotor note new --amount 10000000 --out note.txt
+//! rotor deposit --note note.txt
+//! rotor prover-toml --note note.txt --recipient G...   # then nargo/bb
+//! rotor withdraw --relayer http://localhost:3001 --recipient G...
+//! rotor status --note note.txt
+//! ```
+//!
+//! `--network local` targets a `stellar container start local` sandbox.
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use std::path::PathBuf;
+use stellar_xdr::curr::ScVal;
+
+mod note;
+mod pool;
+mod prover;
+mod rpc;
+mod tx;
+
+#[cfg(test)]
+mod test;
+
+use note::{address_to_field, parse_field, to_hex};
+use pool::{find_leaf, Pool};
+use tx::Signer;
+
+// ---------------------------------------------------------------------------
+// Arguments
+// ---------------------------------------------------------------------------
+#[derive(Parser)]
+#[command(
+    name = "rotor",
+    about = "Command-line client for the Rotor shielded pool"
+)]
+struct Cli {
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    /// Local quickstart sandbox.
+    Local,
+}
+
+impl Network {
+    pub fn rpc_url(self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://mainnet.sorobanrpc.com",
+            Network::Testnet => "https://soroban-testnet.stellar.org",
+            Network::Local => "http://localhost:8000/soroban/rpc",
+        }
+    }
+
+    pub fn passphrase(self) -> &'static str {
+        match self {
+            Network::Mainnet => "Public Global Stellar Network ; September 2015",
+            Network::Testnet => "Test SDF Network ; September 2015",
+            Network::Local => "Standalone Network ; February 2017",
+        }
+    }
+}
+
+#[derive(Args)]
+struct NetworkArgs {
+    /// Network preset supplying the RPC URL and passphrase.
+    #[arg(long, global = true, env = "ROTOR_NETWORK", value_enum, default_value_t = Network::Mainnet)]
+    network: Network,
+
+    /// Override the preset's Soroban RPC URL.
+    #[arg(long, global = true, env = "STELLAR_RPC")]
+    rpc_url: Option<String>,
+
+    /// Override the preset's network passphrase.
+    #[arg(long, global = true, env = "NETWORK_PASSPHRASE")]
+    network_passphrase: Option<String>,
+
+    /// rotor-core contract id (C...).
+    #[arg(long, global = true, env = "CONTRACT_ID")]
+    contract: Option<String>,
+}
+
+impl NetworkArgs {
+    fn pool(&self) -> Result<Pool> {
+        let contract = self
+            .contract
+            .as_deref()
+            .context("no contract id; pass --contract or set CONTRACT_ID")?;
+        Pool::new(
+            self.rpc_url.as_deref().unwrap_or(self.network.rpc_url()),
+            self.network_passphrase
+                .as_deref()
+                .unwrap_or(self.network.passphrase()),
+            contract,
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create notes.
+    Note {
+        #[command(subcommand)]
+        command: NoteCommand,
+    },
+    /// Fund the pool with the note's amount and record its commitment. The
+    /// note file is rewritten with the leaf index. A denominated pool must
+    /// take the note's amount, and funds itself in `deposit`.
+    Deposit {
+        #[arg(long)]
+        note: PathBuf,
+        /// Depositor secret key (S...).
+        #[arg(long, env = "ROTOR_SECRET_KEY", hide_env_values = true)]
+        secret_key: String,
+        /// Only call `deposit`; the pool was already funded. Implied by
+        /// denominated pools.
+        #[arg(long)]
+        skip_fund: bool,
+    },
+    /// Print the note's Merkle path against the current root.
+    Path {
+        #[arg(long)]
+        note: PathBuf,
+    },
+    /// Write the circuit inputs for withdrawing a note to `recipient`.
+    ProverToml {
+        #[arg(long)]
+        note: PathBuf,
+        /// Stellar address (G... or M...) the proof pays out to.
+        #[arg(long)]
+        recipient: String,
+        /// Most the relayer may take out of the note, in stroops.
+        #[arg(long, default_value_t = 0)]
+        relayer_fee: u64,
+        #[arg(long, default_value = "Prover.toml")]
+        out: PathBuf,
+    },
+    /// Submit a proof to a relayer for withdrawal.
+    Withdraw {
+        /// Relayer base URL, e.g. http://localhost:3001.
+        #[arg(long)]
+        relayer: String,
+        #[arg(long)]
+        recipient: String,
+        #[arg(long, default_value = "circuit/target/proof/proof")]
+        proof: PathBuf,
+        #[arg(long, default_value = "circuit/target/proof/public_inputs")]
+        public_inputs: PathBuf,
+    },
+    /// Check whether a note has been spent.
+    Status {
+        /// Nullifier hash (hex).
+        #[arg(long, required_unless_present = "note", conflicts_with = "note")]
+        nullifier: Option<String>,
+        #[arg(long)]
+        note: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum NoteCommand {
+    /// Generate a note for the pool with a fresh nullifier and secret.
+    New {
+        /// Amount in stroops (10000000 = 1 XLM).
+        #[arg(long)]
+        amount: u64,
+        /// Write the note here instead of stdout.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let net = &cli.network;
+
+    match cli.command {
+        Command::Note {
+            command: NoteCommand::New { amount, out },
+        } => {
+            if amount == 0 {
+                bail!("amount must be positive");
+            }
+            let note = note::generate(&net.pool()?, amount)?;
+            match out {
+                Some(path) => {
+                    note::save(&note, &path)?;
+                    println!("commitment:    {}", to_hex(&note.commitment()));
+                    println!("nullifierHash: {}", to_hex(&note.nullifier_hash()));
+                    println!("note written to {}", path.display());
+                }
+                None => println!("{note}"),
+            }
+        }
+
+        Command::Deposit {
+            note: note_path,
+            secret_key,
+            skip_fund,
+        } => {
+            let mut note = note::load(&note_path)?;
+            let pool = net.pool()?;
+            note::check_deployment(&note, &pool)?;
+            let signer = Signer::from_secret(&secret_key)?;
+            let depositor = tx::account_val(&signer.public_key());
+            println!("depositor: {}", signer.address());
+
+            // A denominated pool pulls the denomination in `deposit` itself.
+            let denomination = pool.denomination()?;
+            if denomination > 0 && denomination != note.denomination {
+                bail!(
+                    "note is worth {} stroops but the pool takes {denomination}",
+                    note.denomination
+                );
+            }
+            if !skip_fund && denomination == 0 {
+                let token = pool.token()?;
+                let (hash, _) = pool.invoke_on(
+                    &signer,
+                    &token,
+                    "transfer",
+                    vec![
+                        depositor.clone(),
+                        ScVal::Address(pool.contract.clone()),
+                        note.denomination.into(),
+                    ],
+                )?;
+                println!("funded pool with {} stroops: {hash}", note.denomination);
+            }
+
+            let (hash, index) = pool.invoke(
+                &signer,
+                "deposit",
+                vec![depositor, tx::bytes_val(&note.commitment())?],
+            )?;
+            let index: u64 = tx::from_val(index)?;
+            println!("deposited at leaf {index}: {hash}");
+
+            note.leaf_index = Some(index);
+            note::save(&note, &note_path)?;
+        }
+
+        Command::Path { note } => {
+            let note = note::load(&note)?;
+            let pool = net.pool()?;
+            note::check_deployment(&note, &pool)?;
+            let tree = pool.tree()?;
+            let index = find_leaf(&tree, &note.commitment(), note.leaf_index)?;
+            let path = tree.path(index);
+
+            let siblings: Vec<String> = path.siblings.iter().map(to_hex).collect();
+            let out = json!({
+                "leafIndex": index,
+                "depth": tree.depth(),
+                "root": to_hex(&tree.root()),
+                "merkleProof": siblings,
+                "isEven": path.is_even,
+            });
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+
+        Command::ProverToml {
+            note,
+            recipient,
+            relayer_fee,
+            out,
+        } => {
+            let note = note::load(&note)?;
+            let pool = net.pool()?;
+            note::check_deployment(&note, &pool)?;
+            let tree = pool.tree()?;
+            let index = find_leaf(&tree, &note.commitment(), note.leaf_index)?;
+
+            let root = tree.root();
+            if root != pool.latest_root()? {
+                bail!("rebuilt root does not match the contract; a deposit landed mid-read, retry");
+            }
+
+            let toml = prover::prover_toml(
+                &note,
+                &root,
+                &address_to_field(&recipient)?,
+                &tree.path(index),
+                relayer_fee,
+            );
+            std::fs::write(&out, toml)?;
+            println!("leaf {index}, root {}", to_hex(&root));
+            println!("wrote {}", out.display());
+        }
+
+        Command::Withdraw {
+            relayer,
+            recipient,
+            proof,
+            public_inputs,
+        } => {
+            let public_inputs = prover::load_public_inputs(&public_inputs)?;
+            if public_inputs[2] != to_hex(&address_to_field(&recipient)?) {
+                bail!("proof recipient does not match {recipient}");
+            }
+            let proof =
+                std::fs::read(&proof).with_context(|| format!("reading {}", proof.display()))?;
+
+            let url = format!("{}/withdraw", relayer.trim_end_matches('/'));
+            let response = ureq::post(&url).send_json(json!({
+                "proof": proof,
+                "publicInputs": public_inputs,
+                "recipient": recipient,
+            }));
+            let body: serde_json::Value = match response {
+                Ok(r) => r.into_json()?,
+                Err(ureq::Error::Status(_, r)) => r.into_json()?,
+                Err(e) => return Err(e).context("contacting relayer"),
+            };
+            println!("{}", serde_json::to_string_pretty(&body)?);
+            if body["success"] != true {
+                bail!("relayer rejected the withdrawal");
+            }
+        }
+
+        Command::Status { nullifier, note } => {
+            let nullifier_hash = match (nullifier, note) {
+                (Some(hex), _) => parse_field(&hex)?,
+                (None, Some(path)) => note::load(&path)?.nullifier_hash(),
+                (None, None) => unreachable!("clap requires one of them"),
+            };
+            let spent = net.pool()?.is_spent(&nullifier_hash)?;
+            let out = json!({ "nullifierHash": to_hex(&nullifier_hash), "spent": spent });
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rotor_merkle::Node;
+use rotor_note::{network_id, Note, Recipient};
+use std::path::Path;
+use std::str::FromStr;
+use stellar_strkey::Strkey;
+use stellar_xdr::curr::{ContractId, Hash, ScAddress};
+
+use crate::pool::Pool;
+
+// ---------------------------------------------------------------------------
+// Note files
+//
+// A note file holds one encoded `rotor-v1-...` line (see the rotor-note
+// crate). The encoding carries the network and contract, so a note is checked
+// against the pool before it is used.
+// ---------------------------------------------------------------------------
+
+/// Generate a note for `amount_stroops` with a fresh nullifier and secret.
+pub fn generate(pool: &Pool, amount_stroops: u64) -> Result<Note> {
+    let ScAddress::Contract(ContractId(Hash(contract_id))) = pool.contract else {
+        unreachable!("Pool::new only accepts contract ids");
+    };
+    Note::new(
+        network_id(&pool.passphrase),
+        contract_id,
+        amount_stroops.into(),
+        random_field()?,
+        random_field()?,
+    )
+    .map_err(|e| anyhow!("generating note: {e}"))
+}
+
+/// Read a note file, rejecting mistyped or truncated notes by checksum.
+pub fn load(path: &Path) -> Result<Note> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("reading note {}", path.display()))?;
+    Note::decode(&raw).map_err(|e| anyhow!("{}: {e}", path.display()))
+}
+
+pub fn save(note: &Note, path: &Path) -> Result<()> {
+    std::fs::write(path, format!("{note}\n"))
+        .with_context(|| format!("writing note {}", path.display()))
+}
+
+/// Fail unless `note` was made for `pool`'s network and contract.
+pub fn check_deployment(note: &Note, pool: &Pool) -> Result<()> {
+    if note.network_id != network_id(&pool.passphrase) {
+        bail!("note was made for a different network");
+    }
+    if ScAddress::Contract(ContractId(Hash(note.contract_id))) != pool.contract {
+        bail!(
+            "note was made for pool {}",
+            stellar_strkey::Contract(note.contract_id)
+        );
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Field encoding helpers
+// ---------------------------------------------------------------------------
+
+/// Random 32-byte field element; the top byte is zeroed to stay below the
+/// BN254 modulus, as `randomField` does in the client.
+pub fn random_field() -> Result<Node> {
+    let mut buf = [0u8; 32];
+    getrandom::getrandom(&mut buf).map_err(|e| anyhow::anyhow!("reading randomness: {e}"))?;
+    buf[0] = 0;
+    Ok(buf)
+}
+
+/// The recipient field the circuit binds to, [`Recipient::field`] of a G...
+/// account, C... contract or M... muxed account (`stellarAddressToField` in
+/// the relayer).
+pub fn address_to_field(address: &str) -> Result<Node> {
+    let recipient = match Strkey::from_str(address) {
+        Ok(Strkey::PublicKeyEd25519(pk)) => Recipient::Account(pk.0),
+        Ok(Strkey::Contract(contract)) => Recipient::Contract(contract.0),
+        Ok(Strkey::MuxedAccountEd25519(muxed)) => Recipient::Muxed {
+            ed25519: muxed.ed25519,
+            id: muxed.id,
+        },
+        _ => bail!("expected a G..., C... or M... address, got {address}"),
+    };
+    Ok(recipient.field())
+}
+
+pub fn to_hex(node: &Node) -> String {
+    format!("0x{}", hex::encode(node))
+}
+
+/// Parse a 0x-prefixed (or bare) hex field, left-padding short values.
+pub fn parse_field(s: &str) -> Result<Node> {
+    let clean = s.trim().trim_start_matches("0x");
+    if clean.len() > 64 {
+        bail!("field {s} is longer than 32 bytes");
+    }
+    let padded = format!("{clean:0>64}");
+    let mut out = [0u8; 32];
+    hex::decode_to_slice(&padded, &mut out).with_context(|| format!("invalid hex field {s}"))?;
+    Ok(out)
+}
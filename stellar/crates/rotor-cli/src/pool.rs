@@ -0,0 +1,200 @@
+use anyhow::{bail, Context, Result};
+use rotor_merkle::{native::NativeHasher, MerkleTree, Node};
+use stellar_xdr::curr::{
+    AccountId, ContractDataDurability, LedgerEntryData, LedgerKey, LedgerKeyAccount,
+    LedgerKeyContractData, PublicKey, ScAddress, ScSymbol, ScVal, ScVec, Uint256,
+};
+
+use crate::rpc::{Rpc, SimulationError};
+use crate::tx::{self, Signer};
+
+/// Source account for read-only simulations when no key is configured.
+/// Simulation never checks the sequence number or signatures.
+const VIEW_SOURCE: [u8; 32] = [0u8; 32];
+
+// ---------------------------------------------------------------------------
+// Client for a deployed rotor-core pool
+// ---------------------------------------------------------------------------
+pub struct Pool {
+    pub rpc: Rpc,
+    pub passphrase: String,
+    pub contract: ScAddress,
+}
+
+impl Pool {
+    pub fn new(rpc_url: &str, passphrase: &str, contract_id: &str) -> Result<Self> {
+        Ok(Self {
+            rpc: Rpc::new(rpc_url),
+            passphrase: passphrase.to_string(),
+            contract: tx::contract_address(contract_id)?,
+        })
+    }
+
+    /// Simulate a read-only call and return its result.
+    pub fn view(&self, function: &str, args: Vec<ScVal>) -> Result<ScVal> {
+        let call = tx::invoke(VIEW_SOURCE, 0, &self.contract, function, args)?;
+        let sim = self.rpc.simulate(&tx::unsigned(call))?;
+        tx::simulated_return(&sim)
+    }
+
+    /// Simulate, sign and submit a call to `contract`, returning the tx hash
+    /// and the call's return value.
+    pub fn invoke_on(
+        &self,
+        signer: &Signer,
+        contract: &ScAddress,
+        function: &str,
+        args: Vec<ScVal>,
+    ) -> Result<(String, ScVal)> {
+        let seq = self.sequence(&signer.public_key())? + 1;
+        let call = tx::invoke(signer.public_key(), seq, contract, function, args)?;
+        let sim = self.rpc.simulate(&tx::unsigned(call.clone()))?;
+        let simulated = tx::simulated_return(&sim)?;
+
+        let envelope = signer.sign(tx::assemble(call, &sim)?, &self.passphrase)?;
+        let (hash, status) = self.rpc.send_and_wait(&envelope)?;
+
+        let applied = match status.result_meta_xdr.as_deref() {
+            Some(meta) => tx::meta_return(meta)?,
+            None => None,
+        };
+        Ok((hash, applied.unwrap_or(simulated)))
+    }
+
+    pub fn invoke(
+        &self,
+        signer: &Signer,
+        function: &str,
+        args: Vec<ScVal>,
+    ) -> Result<(String, ScVal)> {
+        self.invoke_on(signer, &self.contract, function, args)
+    }
+
+    fn sequence(&self, account: &[u8; 32]) -> Result<i64> {
+        let key = LedgerKey::Account(LedgerKeyAccount {
+            account_id: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(*account))),
+        });
+        match self.rpc.get_ledger_entries(&[key])?.pop().flatten() {
+            Some(LedgerEntryData::Account(entry)) => Ok(entry.seq_num.0),
+            _ => bail!("source account not found; fund it first"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Contract views
+    // -----------------------------------------------------------------------
+
+    pub fn token(&self) -> Result<ScAddress> {
+        match self.view("get_token", vec![])? {
+            ScVal::Address(address) => Ok(address),
+            other => bail!("unexpected get_token result {other:?}"),
+        }
+    }
+
+    pub fn latest_root(&self) -> Result<Node> {
+        tx::to_bytes32(self.view("get_latest_root", vec![])?)
+    }
+
+    pub fn next_index(&self) -> Result<u64> {
+        tx::from_val(self.view("get_next_index", vec![])?)
+    }
+
+    pub fn depth(&self) -> Result<u32> {
+        tx::from_val(self.view("get_depth", vec![])?)
+    }
+
+    pub fn is_spent(&self, nullifier_hash: &Node) -> Result<bool> {
+        tx::from_val(self.view("is_spent", vec![tx::bytes_val(nullifier_hash)?])?)
+    }
+
+    pub fn stores_leaves(&self) -> Result<bool> {
+        stores_leaves_from(self.view("stores_leaves", vec![]))
+    }
+
+    /// Amount every `deposit` pulls, or 0 if deposits are funded separately.
+    /// Pools deployed before denominations were enforced have no such view.
+    pub fn denomination(&self) -> Result<i128> {
+        view_or(self.view("get_denomination", vec![]), 0)
+    }
+
+    /// Rebuild the tree from the contract's `Leaf(i)` entries.
+    pub fn tree(&self) -> Result<MerkleTree<NativeHasher>> {
+        if !self.stores_leaves()? {
+            bail!(
+                "this pool keeps leaves only in NewCommitment events; \
+                 build the Merkle path from an indexer"
+            );
+        }
+        let count = self.next_index()?;
+        let keys = (0..count)
+            .map(|i| self.leaf_key(i))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tree = MerkleTree::new(NativeHasher, self.depth()?);
+        for (i, entry) in self.rpc.get_ledger_entries(&keys)?.into_iter().enumerate() {
+            let leaf = match entry {
+                Some(LedgerEntryData::ContractData(data)) => tx::to_bytes32(data.val)?,
+                _ => bail!("leaf {i} missing from contract storage (archived?)"),
+            };
+            tree.insert(leaf);
+        }
+        Ok(tree)
+    }
+
+    /// Ledger key of `DataKey::Leaf(index)`.
+    fn leaf_key(&self, index: u64) -> Result<LedgerKey> {
+        let key = ScVal::Vec(Some(ScVec(
+            vec![
+                ScVal::Symbol(ScSymbol("Leaf".try_into()?)),
+                ScVal::U64(index),
+            ]
+            .try_into()?,
+        )));
+        Ok(LedgerKey::ContractData(LedgerKeyContractData {
+            contract: self.contract.clone(),
+            key,
+            durability: ContractDataDurability::Persistent,
+        }))
+    }
+}
+
+/// Interpret the `stores_leaves` view. Pools deployed before `store_leaves`
+/// existed have no such view and always store their leaves; any other error
+/// is returned, so an unreachable RPC is not mistaken for such a pool.
+pub fn stores_leaves_from(view: Result<ScVal>) -> Result<bool> {
+    view_or(view, true)
+}
+
+/// Interpret a view added in a later pool version, using `default` only if
+/// the pool lacks the function.
+fn view_or<T: TryFrom<ScVal>>(view: Result<ScVal>, default: T) -> Result<T> {
+    match view {
+        Ok(val) => tx::from_val(val),
+        Err(err)
+            if err
+                .downcast_ref::<SimulationError>()
+                .is_some_and(SimulationError::is_missing_function) =>
+        {
+            Ok(default)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Find `commitment` in `tree` and return its index, trying the note's leaf
+/// index hint first.
+pub fn find_leaf(
+    tree: &MerkleTree<NativeHasher>,
+    commitment: &Node,
+    hint: Option<u64>,
+) -> Result<u64> {
+    let leaves = tree.leaves();
+    if let Some(i) = hint.filter(|&i| leaves.get(i as usize) == Some(commitment)) {
+        return Ok(i);
+    }
+    leaves
+        .iter()
+        .position(|leaf| leaf == commitment)
+        .map(|i| i as u64)
+        .context("commitment not found in the pool; was the deposit confirmed?")
+}
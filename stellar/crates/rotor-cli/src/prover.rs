@@ -0,0 +1,75 @@
+use anyhow::{bail, Context, Result};
+use rotor_merkle::{MerklePath, Node};
+use rotor_note::Note;
+use std::path::Path;
+
+use crate::note::to_hex;
+
+/// Public inputs in circuit order: root, nullifier_hash, recipient, amount,
+/// relayer_fee (the contract's `WithdrawV2` layout).
+pub const PUBLIC_INPUTS: usize = 5;
+
+/// Render a Prover.toml for `note`, laid out like circuit/Prover.toml. The
+/// proof will let the relayer take up to `relayer_fee` stroops.
+pub fn prover_toml(
+    note: &Note,
+    root: &Node,
+    recipient: &Node,
+    path: &MerklePath,
+    relayer_fee: u64,
+) -> String {
+    let quoted = |nodes: &[Node]| {
+        nodes
+            .iter()
+            .map(|n| format!("\"{}\"", to_hex(n)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let is_even = path
+        .is_even
+        .iter()
+        .map(|b| b.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut fee = [0u8; 32];
+    fee[24..].copy_from_slice(&relayer_fee.to_be_bytes());
+
+    format!(
+        "# amount is in stroops as field element (10_000_000 = 1 XLM)\n\
+         amount = \"{amount}\"\n\
+         root = \"{root}\"\n\
+         nullifier_hash = \"{nullifier_hash}\"\n\
+         # relayer_fee caps what the relayer takes (100_000 = 0.01 XLM)\n\
+         relayer_fee = \"{relayer_fee}\"\n\
+         recipient = \"{recipient}\"\n\
+         nullifier = \"{nullifier}\"\n\
+         secret = \"{secret}\"\n\
+         merkleProof = [{proof}]\n\
+         is_even = [{is_even}]\n",
+        amount = to_hex(&note.amount_field()),
+        root = to_hex(root),
+        nullifier_hash = to_hex(&note.nullifier_hash()),
+        relayer_fee = to_hex(&fee),
+        recipient = to_hex(recipient),
+        nullifier = to_hex(&note.nullifier),
+        secret = to_hex(&note.secret),
+        proof = quoted(&path.siblings),
+    )
+}
+
+/// Split bb's `public_inputs` file (concatenated 32-byte fields) into hex.
+pub fn load_public_inputs(path: &Path) -> Result<Vec<String>> {
+    let raw = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    if raw.len() != PUBLIC_INPUTS * 32 {
+        bail!(
+            "{} holds {} bytes; expected {PUBLIC_INPUTS} fields of 32 bytes \
+             [root, nullifier_hash, recipient, amount, relayer_fee]",
+            path.display(),
+            raw.len()
+        );
+    }
+    Ok(raw
+        .chunks(32)
+        .map(|c| to_hex(&c.try_into().unwrap()))
+        .collect())
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use stellar_xdr::curr::{
+    LedgerEntryData, LedgerKey, Limits, ReadXdr, TransactionEnvelope, WriteXdr,
+};
+
+/// getLedgerEntries accepts at most this many keys per request.
+pub const MAX_LEDGER_KEYS: usize = 200;
+
+const POLL_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// ---------------------------------------------------------------------------
+// Minimal Soroban JSON-RPC client
+// ---------------------------------------------------------------------------
+pub struct Rpc {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Simulation {
+    pub transaction_data: Option<String>,
+    #[serde(default)]
+    pub min_resource_fee: Option<String>,
+    #[serde(default)]
+    pub results: Vec<SimulationResult>,
+    pub error: Option<String>,
+}
+
+/// A simulation the host rejected, as opposed to a transport or RPC failure.
+#[derive(Debug)]
+pub struct SimulationError(pub String);
+
+impl SimulationError {
+    /// Whether the contract has no function of the simulated name, e.g. a
+    /// view added after the contract was deployed.
+    pub fn is_missing_function(&self) -> bool {
+        self.0.contains("non-existent contract function")
+    }
+}
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "simulation failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+#[derive(Debug, Deserialize)]
+pub struct SimulationResult {
+    #[serde(default)]
+    pub auth: Vec<String>,
+    pub xdr: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SendResponse {
+    hash: String,
+    status: String,
+    error_result_xdr: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionStatus {
+    pub status: String,
+    pub result_meta_xdr: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LedgerEntries {
+    #[serde(default)]
+    entries: Vec<LedgerEntryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LedgerEntryResult {
+    key: String,
+    xdr: String,
+}
+
+impl Rpc {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(body)
+            .with_context(|| format!("{method} request to {}", self.url))?
+            .into_json()?;
+
+        if let Some(error) = response.get("error") {
+            bail!("{method} failed: {error}");
+        }
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{method} returned no result"))?;
+        serde_json::from_value(result).with_context(|| format!("decoding {method} result"))
+    }
+
+    /// Fetch ledger entries, returned in the same order as `keys` (`None`
+    /// for entries that do not exist).
+    pub fn get_ledger_entries(&self, keys: &[LedgerKey]) -> Result<Vec<Option<LedgerEntryData>>> {
+        let mut out = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_LEDGER_KEYS) {
+            let encoded = chunk
+                .iter()
+                .map(|k| k.to_xdr_base64(Limits::none()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let found: LedgerEntries = self.call("getLedgerEntries", json!({ "keys": encoded }))?;
+
+            for key in &encoded {
+                let entry = match found.entries.iter().find(|e| &e.key == key) {
+                    Some(e) => Some(LedgerEntryData::from_xdr_base64(&e.xdr, Limits::none())?),
+                    None => None,
+                };
+                out.push(entry);
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn simulate(&self, tx: &TransactionEnvelope) -> Result<Simulation> {
+        let sim: Simulation = self.call(
+            "simulateTransaction",
+            json!({ "transaction": tx.to_xdr_base64(Limits::none())? }),
+        )?;
+        if let Some(error) = &sim.error {
+            return Err(SimulationError(error.clone()).into());
+        }
+        Ok(sim)
+    }
+
+    /// Submit a signed transaction and wait until it is applied.
+    pub fn send_and_wait(&self, tx: &TransactionEnvelope) -> Result<(String, TransactionStatus)> {
+        let sent: SendResponse = self.call(
+            "sendTransaction",
+            json!({ "transaction": tx.to_xdr_base64(Limits::none())? }),
+        )?;
+        if sent.status == "ERROR" {
+            bail!(
+                "transaction rejected: {}",
+                sent.error_result_xdr.unwrap_or_default()
+            );
+        }
+
+        let deadline = Instant::now() + POLL_TIMEOUT;
+        while Instant::now() < deadline {
+            let status: TransactionStatus =
+                self.call("getTransaction", json!({ "hash": sent.hash }))?;
+            match status.status.as_str() {
+                "SUCCESS" => return Ok((sent.hash, status)),
+                "FAILED" => bail!("transaction {} failed on-chain", sent.hash),
+                _ => sleep(POLL_INTERVAL),
+            }
+        }
+        bail!(
+            "transaction {} not confirmed within {}s",
+            sent.hash,
+            POLL_TIMEOUT.as_secs()
+        )
+    }
+}
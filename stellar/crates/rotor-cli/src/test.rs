@@ -0,0 +1,309 @@
+#![cfg(test)]
+
+use clap::Parser;
+use rotor_merkle::{native::NativeHasher, MerkleTree};
+use rotor_note::{network_id, Note, Recipient};
+use std::path::PathBuf;
+use stellar_xdr::curr::{Limits, SorobanTransactionData, TransactionExt, WriteXdr};
+
+use crate::note::{self, address_to_field, parse_field, to_hex};
+use crate::pool::{find_leaf, stores_leaves_from, Pool};
+use crate::prover::{load_public_inputs, prover_toml};
+use crate::rpc::{Simulation, SimulationError, SimulationResult};
+use crate::tx::{self, Signer, INCLUSION_FEE};
+use crate::Cli;
+
+const PROVER_TOML: &str = include_str!("../../../../circuit/Prover.toml");
+
+// Throwaway key, only used to check signatures.
+const SECRET: &str = "SBGWSG6BTNCKCOB3DIFBGCVMUPQFYPA2G4O34RMTB343OYPXU5DJDVMN";
+
+fn prover_value(key: &str) -> &'static str {
+    PROVER_TOML
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().trim_matches('"'))
+        .unwrap()
+}
+
+fn local_pool(contract_id: [u8; 32]) -> Pool {
+    let network = crate::Network::Local;
+    let contract = stellar_strkey::Contract(contract_id).to_string();
+    Pool::new(network.rpc_url(), network.passphrase(), &contract).unwrap()
+}
+
+fn fixture_note() -> Note {
+    Note::new(
+        network_id(crate::Network::Local.passphrase()),
+        [7; 32],
+        10_000_000,
+        parse_field(prover_value("nullifier")).unwrap(),
+        parse_field(prover_value("secret")).unwrap(),
+    )
+    .unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rotor-cli-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn test_note_matches_circuit_fixture() {
+    let note = fixture_note();
+
+    assert_eq!(to_hex(&note.amount_field()), prover_value("amount"));
+    assert_eq!(
+        to_hex(&note.nullifier_hash()),
+        prover_value("nullifier_hash")
+    );
+
+    let mut tree = MerkleTree::new(NativeHasher, 20);
+    tree.insert(note.commitment());
+    assert_eq!(to_hex(&tree.root()), prover_value("root"));
+}
+
+#[test]
+fn test_generated_notes_are_fresh_fields() {
+    let pool = local_pool([7; 32]);
+    let a = note::generate(&pool, 1).unwrap();
+    let b = note::generate(&pool, 1).unwrap();
+
+    assert_ne!(a.nullifier, b.nullifier);
+    assert_ne!(a.commitment(), b.commitment());
+    assert!(a.nullifier[0] == 0 && a.secret[0] == 0);
+    assert_eq!(a.denomination, 1);
+    note::check_deployment(&a, &pool).unwrap();
+}
+
+#[test]
+fn test_note_file_round_trip() {
+    let path = temp_path("note.txt");
+    let note = fixture_note();
+
+    note::save(&note, &path).unwrap();
+    assert_eq!(note::load(&path).unwrap(), note);
+
+    // A note with a mistyped character fails its checksum.
+    let mut encoded = std::fs::read_to_string(&path).unwrap();
+    let typo = if &encoded[20..21] == "0" { "1" } else { "0" };
+    encoded.replace_range(20..21, typo);
+    std::fs::write(&path, encoded).unwrap();
+    assert!(note::load(&path).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_note_deployment() {
+    let note = fixture_note();
+
+    note::check_deployment(&note, &local_pool([7; 32])).unwrap();
+    assert!(note::check_deployment(&note, &local_pool([8; 32])).is_err());
+
+    let testnet = crate::Network::Testnet;
+    let pool = Pool::new(
+        testnet.rpc_url(),
+        testnet.passphrase(),
+        &stellar_strkey::Contract([7; 32]).to_string(),
+    )
+    .unwrap();
+    assert!(note::check_deployment(&note, &pool).is_err());
+}
+
+#[test]
+fn test_find_leaf_uses_hint() {
+    let mut tree = MerkleTree::new(NativeHasher, 20);
+    for leaf in [[1; 32], [2; 32], [1; 32]] {
+        tree.insert(leaf);
+    }
+
+    assert_eq!(find_leaf(&tree, &[1; 32], None).unwrap(), 0);
+    assert_eq!(find_leaf(&tree, &[1; 32], Some(2)).unwrap(), 2);
+    // A stale or wrong hint falls back to searching.
+    assert_eq!(find_leaf(&tree, &[2; 32], Some(0)).unwrap(), 1);
+    assert_eq!(find_leaf(&tree, &[2; 32], Some(99)).unwrap(), 1);
+    assert!(find_leaf(&tree, &[3; 32], None).is_err());
+}
+
+#[test]
+fn test_stores_leaves_only_defaults_for_missing_view() {
+    let missing = "HostError: Error(WasmVm, MissingValue)\n\nEvent log (newest first):\n   \
+                   0: [Diagnostic Event] topics:[error, Error(WasmVm, MissingValue)], \
+                   data:[\"trying to invoke non-existent contract function\", stores_leaves]";
+    assert!(stores_leaves_from(Err(SimulationError(missing.into()).into())).unwrap());
+    assert!(!stores_leaves_from(Ok(stellar_xdr::curr::ScVal::Bool(false))).unwrap());
+
+    let trapped = SimulationError("HostError: Error(Storage, MissingValue)".into());
+    assert!(stores_leaves_from(Err(trapped.into())).is_err());
+    assert!(stores_leaves_from(Err(anyhow::anyhow!("connection refused"))).is_err());
+}
+
+#[test]
+fn test_amount_field() {
+    assert_eq!(
+        to_hex(&fixture_note().amount_field()),
+        "0x0000000000000000000000000000000000000000000000000000000000989680"
+    );
+}
+
+#[test]
+fn test_address_to_field() {
+    let signer = Signer::from_secret(SECRET).unwrap();
+    let key = signer.public_key();
+
+    let field = address_to_field(&signer.address()).unwrap();
+    assert_eq!(field, Recipient::Account(key).field());
+
+    let muxed = stellar_strkey::ed25519::MuxedAccount {
+        ed25519: key,
+        id: 42,
+    }
+    .to_string();
+    assert_eq!(
+        address_to_field(&muxed).unwrap(),
+        Recipient::Muxed {
+            ed25519: key,
+            id: 42
+        }
+        .field()
+    );
+    assert_ne!(address_to_field(&muxed).unwrap(), field);
+
+    let contract = stellar_strkey::Contract(key).to_string();
+    assert_eq!(
+        address_to_field(&contract).unwrap(),
+        Recipient::Contract(key).field()
+    );
+
+    assert!(address_to_field("not an address").is_err());
+}
+
+#[test]
+fn test_sign_transaction() {
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+    use stellar_xdr::curr::TransactionEnvelope;
+
+    let signer = Signer::from_secret(SECRET).unwrap();
+    let contract = tx::contract_address(&stellar_strkey::Contract([7; 32]).to_string()).unwrap();
+    let call = tx::invoke(signer.public_key(), 5, &contract, "get_next_index", vec![]).unwrap();
+    let passphrase = crate::Network::Local.passphrase();
+
+    let TransactionEnvelope::Tx(env) = signer.sign(call.clone(), passphrase).unwrap() else {
+        panic!("expected a v1 envelope");
+    };
+    let sig = &env.signatures[0];
+    assert_eq!(sig.hint.0, signer.public_key()[28..]);
+
+    let hash = call.hash(tx::network_id(passphrase)).unwrap();
+    let key = VerifyingKey::from_bytes(&signer.public_key()).unwrap();
+    let signature = Signature::from_slice(&sig.signature.0).unwrap();
+    assert!(key.verify(&hash, &signature).is_ok());
+}
+
+#[test]
+fn test_assemble_applies_simulation() {
+    let contract = tx::contract_address(&stellar_strkey::Contract([7; 32]).to_string()).unwrap();
+    let call = tx::invoke([1; 32], 1, &contract, "deposit", vec![]).unwrap();
+    let sim = Simulation {
+        transaction_data: Some(
+            SorobanTransactionData::default()
+                .to_xdr_base64(Limits::none())
+                .unwrap(),
+        ),
+        min_resource_fee: Some("1234".to_string()),
+        results: vec![SimulationResult {
+            auth: vec![],
+            xdr: stellar_xdr::curr::ScVal::U32(3)
+                .to_xdr_base64(Limits::none())
+                .unwrap(),
+        }],
+        error: None,
+    };
+
+    let assembled = tx::assemble(call, &sim).unwrap();
+    assert_eq!(assembled.fee, INCLUSION_FEE + 1234);
+    assert!(matches!(assembled.ext, TransactionExt::V1(_)));
+    assert_eq!(
+        tx::from_val::<u32>(tx::simulated_return(&sim).unwrap()).unwrap(),
+        3
+    );
+}
+
+#[test]
+fn test_prover_toml_matches_fixture() {
+    let note = fixture_note();
+    let mut tree = MerkleTree::new(NativeHasher, 20);
+    tree.insert(note.commitment());
+
+    let recipient = parse_field(prover_value("recipient")).unwrap();
+    let toml = prover_toml(&note, &tree.root(), &recipient, &tree.path(0), 100_000);
+
+    for (key, value) in toml.lines().filter_map(|l| l.split_once(" = ")) {
+        let expected = PROVER_TOML
+            .lines()
+            .find_map(|l| l.strip_prefix(&format!("{key} = ")))
+            .unwrap();
+        assert_eq!(value, expected, "{key}");
+    }
+}
+
+#[test]
+fn test_load_public_inputs() {
+    let path = temp_path("public_inputs");
+    let fields: Vec<u8> = (0..5u8).flat_map(|i| [i; 32]).collect();
+    std::fs::write(&path, &fields).unwrap();
+
+    let inputs = load_public_inputs(&path).unwrap();
+    assert_eq!(inputs.len(), 5);
+    assert_eq!(inputs[4], to_hex(&[4; 32]));
+
+    // Four fields: a proof for a circuit without the public relayer fee.
+    std::fs::write(&path, &fields[..128]).unwrap();
+    assert!(load_public_inputs(&path).is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_cli_arguments() {
+    assert!(Cli::try_parse_from(["rotor", "note", "new", "--amount", "10000000"]).is_ok());
+    assert!(Cli::try_parse_from(["rotor", "status"]).is_err());
+    assert!(Cli::try_parse_from(["rotor", "status", "--nullifier", "0x01"]).is_ok());
+    assert!(
+        Cli::try_parse_from(["rotor", "--network", "local", "path", "--note", "n.txt"]).is_ok()
+    );
+}
+
+/// End to end against a local sandbox (`stellar container start local`) with
+/// a deployed pool. Set ROTOR_SANDBOX_CONTRACT and ROTOR_SANDBOX_SECRET (a
+/// funded account), then run with `--ignored`.
+#[test]
+#[ignore]
+fn test_sandbox_deposit() {
+    let contract = std::env::var("ROTOR_SANDBOX_CONTRACT").unwrap();
+    let signer = Signer::from_secret(&std::env::var("ROTOR_SANDBOX_SECRET").unwrap()).unwrap();
+    let network = crate::Network::Local;
+    let pool = Pool::new(network.rpc_url(), network.passphrase(), &contract).unwrap();
+
+    let note = note::generate(&pool, 1_000_000).unwrap();
+    let (_, index) = pool
+        .invoke(
+            &signer,
+            "deposit",
+            vec![
+                tx::account_val(&signer.public_key()),
+                tx::bytes_val(&note.commitment()).unwrap(),
+            ],
+        )
+        .unwrap();
+    let index: u64 = tx::from_val(index).unwrap();
+
+    let tree = pool.tree().unwrap();
+    assert_eq!(find_leaf(&tree, &note.commitment(), None).unwrap(), index);
+    assert_eq!(tree.root(), pool.latest_root().unwrap());
+    assert!(tree
+        .path(index)
+        .verify(&NativeHasher, &tree.root(), &note.commitment()));
+    assert!(!pool.is_spent(&note.nullifier_hash()).unwrap());
+}
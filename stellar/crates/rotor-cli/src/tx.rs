@@ -0,0 +1,207 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signer as _, SigningKey};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use stellar_xdr::curr::{
+    AccountId, DecoratedSignature, HostFunction, InvokeContractArgs, InvokeHostFunctionOp, Limits,
+    Memo, MuxedAccount, Operation, OperationBody, Preconditions, PublicKey, ReadXdr, ScAddress,
+    ScSymbol, ScVal, SequenceNumber, Signature, SignatureHint, SorobanAuthorizationEntry,
+    SorobanTransactionData, TimeBounds, TimePoint, Transaction, TransactionEnvelope,
+    TransactionExt, TransactionMeta, TransactionV1Envelope, Uint256, VecM,
+};
+
+use crate::rpc::Simulation;
+
+/// Inclusion fee bid, matching the relayer and client (`fee: "1000000"`).
+pub const INCLUSION_FEE: u32 = 1_000_000;
+
+/// Transactions expire this many seconds after they are built.
+pub const TX_TIMEOUT_SECS: u64 = 180;
+
+// ---------------------------------------------------------------------------
+// Keys
+// ---------------------------------------------------------------------------
+
+/// Ed25519 key pair decoded from an `S...` secret seed.
+pub struct Signer {
+    key: SigningKey,
+}
+
+impl Signer {
+    pub fn from_secret(secret: &str) -> Result<Self> {
+        let seed = stellar_strkey::ed25519::PrivateKey::from_str(secret.trim())
+            .map_err(|_| anyhow!("invalid secret key (expected S...)"))?;
+        Ok(Self {
+            key: SigningKey::from_bytes(&seed.0),
+        })
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    /// `G...` address of this key.
+    pub fn address(&self) -> String {
+        stellar_strkey::ed25519::PublicKey(self.public_key()).to_string()
+    }
+
+    /// Sign `tx` for the network identified by `passphrase`.
+    pub fn sign(&self, tx: Transaction, passphrase: &str) -> Result<TransactionEnvelope> {
+        let hash = tx.hash(network_id(passphrase))?;
+        let signature = self.key.sign(&hash).to_bytes();
+
+        let public_key = self.public_key();
+        let hint = SignatureHint(public_key[28..].try_into().unwrap());
+        let decorated = DecoratedSignature {
+            hint,
+            signature: Signature(signature.to_vec().try_into()?),
+        };
+        Ok(TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx,
+            signatures: vec![decorated].try_into()?,
+        }))
+    }
+}
+
+pub fn network_id(passphrase: &str) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+// ---------------------------------------------------------------------------
+// Building and assembling contract invocations
+// ---------------------------------------------------------------------------
+
+/// Unsigned, unsimulated transaction calling `function` on `contract`.
+pub fn invoke(
+    source: [u8; 32],
+    seq_num: i64,
+    contract: &ScAddress,
+    function: &str,
+    args: Vec<ScVal>,
+) -> Result<Transaction> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let op = Operation {
+        source_account: None,
+        body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function: HostFunction::InvokeContract(InvokeContractArgs {
+                contract_address: contract.clone(),
+                function_name: ScSymbol(function.try_into()?),
+                args: args.try_into()?,
+            }),
+            auth: VecM::default(),
+        }),
+    };
+
+    Ok(Transaction {
+        source_account: MuxedAccount::Ed25519(Uint256(source)),
+        fee: INCLUSION_FEE,
+        seq_num: SequenceNumber(seq_num),
+        cond: Preconditions::Time(TimeBounds {
+            min_time: TimePoint(0),
+            max_time: TimePoint(now + TX_TIMEOUT_SECS),
+        }),
+        memo: Memo::None,
+        operations: vec![op].try_into()?,
+        ext: TransactionExt::V0,
+    })
+}
+
+/// Apply a simulation's footprint, resource fee and auth entries, as
+/// `rpc.prepareTransaction` does in the JS SDK.
+pub fn assemble(mut tx: Transaction, sim: &Simulation) -> Result<Transaction> {
+    let data = sim
+        .transaction_data
+        .as_deref()
+        .context("simulation returned no transaction data")?;
+    let data = SorobanTransactionData::from_xdr_base64(data, Limits::none())?;
+
+    let resource_fee: u32 = sim
+        .min_resource_fee
+        .as_deref()
+        .unwrap_or("0")
+        .parse()
+        .context("invalid minResourceFee")?;
+
+    let auth = match sim.results.first() {
+        Some(result) => result
+            .auth
+            .iter()
+            .map(|a| SorobanAuthorizationEntry::from_xdr_base64(a, Limits::none()))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    let mut ops = tx.operations.to_vec();
+    match ops.first_mut().map(|op| &mut op.body) {
+        Some(OperationBody::InvokeHostFunction(invoke)) => invoke.auth = auth.try_into()?,
+        _ => bail!("expected a single InvokeHostFunction operation"),
+    }
+
+    tx.operations = ops.try_into()?;
+    tx.fee = tx.fee.saturating_add(resource_fee);
+    tx.ext = TransactionExt::V1(data);
+    Ok(tx)
+}
+
+/// Return value of the first simulated invocation.
+pub fn simulated_return(sim: &Simulation) -> Result<ScVal> {
+    let result = sim
+        .results
+        .first()
+        .context("simulation returned no result")?;
+    Ok(ScVal::from_xdr_base64(&result.xdr, Limits::none())?)
+}
+
+/// Return value recorded in an applied transaction's meta.
+pub fn meta_return(meta_xdr: &str) -> Result<Option<ScVal>> {
+    Ok(
+        match TransactionMeta::from_xdr_base64(meta_xdr, Limits::none())? {
+            TransactionMeta::V3(v3) => v3.soroban_meta.map(|m| m.return_value),
+            TransactionMeta::V4(v4) => v4.soroban_meta.and_then(|m| m.return_value),
+            _ => None,
+        },
+    )
+}
+
+pub fn contract_address(contract_id: &str) -> Result<ScAddress> {
+    match ScAddress::from_str(contract_id.trim()) {
+        Ok(address @ ScAddress::Contract(_)) => Ok(address),
+        _ => bail!("invalid contract id {contract_id} (expected C...)"),
+    }
+}
+
+pub fn bytes_val(bytes: &[u8]) -> Result<ScVal> {
+    ScVal::try_from(bytes).map_err(|_| anyhow!("value too large for ScVal bytes"))
+}
+
+/// Convert a contract return value, naming the expected type on mismatch.
+pub fn from_val<T: TryFrom<ScVal>>(val: ScVal) -> Result<T> {
+    let shown = format!("{val:?}");
+    T::try_from(val).map_err(|_| anyhow!("expected {}, got {shown}", std::any::type_name::<T>()))
+}
+
+pub fn to_bytes32(val: ScVal) -> Result<[u8; 32]> {
+    match val {
+        ScVal::Bytes(b) => b
+            .to_vec()
+            .try_into()
+            .map_err(|_| anyhow!("expected 32 bytes")),
+        other => bail!("expected bytes, got {other:?}"),
+    }
+}
+
+/// Wrap an unsigned transaction for simulation.
+pub fn unsigned(tx: Transaction) -> TransactionEnvelope {
+    TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx,
+        signatures: VecM::default(),
+    })
+}
+
+/// `Address` value for an ed25519 account.
+pub fn account_val(public_key: &[u8; 32]) -> ScVal {
+    ScVal::Address(ScAddress::Account(AccountId(
+        PublicKey::PublicKeyTypeEd25519(Uint256(*public_key)),
+    )))
+}
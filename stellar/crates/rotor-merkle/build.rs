@@ -0,0 +1,55 @@
+//! Generates the zero table (`$OUT_DIR/zeros.rs`) from a seed:
+//!
+//!   zeros(0)   = keccak256(seed) % BN254_FIELD_SIZE
+//!   zeros(i+1) = Poseidon2::hash([zeros(i), zeros(i)], 2)
+//!
+//! The seed defaults to "cyfrin", which the circuit fixtures and the client's
+//! tables are built from. Set `ROTOR_ZERO_SEED` at build time to give a
+//! deployment its own empty tree; clients must then use the same table.
+#[path = "src/poseidon2.rs"]
+#[allow(dead_code)]
+mod poseidon2;
+#[path = "src/poseidon2_constants.rs"]
+mod poseidon2_constants;
+
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use std::fmt::Write;
+use tiny_keccak::{Hasher, Keccak};
+
+const DEFAULT_SEED: &str = "cyfrin";
+const LEVELS: usize = 32;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=ROTOR_ZERO_SEED");
+    println!("cargo:rerun-if-changed=src/poseidon2.rs");
+    println!("cargo:rerun-if-changed=src/poseidon2_constants.rs");
+
+    let seed = std::env::var("ROTOR_ZERO_SEED").unwrap_or_else(|_| DEFAULT_SEED.to_string());
+
+    let mut digest = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(seed.as_bytes());
+    keccak.finalize(&mut digest);
+
+    let mut zero = Fr::from_be_bytes_mod_order(&digest);
+    let mut table = String::new();
+    for _ in 0..LEVELS {
+        let hex: String = poseidon2::from_field(&zero)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        writeln!(table, "    hex(\"{hex}\"),").unwrap();
+        zero = poseidon2::poseidon2_hash(&[zero, zero]);
+    }
+
+    let out = format!(
+        "/// Levels covered by [`ZEROS`].\n\
+         pub const ZERO_LEVELS: u32 = {LEVELS};\n\n\
+         /// Seed [`ZEROS`] was generated from (`ROTOR_ZERO_SEED` at build time).\n\
+         pub const ZERO_SEED: &str = {seed:?};\n\n\
+         pub const ZEROS: [Node; ZERO_LEVELS as usize] = [\n{table}];\n"
+    );
+    let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("zeros.rs");
+    std::fs::write(path, out).unwrap();
+}
@@ -0,0 +1,39 @@
+//! Poseidon2 incremental Merkle tree shared by the Rotor contracts and
+//! off-chain tooling.
+//!
+//! The tree mirrors `circuit/src/merkle_tree.nr`: leaves sit at level 0, an
+//! empty subtree at level `i` hashes to `ZEROS[i]`, and every parent is Noir's
+//! `Poseidon2::hash([left, right], 2)` (the t=4 sponge).
+//!
+//! - `soroban` feature: [`soroban::SorobanHasher`], backed by the host's
+//!   Poseidon2 permutation. This is what the contracts build with.
+//! - `std` feature: [`native::NativeHasher`] and the in-memory [`MerkleTree`]
+//!   for indexers, the CLI and tests.
+//! - `testutils` feature: [`testutils::Cost`], the budget checks the
+//!   contracts' tests share.
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+mod tree;
+mod zeros;
+
+#[cfg(feature = "std")]
+pub mod native;
+#[cfg(feature = "std")]
+mod poseidon2;
+#[cfg(feature = "std")]
+mod poseidon2_constants;
+#[cfg(feature = "soroban")]
+pub mod soroban;
+#[cfg(feature = "testutils")]
+pub mod testutils;
+
+#[cfg(test)]
+mod test;
+
+pub use tree::{insert, verify, Frontier, Hasher, Node, MAX_DEPTH};
+#[cfg(feature = "std")]
+pub use tree::{MerklePath, MerkleTree};
+pub use zeros::{initial_root, zero, ZEROS, ZERO_LEVELS, ZERO_SEED};
@@ -0,0 +1,24 @@
+use ark_bn254::Fr;
+use std::vec::Vec;
+
+use crate::tree::{Hasher, Node};
+
+pub use crate::poseidon2::{from_field, permute, poseidon2_hash, to_field};
+
+/// Poseidon2 computed natively, bit-for-bit the same as the host permutation
+/// and Noir's `Poseidon2::hash`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeHasher;
+
+impl Hasher for NativeHasher {
+    fn hash_pair(&self, left: &Node, right: &Node) -> Node {
+        hash(&[*left, *right])
+    }
+}
+
+/// Noir `Poseidon2::hash(inputs, inputs.len())` over 32-byte big-endian
+/// field elements, e.g. a note commitment `hash(&[nullifier, secret, amount])`.
+pub fn hash(inputs: &[Node]) -> Node {
+    let fields: Vec<Fr> = inputs.iter().map(to_field).collect();
+    from_field(&poseidon2_hash(&fields))
+}
@@ -0,0 +1,93 @@
+//! Native Poseidon2 for BN254 (t=4), shared by `native` and the build script
+//! that generates the zero table, so it depends on nothing else in the crate
+//! but its constants.
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, Field, PrimeField};
+
+use crate::poseidon2_constants::{INTERNAL_DIAGONAL, ROUND_CONSTANTS};
+
+const WIDTH: usize = 4;
+const RATE: usize = 3;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 56;
+
+/// Noir's Poseidon2 sponge: the capacity element is seeded with
+/// `len << 64`, inputs are absorbed `RATE` at a time and the first state
+/// element is squeezed.
+pub fn poseidon2_hash(inputs: &[Fr]) -> Fr {
+    let mut state = [Fr::from(0u64); WIDTH];
+    state[RATE] = Fr::from(inputs.len() as u64) * Fr::from(1u128 << 64);
+
+    let mut chunks = inputs.chunks(RATE).peekable();
+    if chunks.peek().is_none() {
+        permute(&mut state);
+    }
+    for chunk in chunks {
+        for (s, input) in state.iter_mut().zip(chunk) {
+            *s += input;
+        }
+        permute(&mut state);
+    }
+    state[0]
+}
+
+/// The Poseidon2 permutation for t=4.
+pub fn permute(state: &mut [Fr; WIDTH]) {
+    external_matrix(state);
+
+    let half = FULL_ROUNDS / 2;
+    for round in ROUND_CONSTANTS.iter().take(half) {
+        full_round(state, round);
+    }
+    for round in ROUND_CONSTANTS.iter().skip(half).take(PARTIAL_ROUNDS) {
+        state[0] = sbox(state[0] + round[0]);
+        internal_matrix(state);
+    }
+    for round in ROUND_CONSTANTS.iter().skip(half + PARTIAL_ROUNDS) {
+        full_round(state, round);
+    }
+}
+
+/// Interpret 32 big-endian bytes as a field element, reducing mod p.
+pub fn to_field(node: &[u8; 32]) -> Fr {
+    Fr::from_be_bytes_mod_order(node)
+}
+
+pub fn from_field(field: &Fr) -> [u8; 32] {
+    let bytes = field.into_bigint().to_bytes_be();
+    let mut out = [0u8; 32];
+    out[32 - bytes.len()..].copy_from_slice(&bytes);
+    out
+}
+
+fn full_round(state: &mut [Fr; WIDTH], constants: &[Fr; WIDTH]) {
+    for (s, c) in state.iter_mut().zip(constants) {
+        *s = sbox(*s + c);
+    }
+    external_matrix(state);
+}
+
+fn sbox(x: Fr) -> Fr {
+    let x2 = x * x;
+    x2 * x2 * x
+}
+
+// M4 from the Poseidon2 paper, laid out as in Barretenberg.
+fn external_matrix(s: &mut [Fr; WIDTH]) {
+    let t0 = s[0] + s[1];
+    let t1 = s[2] + s[3];
+    let t2 = s[1].double() + t1;
+    let t3 = s[3].double() + t0;
+    let t4 = t1.double().double() + t3;
+    let t5 = t0.double().double() + t2;
+    let t6 = t3 + t5;
+    let t7 = t2 + t4;
+    *s = [t6, t5, t7, t4];
+}
+
+fn internal_matrix(s: &mut [Fr; WIDTH]) {
+    let sum: Fr = s.iter().sum();
+    for (x, d) in s.iter_mut().zip(INTERNAL_DIAGONAL) {
+        *x = *x * d + sum;
+    }
+}
@@ -0,0 +1,68 @@
+use soroban_poseidon::poseidon2_hash;
+use soroban_sdk::{crypto::BnScalar, Bytes, BytesN, Env, Vec, U256};
+
+use crate::tree::{Frontier, Hasher, Node};
+use crate::zeros::zero;
+
+/// Poseidon2 over the Soroban host permutation.
+pub struct SorobanHasher<'a> {
+    env: &'a Env,
+}
+
+impl<'a> SorobanHasher<'a> {
+    pub fn new(env: &'a Env) -> Self {
+        Self { env }
+    }
+}
+
+impl Hasher for SorobanHasher<'_> {
+    /// Matches Noir circuit: `Poseidon2::hash([left, right], 2)`. Noir's sponge
+    /// runs the t=4 permutation (rate 3), so the width here must be 4 as well.
+    fn hash_pair(&self, left: &Node, right: &Node) -> Node {
+        hash(self.env, &[*left, *right])
+    }
+}
+
+/// Noir `Poseidon2::hash(inputs, inputs.len())` on the host, e.g. a note
+/// commitment `hash(env, &[nullifier, secret, amount])`. Same output as
+/// `native::hash`.
+pub fn hash(env: &Env, inputs: &[Node]) -> Node {
+    let mut fields = Vec::new(env);
+    for input in inputs {
+        fields.push_back(U256::from_be_bytes(env, &Bytes::from_array(env, input)));
+    }
+    let result: U256 = poseidon2_hash::<4, BnScalar>(env, &fields);
+
+    let result_bytes = result.to_be_bytes();
+    let mut arr = [0u8; 32];
+    for (i, byte) in arr.iter_mut().enumerate() {
+        *byte = result_bytes.get(i as u32).unwrap();
+    }
+    arr
+}
+
+/// A frontier held in one `Vec<BytesN<32>>`, so a contract can keep the whole
+/// thing in a single storage entry: load it, insert, store it back.
+pub struct VecFrontier(pub Vec<BytesN<32>>);
+
+impl VecFrontier {
+    /// Frontier of an empty tree of `depth` levels.
+    pub fn empty(env: &Env, depth: u32) -> Self {
+        let mut nodes = Vec::new(env);
+        for level in 0..depth {
+            nodes.push_back(BytesN::from_array(env, &zero(level)));
+        }
+        Self(nodes)
+    }
+}
+
+impl Frontier for VecFrontier {
+    fn get(&self, level: u32) -> Node {
+        self.0.get(level).expect("level out of bounds").to_array()
+    }
+
+    fn set(&mut self, level: u32, node: Node) {
+        let env = self.0.env().clone();
+        self.0.set(level, BytesN::from_array(&env, &node));
+    }
+}
@@ -0,0 +1,216 @@
+#![cfg(test)]
+#![cfg(all(feature = "soroban", feature = "std"))]
+extern crate std;
+
+use std::vec::Vec;
+
+use crate::native::{self, NativeHasher};
+use crate::soroban::{self, SorobanHasher, VecFrontier};
+use crate::{
+    initial_root, insert, zero, Frontier, Hasher, MerkleTree, Node, MAX_DEPTH, ZEROS, ZERO_LEVELS,
+    ZERO_SEED,
+};
+use soroban_sdk::Env;
+use tiny_keccak::{Hasher as _, Keccak};
+
+const PROVER_TOML: &str = include_str!("../../../../circuit/Prover.toml");
+
+fn parse_node(s: &str) -> Node {
+    let s = s.trim().trim_matches('"').trim_start_matches("0x");
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    out
+}
+
+fn prover_value(key: &str) -> &'static str {
+    PROVER_TOML
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+        .unwrap_or_else(|| panic!("{key} missing from Prover.toml"))
+}
+
+fn prover_node(key: &str) -> Node {
+    parse_node(prover_value(key))
+}
+
+fn leaf(i: u64) -> Node {
+    let mut node = [0u8; 32];
+    node[24..].copy_from_slice(&(i + 1).to_be_bytes());
+    node[0] = 0x0a;
+    node
+}
+
+/// Frontier kept in memory, standing in for contract storage.
+struct ArrayFrontier([Node; MAX_DEPTH as usize]);
+
+impl Frontier for ArrayFrontier {
+    fn get(&self, level: u32) -> Node {
+        self.0[level as usize]
+    }
+
+    fn set(&mut self, level: u32, node: Node) {
+        self.0[level as usize] = node;
+    }
+}
+
+#[test]
+fn test_native_matches_soroban() {
+    let env = Env::default();
+    let soroban = SorobanHasher::new(&env);
+
+    for (left, right) in [(ZEROS[0], ZEROS[0]), (leaf(0), leaf(1)), (leaf(1), leaf(0))] {
+        assert_eq!(
+            NativeHasher.hash_pair(&left, &right),
+            soroban.hash_pair(&left, &right)
+        );
+    }
+
+    env.cost_estimate().budget().reset_unlimited();
+    for inputs in [&[leaf(0)][..], &[leaf(0), leaf(1), leaf(2)]] {
+        assert_eq!(native::hash(inputs), soroban::hash(&env, inputs));
+    }
+}
+
+#[test]
+fn test_zeros_chain() {
+    for level in 0..ZERO_LEVELS - 1 {
+        let z = zero(level);
+        assert_eq!(NativeHasher.hash_pair(&z, &z), zero(level + 1));
+    }
+}
+
+/// Recompute the whole table from the seed, independently of build.rs.
+#[test]
+fn test_zeros_from_seed() {
+    let mut digest = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(ZERO_SEED.as_bytes());
+    keccak.finalize(&mut digest);
+
+    let mut expected = native::from_field(&native::to_field(&digest));
+    for (level, z) in ZEROS.iter().enumerate() {
+        assert_eq!(*z, expected, "level {level}");
+        expected = NativeHasher.hash_pair(&expected, &expected);
+    }
+    assert_eq!(ZEROS.len(), 32);
+
+    if ZERO_SEED == "cyfrin" {
+        assert_eq!(
+            ZEROS[0],
+            parse_node("0x0d823319708ab99ec915efd4f7e03d11ca1790918e8f04cd14100aceca2aa9ff")
+        );
+        assert_eq!(
+            ZEROS[19],
+            parse_node("0x1b9421fbd79f6972a348a3dd4721781ec25a5d8d27342942ae00aba80a3904d4")
+        );
+    }
+}
+
+#[test]
+fn test_native_hash_matches_circuit() {
+    let nullifier = prover_node("nullifier");
+    let commitment = native::hash(&[nullifier, prover_node("secret"), prover_node("amount")]);
+
+    assert_eq!(native::hash(&[nullifier]), prover_node("nullifier_hash"));
+
+    let mut tree = MerkleTree::new(NativeHasher, 20);
+    tree.insert(commitment);
+    assert_eq!(tree.root(), prover_node("root"));
+
+    let path = tree.path(0);
+    let fixture: Vec<Node> = prover_value("merkleProof")
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .map(parse_node)
+        .collect();
+    assert_eq!(path.siblings, fixture);
+    assert!(path.is_even.iter().all(|&even| even));
+}
+
+#[test]
+fn test_tree_matches_incremental_insert() {
+    let env = Env::default();
+    // Hashing outside a contract call still draws on the test budget.
+    env.cost_estimate().budget().reset_unlimited();
+    let soroban = SorobanHasher::new(&env);
+
+    for depth in [1, 3, 5] {
+        let mut tree = MerkleTree::new(NativeHasher, depth);
+        let mut frontier = ArrayFrontier([[0u8; 32]; MAX_DEPTH as usize]);
+        let mut packed = VecFrontier::empty(&env, depth);
+
+        for i in 0..(1u64 << depth).min(12) {
+            let root = insert(&soroban, &mut frontier, depth, i, leaf(i));
+            assert_eq!(insert(&soroban, &mut packed, depth, i, leaf(i)), root);
+            assert_eq!(tree.insert(leaf(i)), i);
+            assert_eq!(tree.root(), root, "depth {depth}, leaf {i}");
+        }
+    }
+}
+
+#[test]
+fn test_paths_verify() {
+    let mut tree = MerkleTree::new(NativeHasher, 4);
+    for i in 0..11 {
+        tree.insert(leaf(i));
+    }
+    let root = tree.root();
+
+    for i in 0..11 {
+        let path = tree.path(i);
+        assert_eq!(path.siblings.len(), 4);
+        assert!(path.verify(&NativeHasher, &root, &leaf(i)));
+        assert!(!path.verify(&NativeHasher, &root, &leaf(i + 1)));
+    }
+}
+
+#[test]
+fn test_empty_tree_root() {
+    let tree = MerkleTree::new(NativeHasher, 20);
+    assert!(tree.is_empty());
+    assert_eq!(tree.root(), initial_root(20));
+}
+
+/// The last leaf of a depth-32 tree is a right child at every level, so its
+/// root is the frontier folded onto it; the index does not wrap.
+#[test]
+fn test_deepest_tree_last_leaf() {
+    let mut frontier = ArrayFrontier([[0u8; 32]; MAX_DEPTH as usize]);
+    for level in 0..MAX_DEPTH {
+        frontier.set(level, leaf(100 + level as u64));
+    }
+    let last = (1u64 << MAX_DEPTH) - 1;
+
+    let mut expected = leaf(last);
+    for level in 0..MAX_DEPTH {
+        expected = NativeHasher.hash_pair(&frontier.get(level), &expected);
+    }
+    let root = insert(&NativeHasher, &mut frontier, MAX_DEPTH, last, leaf(last));
+    assert_eq!(root, expected);
+}
+
+#[test]
+#[should_panic(expected = "merkle tree is full")]
+fn test_deepest_tree_full() {
+    let mut frontier = ArrayFrontier([[0u8; 32]; MAX_DEPTH as usize]);
+    insert(
+        &NativeHasher,
+        &mut frontier,
+        MAX_DEPTH,
+        1u64 << MAX_DEPTH,
+        leaf(0),
+    );
+}
+
+#[test]
+#[should_panic(expected = "merkle tree is full")]
+fn test_tree_full() {
+    let mut tree = MerkleTree::new(NativeHasher, 2);
+    for i in 0..5 {
+        tree.insert(leaf(i));
+    }
+}
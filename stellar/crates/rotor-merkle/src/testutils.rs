@@ -0,0 +1,72 @@
+//! Budget regression checks shared by the contracts' tests.
+//!
+//! A test meters one call and checks it against a limit from its budget
+//! table, usually about 10% over the measured values. A failing check
+//! reports the measured cost, so a change meant to cost more can raise the
+//! limit from the failure message.
+use soroban_sdk::Env;
+
+/// Resources metered for one contract call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cost {
+    pub instructions: i64,
+    pub mem_bytes: i64,
+    pub read_entries: u32,
+    pub write_entries: u32,
+    pub write_bytes: u32,
+}
+
+impl Cost {
+    /// The resources of the last call made through `env`.
+    pub fn of_last_call(env: &Env) -> Self {
+        let r = env.cost_estimate().resources();
+        Self {
+            instructions: r.instructions,
+            mem_bytes: r.mem_bytes,
+            read_entries: r.disk_read_entries + r.memory_read_entries,
+            write_entries: r.write_entries,
+            write_bytes: r.write_bytes,
+        }
+    }
+
+    /// Assert every resource is within `limit`.
+    pub fn check(self, name: &str, limit: Cost) {
+        assert!(
+            self.instructions <= limit.instructions,
+            "{name}: instructions {self:?} > {limit:?}"
+        );
+        assert!(
+            self.mem_bytes <= limit.mem_bytes,
+            "{name}: memory {self:?} > {limit:?}"
+        );
+        assert!(
+            self.read_entries <= limit.read_entries,
+            "{name}: reads {self:?} > {limit:?}"
+        );
+        assert!(
+            self.write_entries <= limit.write_entries,
+            "{name}: writes {self:?} > {limit:?}"
+        );
+        assert!(
+            self.write_bytes <= limit.write_bytes,
+            "{name}: write bytes {self:?} > {limit:?}"
+        );
+    }
+}
+
+/// A budget table entry, in the order of [`Cost`]'s fields.
+pub const fn cost(
+    instructions: i64,
+    mem_bytes: i64,
+    read_entries: u32,
+    write_entries: u32,
+    write_bytes: u32,
+) -> Cost {
+    Cost {
+        instructions,
+        mem_bytes,
+        read_entries,
+        write_entries,
+        write_bytes,
+    }
+}
@@ -0,0 +1,203 @@
+use crate::zeros::ZEROS;
+
+#[cfg(feature = "std")]
+use crate::zeros::initial_root;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+/// A tree node: a BN254 field element, 32 bytes big-endian.
+pub type Node = [u8; 32];
+
+/// Deepest tree supported, bounded by the [`ZERO_LEVELS`](crate::ZERO_LEVELS)
+/// of the zero table. Leaf indices are `u64`, so all 2^32 leaves of the
+/// deepest tree are addressable.
+pub const MAX_DEPTH: u32 = 32;
+
+/// Two-to-one compression used for every parent node.
+pub trait Hasher {
+    fn hash_pair(&self, left: &Node, right: &Node) -> Node;
+}
+
+/// The cached left sibling ("filled subtree") at each level of an
+/// incremental tree. Contracts back this with their own storage.
+pub trait Frontier {
+    fn get(&self, level: u32) -> Node;
+    fn set(&mut self, level: u32, node: Node);
+}
+
+// ---------------------------------------------------------------------------
+// Incremental insert and path verification
+// ---------------------------------------------------------------------------
+
+/// Insert `leaf` at `index` in a tree of `depth` levels and return the new
+/// root. Only the frontier is read or written, so this costs `depth` hashes.
+pub fn insert<H: Hasher, F: Frontier>(
+    hasher: &H,
+    frontier: &mut F,
+    depth: u32,
+    index: u64,
+    leaf: Node,
+) -> Node {
+    assert!(depth > 0 && depth <= MAX_DEPTH, "depth must be 1..=32");
+    assert!(index < 1u64 << depth, "merkle tree is full");
+
+    let mut current_index = index;
+    let mut current_hash = leaf;
+
+    for level in 0..depth {
+        if current_index.is_multiple_of(2) {
+            // Even: current is left child, right sibling is zero at this level
+            frontier.set(level, current_hash);
+            current_hash = hasher.hash_pair(&current_hash, &ZEROS[level as usize]);
+        } else {
+            // Odd: current is right child, left sibling is cached subtree
+            current_hash = hasher.hash_pair(&frontier.get(level), &current_hash);
+        }
+        current_index /= 2;
+    }
+
+    current_hash
+}
+
+/// Recompute the root from `leaf` and its path, exactly as
+/// `calculate_merkle_root` does in the circuit. `is_even[i]` is true when the
+/// node at level `i` is a left child.
+pub fn verify<H: Hasher>(
+    hasher: &H,
+    root: &Node,
+    leaf: &Node,
+    siblings: &[Node],
+    is_even: &[bool],
+) -> bool {
+    if siblings.len() != is_even.len() {
+        return false;
+    }
+
+    let mut hash = *leaf;
+    for (sibling, &even) in siblings.iter().zip(is_even) {
+        hash = if even {
+            hasher.hash_pair(&hash, sibling)
+        } else {
+            hasher.hash_pair(sibling, &hash)
+        };
+    }
+    hash == *root
+}
+
+// ---------------------------------------------------------------------------
+// In-memory tree (std)
+// ---------------------------------------------------------------------------
+
+/// Authentication path for one leaf, in the shape the circuit takes as
+/// `merkleProof` and `is_even`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerklePath {
+    pub siblings: Vec<Node>,
+    pub is_even: Vec<bool>,
+}
+
+#[cfg(feature = "std")]
+impl MerklePath {
+    pub fn verify<H: Hasher>(&self, hasher: &H, root: &Node, leaf: &Node) -> bool {
+        verify(hasher, root, leaf, &self.siblings, &self.is_even)
+    }
+}
+
+/// Full copy of a tree, keeping every non-empty node so paths can be served
+/// for any leaf. Roots match the contracts' after the same inserts.
+#[cfg(feature = "std")]
+pub struct MerkleTree<H> {
+    hasher: H,
+    depth: u32,
+    // layers[0] holds the leaves, layers[depth] the root.
+    layers: Vec<Vec<Node>>,
+}
+
+#[cfg(feature = "std")]
+impl<H: Hasher> MerkleTree<H> {
+    pub fn new(hasher: H, depth: u32) -> Self {
+        assert!(depth > 0 && depth <= MAX_DEPTH, "depth must be 1..=32");
+        Self {
+            hasher,
+            depth,
+            layers: (0..=depth).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Number of leaves inserted (= next leaf index).
+    pub fn len(&self) -> u64 {
+        self.layers[0].len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    pub fn leaves(&self) -> &[Node] {
+        &self.layers[0]
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Append a leaf and return its index.
+    pub fn insert(&mut self, leaf: Node) -> u64 {
+        let index = self.len();
+        assert!(index < 1u64 << self.depth, "merkle tree is full");
+        self.layers[0].push(leaf);
+
+        let mut i = index as usize;
+        for level in 0..self.depth as usize {
+            let (left, right) = if i.is_multiple_of(2) {
+                (self.layers[level][i], self.sibling(level, i))
+            } else {
+                (self.layers[level][i - 1], self.layers[level][i])
+            };
+            let parent = self.hasher.hash_pair(&left, &right);
+
+            i /= 2;
+            let up = &mut self.layers[level + 1];
+            if i < up.len() {
+                up[i] = parent;
+            } else {
+                up.push(parent);
+            }
+        }
+        index
+    }
+
+    /// Current root. Before the first insert this is [`initial_root`], which
+    /// is what the contracts report.
+    pub fn root(&self) -> Node {
+        match self.layers[self.depth as usize].first() {
+            Some(root) => *root,
+            None => initial_root(self.depth),
+        }
+    }
+
+    /// Authentication path for the leaf at `index`.
+    pub fn path(&self, index: u64) -> MerklePath {
+        assert!(index < self.len(), "leaf not found");
+
+        let mut siblings = Vec::with_capacity(self.depth as usize);
+        let mut is_even = Vec::with_capacity(self.depth as usize);
+        let mut i = index as usize;
+        for level in 0..self.depth as usize {
+            siblings.push(self.sibling(level, i));
+            is_even.push(i.is_multiple_of(2));
+            i /= 2;
+        }
+        MerklePath { siblings, is_even }
+    }
+
+    fn sibling(&self, level: usize, i: usize) -> Node {
+        let j = i ^ 1;
+        self.layers[level].get(j).copied().unwrap_or(ZEROS[level])
+    }
+}
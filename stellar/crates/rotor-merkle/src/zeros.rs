@@ -0,0 +1,50 @@
+use crate::tree::{Node, MAX_DEPTH};
+
+// ---------------------------------------------------------------------------
+// Zero hashes for the empty Merkle tree, generated by build.rs:
+//
+// zeros(0) = keccak256(ZERO_SEED) % BN254_FIELD_SIZE
+// zeros(i+1) = Poseidon2(zeros(i), zeros(i))
+//
+// ZERO_SEED is "cyfrin" unless ROTOR_ZERO_SEED is set at build time. With the
+// default seed these MUST match the tables in the client and relayer; the
+// tests in this crate and in incremental-merkle-tree check them against
+// circuit/Prover.toml.
+// ---------------------------------------------------------------------------
+include!(concat!(env!("OUT_DIR"), "/zeros.rs"));
+
+/// The zero element at `level`.
+pub fn zero(level: u32) -> Node {
+    assert!(level < ZERO_LEVELS, "level out of bounds");
+    ZEROS[level as usize]
+}
+
+/// Root a freshly deployed contract of the given depth reports before its
+/// first insert.
+pub fn initial_root(depth: u32) -> Node {
+    assert!(depth > 0 && depth <= MAX_DEPTH, "depth must be 1..=32");
+    ZEROS[depth as usize - 1]
+}
+
+// ---------------------------------------------------------------------------
+// Compile-time hex decoder
+// ---------------------------------------------------------------------------
+const fn hex(s: &str) -> Node {
+    let b = s.as_bytes();
+    let mut out = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        out[i] = (hex_digit(b[i * 2]) << 4) | hex_digit(b[i * 2 + 1]);
+        i += 1;
+    }
+    out
+}
+
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("invalid hex"),
+    }
+}
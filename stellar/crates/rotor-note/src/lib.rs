@@ -0,0 +1,375 @@
+//! Versioned, checksummed encoding for Rotor notes.
+//!
+//! A note is everything needed to withdraw a deposit: the nullifier and
+//! secret, the denomination that went into the commitment, and the deployment
+//! it was made against. Encoded, it is one line of ASCII:
+//!
+//! ```text
+//! rotor-v1-<hex payload><hex checksum>
+//!
+//! payload  = network id   32  sha256(network passphrase)
+//!            contract id  32  C... address payload
+//!            denomination 16  i128, big-endian
+//!            nullifier    32  field element, big-endian
+//!            secret       32  field element, big-endian
+//!            leaf index    8  u64, big-endian; u64::MAX when unknown
+//! checksum = sha256("rotor-v1-" || payload)[..4]
+//! ```
+//!
+//! [`Recipient::field`] is the canonical encoding of a withdrawal recipient
+//! as the circuit's `recipient` field. A withdrawal into a contract call
+//! binds the call instead, with `call_field` (`soroban` feature).
+//!
+//! The core is `no_std` and allocation-free. The `std` feature adds the
+//! native commitment, the `soroban` feature computes it on the host and checks
+//! a note against the running contract.
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "soroban")]
+mod soroban;
+
+#[cfg(feature = "soroban")]
+pub use soroban::{call_field, contract_id, recipient_field};
+
+#[cfg(test)]
+mod test;
+
+use core::fmt;
+use rotor_merkle::Node;
+use sha2::{Digest, Sha256};
+
+/// Human-readable prefix of every encoded note.
+pub const PREFIX: &str = "rotor";
+/// Current format version.
+pub const VERSION: u8 = 1;
+
+const HEADER: &str = "rotor-v1-";
+const PAYLOAD_LEN: usize = 32 + 32 + 16 + 32 + 32 + 8;
+const CHECKSUM_LEN: usize = 4;
+const NO_LEAF_INDEX: u64 = u64::MAX;
+
+/// Length in bytes of an encoded note.
+pub const ENCODED_LEN: usize = HEADER.len() + 2 * (PAYLOAD_LEN + CHECKSUM_LEN);
+
+/// BN254 scalar field modulus, big-endian.
+const MODULUS: Node = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+// ---------------------------------------------------------------------------
+// Errors
+// ---------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Does not start with `rotor-`.
+    Prefix,
+    /// Written by a format version this crate does not read.
+    Version,
+    /// Wrong length for the version.
+    Length,
+    /// Payload or checksum is not lowercase hex.
+    Hex,
+    /// Checksum does not match; the note was mistyped or truncated.
+    Checksum,
+    /// Nullifier or secret is not a canonical field element.
+    Field,
+    /// Denomination is not positive.
+    Denomination,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::Prefix => "not a rotor note",
+            Error::Version => "unsupported note version",
+            Error::Length => "note has the wrong length",
+            Error::Hex => "note contains invalid hex",
+            Error::Checksum => "note checksum mismatch",
+            Error::Field => "nullifier or secret is not a field element",
+            Error::Denomination => "denomination must be positive",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// ---------------------------------------------------------------------------
+// Note
+// ---------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    /// `sha256(network passphrase)`, as returned by `env.ledger().network_id()`.
+    pub network_id: Node,
+    /// Contract id of the pool the note is deposited into.
+    pub contract_id: Node,
+    /// Amount committed to, in the token's base units.
+    pub denomination: i128,
+    pub nullifier: Node,
+    pub secret: Node,
+    /// Where the commitment was inserted, if known. Only a hint: readers
+    /// must still check the leaf against the commitment.
+    pub leaf_index: Option<u64>,
+}
+
+impl Note {
+    pub fn new(
+        network_id: Node,
+        contract_id: Node,
+        denomination: i128,
+        nullifier: Node,
+        secret: Node,
+    ) -> Result<Self, Error> {
+        let note = Self {
+            network_id,
+            contract_id,
+            denomination,
+            nullifier,
+            secret,
+            leaf_index: None,
+        };
+        note.validate()?;
+        Ok(note)
+    }
+
+    /// The denomination as the circuit's `amount` field.
+    pub fn amount_field(&self) -> Node {
+        let mut out = [0u8; 32];
+        out[16..].copy_from_slice(&self.denomination.to_be_bytes());
+        out
+    }
+
+    pub fn encode(&self) -> Encoded {
+        let mut payload = [0u8; PAYLOAD_LEN];
+        let mut at = 0;
+        for part in [
+            &self.network_id[..],
+            &self.contract_id,
+            &self.denomination.to_be_bytes(),
+            &self.nullifier,
+            &self.secret,
+            &self.leaf_index.unwrap_or(NO_LEAF_INDEX).to_be_bytes(),
+        ] {
+            payload[at..at + part.len()].copy_from_slice(part);
+            at += part.len();
+        }
+
+        let mut out = [0u8; ENCODED_LEN];
+        out[..HEADER.len()].copy_from_slice(HEADER.as_bytes());
+        let (payload_hex, checksum_hex) = out[HEADER.len()..].split_at_mut(2 * PAYLOAD_LEN);
+        encode_hex(&payload, payload_hex);
+        encode_hex(&checksum(&payload), checksum_hex);
+        Encoded(out)
+    }
+
+    pub fn decode(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        let rest = s
+            .strip_prefix(PREFIX)
+            .and_then(|r| r.strip_prefix('-'))
+            .ok_or(Error::Prefix)?;
+        if !rest.starts_with("v1-") {
+            return Err(Error::Version);
+        }
+        if s.len() != ENCODED_LEN {
+            return Err(Error::Length);
+        }
+
+        let (payload_hex, checksum_hex) = s.as_bytes()[HEADER.len()..].split_at(2 * PAYLOAD_LEN);
+        let mut payload = [0u8; PAYLOAD_LEN];
+        let mut expected = [0u8; CHECKSUM_LEN];
+        decode_hex(payload_hex, &mut payload)?;
+        decode_hex(checksum_hex, &mut expected)?;
+        if checksum(&payload) != expected {
+            return Err(Error::Checksum);
+        }
+
+        let mut rest = &payload[..];
+        let mut take = |n: usize| {
+            let (head, tail) = rest.split_at(n);
+            rest = tail;
+            head
+        };
+        let network_id = take(32).try_into().unwrap();
+        let contract_id = take(32).try_into().unwrap();
+        let denomination = i128::from_be_bytes(take(16).try_into().unwrap());
+        let nullifier = take(32).try_into().unwrap();
+        let secret = take(32).try_into().unwrap();
+        let leaf_index = match u64::from_be_bytes(take(8).try_into().unwrap()) {
+            NO_LEAF_INDEX => None,
+            index => Some(index),
+        };
+
+        let note = Self {
+            network_id,
+            contract_id,
+            denomination,
+            nullifier,
+            secret,
+            leaf_index,
+        };
+        note.validate()?;
+        Ok(note)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.nullifier >= MODULUS || self.secret >= MODULUS {
+            return Err(Error::Field);
+        }
+        if self.denomination <= 0 {
+            return Err(Error::Denomination);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Note {
+    /// `Poseidon2::hash([nullifier, secret, amount], 3)`, the leaf deposited.
+    pub fn commitment(&self) -> Node {
+        rotor_merkle::native::hash(&[self.nullifier, self.secret, self.amount_field()])
+    }
+
+    /// `Poseidon2::hash([nullifier], 1)`, revealed on withdrawal.
+    pub fn nullifier_hash(&self) -> Node {
+        rotor_merkle::native::hash(&[self.nullifier])
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::str::FromStr for Note {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::decode(s)
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.encode().as_str())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Recipients
+// ---------------------------------------------------------------------------
+
+/// XDR discriminants of `ScVal::Address` and the `ScAddress` arms.
+const SCV_ADDRESS: u32 = 18;
+const SC_ADDRESS_ACCOUNT: u32 = 0;
+const SC_ADDRESS_CONTRACT: u32 = 1;
+const SC_ADDRESS_MUXED_ACCOUNT: u32 = 2;
+
+/// Anything a withdrawal can pay: a G... account, a C... contract or an
+/// M... muxed account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recipient {
+    Account(Node),
+    Contract(Node),
+    Muxed { ed25519: Node, id: u64 },
+}
+
+impl Recipient {
+    /// The XDR of the recipient as `ScVal::Address`, the bytes
+    /// `address.to_xdr()` gives inside a contract.
+    pub fn to_xdr(&self) -> ([u8; 48], usize) {
+        let mut out = [0u8; 48];
+        let mut at = 0;
+        let mut put = |part: &[u8]| {
+            out[at..at + part.len()].copy_from_slice(part);
+            at += part.len();
+        };
+        put(&SCV_ADDRESS.to_be_bytes());
+        match self {
+            Recipient::Account(key) => {
+                put(&SC_ADDRESS_ACCOUNT.to_be_bytes());
+                put(&0u32.to_be_bytes()); // PUBLIC_KEY_TYPE_ED25519
+                put(key);
+            }
+            Recipient::Contract(id) => {
+                put(&SC_ADDRESS_CONTRACT.to_be_bytes());
+                put(id);
+            }
+            Recipient::Muxed { ed25519, id } => {
+                put(&SC_ADDRESS_MUXED_ACCOUNT.to_be_bytes());
+                put(&id.to_be_bytes());
+                put(ed25519);
+            }
+        }
+        (out, at)
+    }
+
+    /// The circuit's `recipient` field: `sha256(xdr)` with the top byte
+    /// zeroed to stay below the BN254 modulus. Every address kind, and every
+    /// muxed id, gets its own field.
+    pub fn field(&self) -> Node {
+        let (xdr, len) = self.to_xdr();
+        let mut field: Node = Sha256::digest(&xdr[..len]).into();
+        field[0] = 0;
+        field
+    }
+}
+
+/// `sha256(passphrase)`, Stellar's network id.
+pub fn network_id(passphrase: &str) -> Node {
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+// ---------------------------------------------------------------------------
+// Encoded form
+// ---------------------------------------------------------------------------
+
+/// An encoded note, held inline so `no_std` callers need no allocator.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Encoded([u8; ENCODED_LEN]);
+
+impl Encoded {
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.0).expect("header and hex are ascii")
+    }
+}
+
+impl fmt::Display for Encoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Encoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn checksum(payload: &[u8; PAYLOAD_LEN]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::new()
+        .chain_update(HEADER.as_bytes())
+        .chain_update(payload)
+        .finalize();
+    digest[..CHECKSUM_LEN].try_into().unwrap()
+}
+
+fn encode_hex(bytes: &[u8], out: &mut [u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for (byte, pair) in bytes.iter().zip(out.chunks_exact_mut(2)) {
+        pair[0] = DIGITS[(byte >> 4) as usize];
+        pair[1] = DIGITS[(byte & 0xf) as usize];
+    }
+}
+
+fn decode_hex(hex: &[u8], out: &mut [u8]) -> Result<(), Error> {
+    let nibble = |c: u8| match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        _ => Err(Error::Hex),
+    };
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use rotor_merkle::{soroban::hash, Node};
+use soroban_sdk::{vec, xdr::ToXdr, Address, Bytes, BytesN, Env, MuxedAddress, Val, Vec};
+
+use crate::Note;
+
+/// XDR prefix of `ScVal::Address(ScAddress::Contract(..))`.
+const CONTRACT_XDR_PREFIX: [u8; 8] = [0, 0, 0, 18, 0, 0, 0, 1];
+
+/// The 32-byte id of a contract address; `None` for accounts.
+pub fn contract_id(address: &Address) -> Option<Node> {
+    let env = address.env();
+    let xdr = address.clone().to_xdr(env);
+    if xdr.len() != 40 || xdr.slice(..8) != Bytes::from_array(env, &CONTRACT_XDR_PREFIX) {
+        return None;
+    }
+    let mut id = [0u8; 32];
+    xdr.slice(8..).copy_into_slice(&mut id);
+    Some(id)
+}
+
+/// [`Recipient::field`](crate::Recipient::field) on the host.
+pub fn recipient_field(recipient: &MuxedAddress) -> BytesN<32> {
+    let env = recipient.env();
+    let mut field = env
+        .crypto()
+        .sha256(&recipient.to_val().to_xdr(env))
+        .to_array();
+    field[0] = 0;
+    BytesN::from_array(env, &field)
+}
+
+/// The recipient field of a withdrawal into `target`'s callback with
+/// `data`: the `ScVal::Vec([target, data])` XDR hashed like
+/// [`recipient_field`]. An `ScVal::Vec` never encodes like an
+/// `ScVal::Address`, so no call field is also a plain recipient's.
+pub fn call_field(target: &Address, data: &Bytes) -> BytesN<32> {
+    let env = target.env();
+    let call: Vec<Val> = vec![env, target.to_val(), data.to_val()];
+    let mut field = env.crypto().sha256(&call.to_xdr(env)).to_array();
+    field[0] = 0;
+    BytesN::from_array(env, &field)
+}
+
+impl Note {
+    /// [`Note::commitment`] on the host, as the contract would compute it.
+    pub fn commitment_on(&self, env: &Env) -> BytesN<32> {
+        let commitment = hash(env, &[self.nullifier, self.secret, self.amount_field()]);
+        BytesN::from_array(env, &commitment)
+    }
+
+    /// [`Note::nullifier_hash`] on the host.
+    pub fn nullifier_hash_on(&self, env: &Env) -> BytesN<32> {
+        BytesN::from_array(env, &hash(env, &[self.nullifier]))
+    }
+
+    /// Whether the note was made for `contract` on the network `env` is
+    /// running on.
+    pub fn is_for(&self, env: &Env, contract: &Address) -> bool {
+        env.ledger().network_id().to_array() == self.network_id
+            && contract_id(contract) == Some(self.contract_id)
+    }
+}
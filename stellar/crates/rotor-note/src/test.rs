@@ -0,0 +1,392 @@
+#![cfg(test)]
+extern crate std;
+
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use crate::{
+    call_field, contract_id, decode_hex, network_id, recipient_field, Error, Note, Recipient,
+    ENCODED_LEN, MODULUS,
+};
+use rotor_merkle::{native::NativeHasher, MerklePath, MerkleTree, Node};
+use soroban_sdk::testutils::{Address as _, Ledger as _, MuxedAddress as _};
+use soroban_sdk::{xdr::FromXdr, Address, Bytes, Env, MuxedAddress};
+
+const PROVER_TOML: &str = include_str!("../../../../circuit/Prover.toml");
+const TRANSACT_TOML: &str = include_str!("../../../../circuits/transact/Prover.toml");
+const PARTIAL_TOML: &str = include_str!("../../../../circuits/withdraw_partial/Prover.toml");
+const TESTNET: &str = "Test SDF Network ; September 2015";
+
+fn prover_node(key: &str) -> Node {
+    let value = PROVER_TOML
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().trim_matches('"').trim_start_matches("0x"))
+        .unwrap();
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    out
+}
+
+/// Every value under `key` in a Prover.toml, nested arrays flattened.
+fn toml_values<'a>(toml: &'a str, key: &str) -> Vec<&'a str> {
+    let value = toml
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v)
+        .unwrap();
+    value
+        .split(['[', ']', ','])
+        .map(|v| v.trim().trim_matches('"'))
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn toml_nodes(toml: &str, key: &str) -> Vec<Node> {
+    toml_values(toml, key)
+        .into_iter()
+        .map(|v| {
+            let mut out = [0u8; 32];
+            decode_hex(v.trim_start_matches("0x").as_bytes(), &mut out).unwrap();
+            out
+        })
+        .collect()
+}
+
+/// The low 16 bytes of an amount field, which must be all it uses.
+fn node_amount(node: &Node) -> u128 {
+    assert_eq!(node[..16], [0; 16]);
+    u128::from_be_bytes(node[16..].try_into().unwrap())
+}
+
+fn fixture_note() -> Note {
+    Note::new(
+        network_id(TESTNET),
+        [7; 32],
+        10_000_000,
+        prover_node("nullifier"),
+        prover_node("secret"),
+    )
+    .unwrap()
+}
+
+/// Replace the character at `at` with a different hex digit.
+fn flip(encoded: &str, at: usize) -> String {
+    let mut chars: std::vec::Vec<char> = encoded.chars().collect();
+    chars[at] = if chars[at] == '0' { '1' } else { '0' };
+    chars.into_iter().collect()
+}
+
+#[test]
+fn test_round_trip() {
+    let mut note = fixture_note();
+    let encoded = note.encode();
+
+    assert_eq!(encoded.as_str().len(), ENCODED_LEN);
+    assert!(encoded.as_str().starts_with("rotor-v1-"));
+    assert_eq!(Note::decode(encoded.as_str()).unwrap(), note);
+    assert_eq!(note.to_string().parse::<Note>().unwrap(), note);
+
+    note.leaf_index = Some(42);
+    assert_eq!(
+        Note::decode(&note.to_string()).unwrap().leaf_index,
+        Some(42)
+    );
+    assert_ne!(note.encode(), encoded);
+
+    // Surrounding whitespace from copy/paste or a trailing newline is fine.
+    assert_eq!(Note::decode(&std::format!(" {note}\n")).unwrap(), note);
+}
+
+#[test]
+fn test_fixture_matches_circuit() {
+    let note = fixture_note();
+
+    assert_eq!(note.amount_field(), prover_node("amount"));
+    assert_eq!(note.nullifier_hash(), prover_node("nullifier_hash"));
+
+    let mut tree = MerkleTree::new(NativeHasher, 20);
+    tree.insert(note.commitment());
+    assert_eq!(tree.root(), prover_node("root"));
+
+    let relayer_fee = node_amount(&prover_node("relayer_fee"));
+    assert!(relayer_fee <= note.denomination as u128);
+}
+
+/// Replays the constraints of circuits/transact/src/main.nr on its fixture.
+#[test]
+fn test_transact_fixture_satisfies_circuit() {
+    let node = |key| toml_nodes(TRANSACT_TOML, key);
+    let root = node("root")[0];
+    let hash = rotor_merkle::native::hash;
+    let siblings = node("in_path");
+    let is_even: Vec<bool> = toml_values(TRANSACT_TOML, "in_is_even")
+        .into_iter()
+        .map(|v| v.parse().unwrap())
+        .collect();
+
+    let mut sum_in = 0;
+    for (i, ((nullifier, secret), amount)) in node("in_nullifier")
+        .iter()
+        .zip(node("in_secret"))
+        .zip(node("in_amount"))
+        .enumerate()
+    {
+        assert!(node_amount(&amount) < 1 << 64);
+        assert_eq!(hash(&[*nullifier]), node("input_nullifiers")[i]);
+        if node_amount(&amount) != 0 {
+            let path = MerklePath {
+                siblings: siblings[i * 20..(i + 1) * 20].to_vec(),
+                is_even: is_even[i * 20..(i + 1) * 20].to_vec(),
+            };
+            let leaf = hash(&[*nullifier, secret, amount]);
+            assert!(path.verify(&NativeHasher, &root, &leaf));
+        }
+        sum_in += node_amount(&amount);
+    }
+    assert_ne!(node("input_nullifiers")[0], node("input_nullifiers")[1]);
+
+    let mut sum_out = 0;
+    for (i, ((nullifier, secret), amount)) in node("out_nullifier")
+        .iter()
+        .zip(node("out_secret"))
+        .zip(node("out_amount"))
+        .enumerate()
+    {
+        assert!(node_amount(&amount) < 1 << 64);
+        assert_eq!(
+            hash(&[*nullifier, secret, amount]),
+            node("output_commitments")[i]
+        );
+        sum_out += node_amount(&amount);
+    }
+    assert_eq!(sum_in, sum_out);
+    assert_eq!(sum_in, 10_000_000);
+}
+
+/// Replays the constraints of circuits/withdraw_partial/src/main.nr on its
+/// fixture.
+#[test]
+fn test_partial_fixture_satisfies_circuit() {
+    let node = |key| toml_nodes(PARTIAL_TOML, key)[0];
+    let hash = rotor_merkle::native::hash;
+    let path = MerklePath {
+        siblings: toml_nodes(PARTIAL_TOML, "merkleProof"),
+        is_even: toml_values(PARTIAL_TOML, "is_even")
+            .into_iter()
+            .map(|v| v.parse().unwrap())
+            .collect(),
+    };
+
+    let leaf = hash(&[node("nullifier"), node("secret"), node("amount")]);
+    assert!(path.verify(&NativeHasher, &node("root"), &leaf));
+    assert_eq!(hash(&[node("nullifier")]), node("nullifier_hash"));
+
+    let withdraw_amount = node_amount(&node("withdraw_amount"));
+    let change = node_amount(&node("amount")) - withdraw_amount;
+    assert!(withdraw_amount < 1 << 64 && change < 1 << 64);
+    let mut change_field = [0u8; 32];
+    change_field[16..].copy_from_slice(&change.to_be_bytes());
+    assert_eq!(
+        hash(&[
+            node("change_nullifier"),
+            node("change_secret"),
+            change_field
+        ]),
+        node("change_commitment")
+    );
+    assert_eq!((withdraw_amount, change), (2_500_000, 7_500_000));
+    assert!(node_amount(&node("relayer_fee")) <= withdraw_amount);
+    assert_eq!(node("recipient"), Recipient::Account([9; 32]).field());
+}
+
+#[test]
+fn test_checksum_catches_single_edits() {
+    let encoded = fixture_note().to_string();
+
+    for at in 9..encoded.len() {
+        assert_eq!(
+            Note::decode(&flip(&encoded, at)),
+            Err(Error::Checksum),
+            "{at}"
+        );
+    }
+    assert_eq!(
+        Note::decode(&encoded[..encoded.len() - 1]),
+        Err(Error::Length)
+    );
+}
+
+#[test]
+fn test_rejects_malformed() {
+    let encoded = fixture_note().to_string();
+
+    assert_eq!(Note::decode("tornado-eth-0.1-1-0x00"), Err(Error::Prefix));
+    assert_eq!(
+        Note::decode(&encoded.replacen("-v1-", "-v2-", 1)),
+        Err(Error::Version)
+    );
+    assert_eq!(
+        Note::decode(&encoded.to_uppercase().replacen("ROTOR-V1", "rotor-v1", 1)),
+        Err(Error::Hex)
+    );
+}
+
+#[test]
+fn test_rejects_invalid_fields() {
+    let note = fixture_note();
+
+    let at_modulus = Note::new(note.network_id, note.contract_id, 1, MODULUS, note.secret);
+    assert_eq!(at_modulus, Err(Error::Field));
+    let zero = Note::new(
+        note.network_id,
+        note.contract_id,
+        0,
+        note.nullifier,
+        note.secret,
+    );
+    assert_eq!(zero, Err(Error::Denomination));
+
+    // A well-formed, correctly checksummed note still has to hold valid fields.
+    let mut negative = note.clone();
+    negative.denomination = -1;
+    assert_eq!(
+        Note::decode(&negative.to_string()),
+        Err(Error::Denomination)
+    );
+}
+
+#[test]
+fn test_soroban_matches_native() {
+    let env = Env::default();
+    env.cost_estimate().budget().reset_unlimited();
+    let note = fixture_note();
+
+    assert_eq!(note.commitment_on(&env).to_array(), note.commitment());
+    assert_eq!(
+        note.nullifier_hash_on(&env).to_array(),
+        note.nullifier_hash()
+    );
+}
+
+#[test]
+fn test_is_for_deployment() {
+    let env = Env::default();
+    env.ledger().set_network_id(network_id(TESTNET));
+    let contract = Address::generate(&env);
+
+    let mut note = fixture_note();
+    note.contract_id = contract_id(&contract).unwrap();
+    assert!(note.is_for(&env, &contract));
+    assert!(!note.is_for(&env, &Address::generate(&env)));
+
+    let account = Address::from_str(
+        &env,
+        "GDAT5HWTGIU4TSSZ4752OUC4SABDLTLZFRPZUJ3D6LKBNEPA7V2CIG54",
+    );
+    assert_eq!(contract_id(&account), None);
+
+    env.ledger()
+        .set_network_id(network_id("Public Global Stellar Network ; September 2015"));
+    assert!(!note.is_for(&env, &contract));
+}
+
+#[test]
+fn test_recipient_field_matches_host() {
+    let env = Env::default();
+    let key = [9u8; 32];
+    // The layout `Recipient::to_xdr` writes must be what the host reads.
+    let host = |recipient: Recipient| {
+        let (xdr, len) = recipient.to_xdr();
+        MuxedAddress::from_xdr(&env, &Bytes::from_slice(&env, &xdr[..len])).unwrap()
+    };
+    let account = host(Recipient::Account(key)).address();
+    let contract = host(Recipient::Contract(key)).address();
+    assert!(contract_id(&account).is_none());
+    assert_eq!(contract_id(&contract), Some(key));
+    let muxed = MuxedAddress::new(account.clone(), 42);
+    assert_eq!(
+        host(Recipient::Muxed {
+            ed25519: key,
+            id: 42
+        }),
+        muxed
+    );
+
+    let cases = [
+        (MuxedAddress::from(account), Recipient::Account(key)),
+        (MuxedAddress::from(contract), Recipient::Contract(key)),
+        (
+            muxed,
+            Recipient::Muxed {
+                ed25519: key,
+                id: 42,
+            },
+        ),
+    ];
+    for (address, recipient) in &cases {
+        let field = recipient.field();
+        assert_eq!(recipient_field(address).to_array(), field);
+        assert_eq!(field[0], 0, "top byte is zeroed");
+    }
+
+    // Each kind, and each muxed id, is its own recipient.
+    let fields = cases.map(|(_, recipient)| recipient.field());
+    assert_ne!(fields[0], fields[1]);
+    assert_ne!(fields[0], fields[2]);
+    assert_ne!(
+        fields[2],
+        Recipient::Muxed {
+            ed25519: key,
+            id: 43
+        }
+        .field()
+    );
+}
+
+#[test]
+fn test_call_field() {
+    let env = Env::default();
+    let target = Address::generate(&env);
+    let data = Bytes::from_array(&env, &[7]);
+    let field = call_field(&target, &data);
+    assert_eq!(field.to_array()[0], 0, "top byte is zeroed");
+
+    // The target and call data are both bound, and a call is never a plain
+    // payment to its target.
+    assert_ne!(field, call_field(&Address::generate(&env), &data));
+    assert_ne!(field, call_field(&target, &Bytes::from_array(&env, &[8])));
+    assert_ne!(field, call_field(&target, &Bytes::new(&env)));
+    assert_ne!(field, recipient_field(&target.into()));
+}
+
+/// Fixed vectors for the relayer and client, which encode recipients too.
+#[test]
+fn test_recipient_field_vectors() {
+    let key = [9u8; 32];
+    for (recipient, expected) in [
+        (
+            Recipient::Account(key),
+            "00fa207bcad37381eb3da0bb87df0c02ac6f5ed00c2ce2aa51bbbb7081742722",
+        ),
+        (
+            Recipient::Contract(key),
+            "00ea5da64a759dceb2fa19b6c45c07db21aa4f61ecbb186537f3d5fb3fd55f91",
+        ),
+        (
+            Recipient::Muxed {
+                ed25519: key,
+                id: 42,
+            },
+            "00a58df775049495886b87cc4eaa3fc152de15e1274776d54901a57d555b182a",
+        ),
+    ] {
+        let mut field = [0u8; 32];
+        decode_hex(expected.as_bytes(), &mut field).unwrap();
+        assert_eq!(recipient.field(), field);
+    }
+}